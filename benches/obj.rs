@@ -0,0 +1,58 @@
+//! Parses the bundled OBJ files with the crate's loader, both streamed in
+//! `BufReader` sized chunks and handed over whole, next to the `tobj` and
+//! `obj` crates for reference.
+//!
+//! Run with `cargo bench --bench obj`.
+
+use std::io::{BufReader, Cursor};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wasm_conways::loader::{load_model, LoadLimits, ModelFormat, NoFiles};
+
+const MODELS: [(&str, &[u8]); 4] = [
+    ("cube.obj", include_bytes!("../cube.obj")),
+    ("cornell_box.obj", include_bytes!("../cornell_box.obj")),
+    ("lamp.obj", include_bytes!("../lamp.obj")),
+    ("model.obj", include_bytes!("../model.obj")),
+];
+
+fn parse_obj(c: &mut Criterion) {
+    for (name, bytes) in MODELS {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        if bytes.len() > 1 << 20 {
+            group.sample_size(20);
+        }
+
+        group.bench_function(BenchmarkId::new("load_model", "streamed"), |b| {
+            b.iter(|| load_model(&mut BufReader::new(Cursor::new(bytes))).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("load_model", "whole"), |b| {
+            b.iter(|| {
+                ModelFormat::Obj
+                    .load(bytes, &NoFiles, &LoadLimits::default())
+                    .unwrap()
+            })
+        });
+        group.bench_function("tobj", |b| {
+            let options = tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            };
+            b.iter(|| {
+                tobj::load_obj_buf(&mut Cursor::new(bytes), &options, |_| {
+                    Err(tobj::LoadError::OpenFileFailed)
+                })
+                .unwrap()
+            })
+        });
+        group.bench_function("obj", |b| {
+            b.iter(|| obj::ObjData::load_buf(Cursor::new(bytes)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse_obj);
+criterion_main!(benches);
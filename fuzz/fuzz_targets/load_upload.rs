@@ -0,0 +1,39 @@
+//! Loads arbitrary bytes the way an upload is loaded: archives are unpacked,
+//! everything else goes to the loader its magic bytes or extension pick.
+//!
+//! Inputs that once hung or crashed it are kept in `regressions/load_upload`;
+//! pass that directory after the corpus to replay them.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_conways::loader::{load_archive, Archive, LoadLimits, ModelFormat, NoFiles};
+
+/// the extension hint comes from the first byte, so extension-only formats
+/// (OBJ, glTF JSON) are reached as well as the ones with magic bytes
+const NAMES: [&str; 6] = [
+    "model.obj",
+    "model.stl",
+    "model.ply",
+    "model.gltf",
+    "model.zip",
+    "model.stl.gz",
+];
+
+fuzz_target!(|data: &[u8]| {
+    let (name, bytes) = match data.split_first() {
+        Some((&hint, bytes)) => (NAMES[hint as usize % NAMES.len()], bytes),
+        None => return,
+    };
+    let limits = LoadLimits {
+        max_file_bytes: 1 << 20,
+        max_line_bytes: 4096,
+        max_vertices: 1 << 16,
+        max_faces: 1 << 16,
+        max_polygon_arity: 64,
+        max_texture_dimension: 4096,
+    };
+    let _ = match Archive::detect(name, bytes) {
+        Some(archive) => load_archive(archive, name, bytes, &NoFiles, &limits),
+        None => ModelFormat::detect(name, bytes, bytes.len() as u64).load(bytes, &NoFiles, &limits),
+    };
+});
@@ -0,0 +1,29 @@
+//! Streams arbitrary bytes through `ObjParser`, split into chunks of a size
+//! taken from the first byte so that lines get cut at every possible place.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_conways::loader::{LoadLimits, NoFiles, ObjParser};
+
+fuzz_target!(|data: &[u8]| {
+    let (chunk_len, source) = match data.split_first() {
+        Some((&chunk_len, source)) => (chunk_len.max(1) as usize, source),
+        None => return,
+    };
+    // small enough that the fuzzer runs into every limit
+    let limits = LoadLimits {
+        max_file_bytes: 1 << 20,
+        max_line_bytes: 4096,
+        max_vertices: 1 << 16,
+        max_faces: 1 << 16,
+        max_polygon_arity: 64,
+        max_texture_dimension: 4096,
+    };
+    let mut parser = ObjParser::new(NoFiles).with_limits(limits);
+    for chunk in source.chunks(chunk_len) {
+        if parser.push(chunk).is_err() {
+            return;
+        }
+    }
+    let _ = parser.finish();
+});
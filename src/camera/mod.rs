@@ -0,0 +1,110 @@
+//! Where the camera looks from, fitted to the bounds of the loaded model.
+
+use glam::Vec3;
+
+use crate::{
+    geometry::{model_bounds, BoundingSphere},
+    loader::ModelData,
+};
+
+/// vertical field of view of the perspective projection
+pub const FIELD_OF_VIEW_DEGREES: f32 = 60.0;
+
+/// extra room around the model when it is framed
+const FRAMING_MARGIN: f32 = 1.1;
+/// the near plane never comes closer than this fraction of the distance,
+/// which keeps depth precision when the camera is zoomed into the model
+const MIN_NEAR_RATIO: f32 = 1e-3;
+/// how far the mouse wheel can zoom, as multiples of the model's radius
+const ZOOM_RANGE: (f32, f32) = (0.1, 100.0);
+/// change in distance per pixel of mouse wheel scrolling
+const ZOOM_PER_SCROLL: f64 = 1e-3;
+
+/**
+ * An orbit camera: it looks at `target` from `distance` away, and the model
+ * is rotated about the target underneath it
+ *
+ * The clipping planes are kept just around the model's bounding sphere so
+ * that the depth buffer's precision is spent on the model
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+    /// radius of the framed model
+    radius: f32,
+}
+
+impl Default for Camera {
+    /// frames a unit sphere at the origin
+    fn default() -> Self {
+        Camera::framing(
+            &BoundingSphere {
+                center: Vec3::ZERO,
+                radius: 1.0,
+            },
+            1.0,
+        )
+    }
+}
+
+impl Camera {
+    /**
+     * Aims at the center of `sphere` from just far enough away that it fits
+     * the view both vertically and, for viewports narrower than they are
+     * tall, horizontally
+     */
+    pub fn framing(sphere: &BoundingSphere, aspect: f32) -> Self {
+        // a single point (or an empty model) still needs some room around it
+        let radius = if sphere.radius > 0.0 {
+            sphere.radius
+        } else {
+            1.0
+        };
+        let half_vertical = FIELD_OF_VIEW_DEGREES.to_radians() * 0.5;
+        let half_horizontal = (half_vertical.tan() * aspect).atan();
+        let half_angle = half_vertical.min(half_horizontal);
+        let mut camera = Self {
+            target: sphere.center,
+            distance: radius / half_angle.sin() * FRAMING_MARGIN,
+            z_near: 0.0,
+            z_far: 0.0,
+            radius,
+        };
+        camera.fit_clip_planes();
+        camera
+    }
+
+    /// frames the bounding sphere of `model`, or `None` if it has no vertices
+    pub fn framing_model(model: &ModelData, aspect: f32) -> Option<Self> {
+        model_bounds(model).map(|bounds| Self::framing(&bounds.sphere, aspect))
+    }
+
+    /// the camera position, above and in front of the target
+    pub fn eye(&self) -> Vec3 {
+        self.target + Vec3::new(0.0, 1.0, 1.0).normalize() * self.distance
+    }
+
+    /// how many pixels one model unit covers on screen, where the model comes nearest
+    pub fn pixels_per_unit(&self, viewport_height: u32) -> f32 {
+        let nearest = (self.distance - self.radius).max(self.z_near);
+        let half_height = (FIELD_OF_VIEW_DEGREES.to_radians() * 0.5).tan() * nearest;
+        viewport_height as f32 * 0.5 / half_height
+    }
+
+    /// moves closer for negative `scroll_delta` (wheel up), further for positive
+    pub fn zoom(&mut self, scroll_delta: f64) {
+        let scale = (1.0 + scroll_delta * ZOOM_PER_SCROLL).max(0.1) as f32;
+        self.distance =
+            (self.distance * scale).clamp(self.radius * ZOOM_RANGE.0, self.radius * ZOOM_RANGE.1);
+        self.fit_clip_planes();
+    }
+
+    fn fit_clip_planes(&mut self) {
+        self.z_near =
+            ((self.distance - self.radius) / FRAMING_MARGIN).max(self.distance * MIN_NEAR_RATIO);
+        self.z_far = (self.distance + self.radius) * FRAMING_MARGIN;
+    }
+}
@@ -0,0 +1,127 @@
+use glam::Vec3;
+
+use crate::loader::{ModelData, Submesh};
+
+/// axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// the smallest box around `points`, `None` if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, point| Self {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        ))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /**
+     * A sphere around `points` using Ritter's algorithm: it starts from two
+     * points far apart and grows to take in any point still outside
+     *
+     * The result is within a few percent of the smallest enclosing sphere;
+     * when the sphere centered on the bounding box happens to be smaller,
+     * that one is returned instead
+     */
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = *points.first()?;
+        let farthest_from = |from: Vec3| {
+            points
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    a.distance_squared(from)
+                        .total_cmp(&b.distance_squared(from))
+                })
+                .unwrap_or(from)
+        };
+        let a = farthest_from(first);
+        let b = farthest_from(a);
+        let mut sphere = Self {
+            center: (a + b) * 0.5,
+            radius: a.distance(b) * 0.5,
+        };
+        for &point in points {
+            let distance = point.distance(sphere.center);
+            if distance > sphere.radius {
+                // move towards the point just enough to reach it
+                let radius = (sphere.radius + distance) * 0.5;
+                sphere.center += (point - sphere.center) * ((radius - sphere.radius) / distance);
+                sphere.radius = radius;
+            }
+        }
+        let box_center = Aabb::from_points(points.iter().copied())?.center();
+        let box_radius = points
+            .iter()
+            .map(|point| point.distance(box_center))
+            .fold(0.0, f32::max);
+        if box_radius < sphere.radius {
+            sphere = Self {
+                center: box_center,
+                radius: box_radius,
+            };
+        }
+        Some(sphere)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    fn from_points(points: &[Vec3]) -> Option<Self> {
+        Some(Self {
+            aabb: Aabb::from_points(points.iter().copied())?,
+            sphere: BoundingSphere::from_points(points)?,
+        })
+    }
+}
+
+/// bounds of every vertex of `model`, including those only used by lines and points
+pub fn model_bounds(model: &ModelData) -> Option<Bounds> {
+    let points: Vec<Vec3> = model
+        .vertices
+        .chunks_exact(3)
+        .map(Vec3::from_slice)
+        .collect();
+    Bounds::from_points(&points)
+}
+
+/// bounds of the vertices the triangles of `submesh` use
+pub fn submesh_bounds(model: &ModelData, submesh: &Submesh) -> Option<Bounds> {
+    let points: Vec<Vec3> = model.indices[submesh.index_range.clone()]
+        .iter()
+        .map(|&vertex| Vec3::from_slice(&model.vertices[vertex as usize * 3..][..3]))
+        .collect();
+    Bounds::from_points(&points)
+}
@@ -0,0 +1,80 @@
+use super::{simplify, SimplifyOptions};
+use crate::loader::ModelData;
+
+/// how `generate_lods` builds its chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodOptions {
+    /// most levels in the chain, the full model included
+    pub max_levels: usize,
+    /// fraction of the previous level's triangles each level aims for
+    pub reduction: f32,
+    /// no level goes below this many triangles
+    pub min_triangles: usize,
+}
+
+impl Default for LodOptions {
+    fn default() -> Self {
+        Self {
+            max_levels: 6,
+            reduction: 0.5,
+            min_triangles: 256,
+        }
+    }
+}
+
+/// one level of detail and how far its surface may be from the full model's
+#[derive(Debug)]
+pub struct LodLevel {
+    /// only the full model has materials, embedded files and load reports,
+    /// the other levels are `ModelData::geometry` so they aren't repeated
+    pub model: ModelData,
+    /// in model units, 0 for the full model
+    pub error: f32,
+}
+
+/**
+ * Simplifies `model` over and over into a chain of levels of detail, from
+ * the full model down to the coarsest level, with the error of each level
+ * growing along the chain
+ *
+ * Each level is made from the one before it, and the chain ends early once
+ * a level can't get at least halfway to its target, e.g. when most of the
+ * vertices are on seams
+ */
+pub fn generate_lods(model: ModelData, options: &LodOptions) -> Vec<LodLevel> {
+    let mut levels = vec![LodLevel { model, error: 0.0 }];
+    while levels.len() < options.max_levels {
+        let previous = levels.last().expect("the chain starts with the full model");
+        let triangles = previous.model.indices.len() / 3;
+        let target = (triangles as f32 * options.reduction) as usize;
+        if target < options.min_triangles {
+            break;
+        }
+        let mut model = previous.model.geometry();
+        let report = simplify(
+            &mut model,
+            &SimplifyOptions {
+                target_triangles: target,
+                max_error: f32::INFINITY,
+            },
+        );
+        if report.triangles > (triangles + target) / 2 {
+            break;
+        }
+        // errors of successive levels add up, since each starts from the last
+        let error = previous.error + report.error;
+        levels.push(LodLevel { model, error });
+    }
+    levels
+}
+
+/**
+ * The coarsest level whose error stays within `max_pixel_error` on screen,
+ * `pixels_per_unit` being the size of one model unit where it is drawn
+ */
+pub fn select_lod(levels: &[LodLevel], pixels_per_unit: f32, max_pixel_error: f32) -> usize {
+    levels
+        .iter()
+        .rposition(|level| level.error * pixels_per_unit <= max_pixel_error)
+        .unwrap_or(0)
+}
@@ -0,0 +1,22 @@
+//! Processing steps that run on loaded `ModelData`, independent of the file
+//! format it was loaded from.
+
+mod bounds;
+mod lod;
+mod normals;
+mod optimize;
+mod simplify;
+mod tangents;
+mod weld;
+
+pub use bounds::{model_bounds, submesh_bounds, Aabb, BoundingSphere, Bounds};
+pub use lod::{generate_lods, select_lod, LodLevel, LodOptions};
+pub use normals::{generate_normals, NormalWeighting};
+pub use optimize::{
+    analyze_vertex_cache, optimize_mesh, optimize_overdraw, optimize_vertex_cache,
+    optimize_vertex_fetch, MeshOptimizationReport, VertexCacheStats, OVERDRAW_THRESHOLD,
+    VERTEX_CACHE_SIZE,
+};
+pub use simplify::{simplify, SimplifyOptions, SimplifyReport};
+pub use tangents::generate_tangents;
+pub use weld::{weld_vertices, WeldOptions, WeldReport};
@@ -0,0 +1,114 @@
+use glam::Vec3;
+
+use crate::loader::ModelData;
+
+/// how much each adjacent triangle contributes to a generated vertex normal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// larger triangles pull the normal further towards themselves
+    Area,
+    /// each triangle counts by the angle of its corner at the vertex, so the
+    /// result doesn't depend on how a surface happens to be triangulated
+    Angle,
+}
+
+/// which triangles around a vertex are allowed to share a normal
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cluster {
+    /// a smoothing group (`s 1`, `s 2`, ...)
+    Group(u32),
+    /// smoothing off: only triangles in the same plane share a normal
+    Flat(Vec3),
+}
+
+impl Cluster {
+    fn matches(&self, other: &Cluster) -> bool {
+        match (self, other) {
+            (Cluster::Group(a), Cluster::Group(b)) => a == b,
+            (Cluster::Flat(a), Cluster::Flat(b)) => a.dot(*b) > 1.0 - 1e-4,
+            _ => false,
+        }
+    }
+}
+
+/**
+ * Fills in normals for every vertex that doesn't already have one
+ *
+ * Triangles in the same smoothing group are smoothed across shared vertices,
+ * while vertices on a boundary between groups (or on faces with smoothing
+ * off) are split so that the edge stays hard. Triangles without a smoothing
+ * group entry are treated as `s off`
+ */
+pub fn generate_normals(model: &mut ModelData, weighting: NormalWeighting) {
+    let vertex_count = model.vertex_count();
+    let needs_normal: Vec<bool> = model
+        .normals
+        .get_or_insert_with(|| vec![0.0; vertex_count * 3])
+        .chunks_exact(3)
+        .map(|n| Vec3::from_slice(n) == Vec3::ZERO)
+        .collect();
+
+    // the clusters already assigned to each vertex, first one keeps the original vertex
+    let mut vertex_clusters: Vec<Vec<(Cluster, u32)>> = vec![Vec::new(); vertex_count];
+    let mut accumulated = vec![Vec3::ZERO; vertex_count];
+
+    for triangle in 0..model.indices.len() / 3 {
+        let corners = [
+            model.indices[triangle * 3] as usize,
+            model.indices[triangle * 3 + 1] as usize,
+            model.indices[triangle * 3 + 2] as usize,
+        ];
+        let positions = corners.map(|v| Vec3::from_slice(&model.vertices[v * 3..v * 3 + 3]));
+        let face_normal = (positions[1] - positions[0]).cross(positions[2] - positions[0]);
+        if face_normal == Vec3::ZERO {
+            // degenerate triangles have no direction to contribute
+            continue;
+        }
+        let cluster = match model.smoothing_groups.get(triangle) {
+            Some(&group) if group != 0 => Cluster::Group(group),
+            _ => Cluster::Flat(face_normal.normalize_or_zero()),
+        };
+
+        for (corner, &vertex) in corners.iter().enumerate() {
+            if !needs_normal[vertex] {
+                continue;
+            }
+            let existing = vertex_clusters[vertex]
+                .iter()
+                .find(|(assigned, _)| assigned.matches(&cluster))
+                .map(|(_, output)| *output);
+            let output = match existing {
+                Some(output) => output,
+                None => {
+                    let output = if vertex_clusters[vertex].is_empty() {
+                        vertex as u32
+                    } else {
+                        accumulated.push(Vec3::ZERO);
+                        model.duplicate_vertex(vertex as u32)
+                    };
+                    vertex_clusters[vertex].push((cluster, output));
+                    output
+                }
+            };
+            model.indices[triangle * 3 + corner] = output;
+
+            let weight = match weighting {
+                NormalWeighting::Area => 1.0,
+                NormalWeighting::Angle => {
+                    let to_next = positions[(corner + 1) % 3] - positions[corner];
+                    let to_prev = positions[(corner + 2) % 3] - positions[corner];
+                    to_next.angle_between(to_prev) / face_normal.length().max(f32::EPSILON)
+                }
+            };
+            // the cross product's length is twice the triangle's area
+            accumulated[output as usize] += face_normal * weight;
+        }
+    }
+
+    let normals = model.normals.as_mut().expect("normals were set above");
+    for (vertex, normal) in accumulated.iter().enumerate() {
+        if *normal != Vec3::ZERO {
+            normals[vertex * 3..vertex * 3 + 3].copy_from_slice(&normal.normalize().to_array());
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::ops::Range;
+
+use ahash::AHashMap;
+use glam::Vec3;
+
+use super::weld::keep_vertices;
+use crate::loader::ModelData;
+
+/// entries of the post-transform cache that is simulated and optimized for
+pub const VERTEX_CACHE_SIZE: usize = 16;
+/// how much worse than the cache-optimized order the overdraw pass may make it
+pub const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+/// how well an index buffer uses a FIFO post-transform vertex cache
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertexCacheStats {
+    /// average cache miss ratio: vertices transformed per triangle, 0.5 at
+    /// best for a large regular mesh and 3 at worst
+    pub acmr: f32,
+    /// average transform to vertex ratio: vertices transformed per vertex
+    /// used, 1 at best
+    pub atvr: f32,
+}
+
+/// the cache statistics before and after `optimize_mesh`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshOptimizationReport {
+    pub before: VertexCacheStats,
+    pub after: VertexCacheStats,
+}
+
+/// a first-in first-out cache of transformed vertices, like most GPUs have
+struct FifoCache {
+    entries: Vec<u32>,
+    next: usize,
+}
+
+impl FifoCache {
+    fn new(size: usize) -> Self {
+        Self {
+            entries: vec![u32::MAX; size.max(1)],
+            next: 0,
+        }
+    }
+
+    /// looks `vertex` up, adding it on a miss; returns whether it missed
+    fn miss(&mut self, vertex: u32) -> bool {
+        if self.entries.contains(&vertex) {
+            return false;
+        }
+        self.entries[self.next] = vertex;
+        self.next = (self.next + 1) % self.entries.len();
+        true
+    }
+}
+
+/// simulates drawing `indices` through a FIFO cache of `cache_size` vertices
+pub fn analyze_vertex_cache(indices: &[u32], cache_size: usize) -> VertexCacheStats {
+    let mut cache = FifoCache::new(cache_size);
+    let misses = indices.iter().filter(|&&v| cache.miss(v)).count();
+    let mut used: Vec<u32> = indices.to_vec();
+    used.sort_unstable();
+    used.dedup();
+    let ratio = |count: usize| {
+        if count == 0 {
+            0.0
+        } else {
+            misses as f32 / count as f32
+        }
+    };
+    VertexCacheStats {
+        acmr: ratio(indices.len() / 3),
+        atvr: ratio(used.len()),
+    }
+}
+
+/**
+ * Runs every reordering below: vertex cache, then overdraw, then vertex fetch,
+ * and reports the cache statistics from before and after
+ *
+ * Only the order of triangles and vertices changes, each submesh keeps the
+ * same triangles
+ */
+pub fn optimize_mesh(model: &mut ModelData) -> MeshOptimizationReport {
+    let before = analyze_vertex_cache(&model.indices, VERTEX_CACHE_SIZE);
+    optimize_vertex_cache(model);
+    optimize_overdraw(model, OVERDRAW_THRESHOLD);
+    optimize_vertex_fetch(model);
+    MeshOptimizationReport {
+        before,
+        after: analyze_vertex_cache(&model.indices, VERTEX_CACHE_SIZE),
+    }
+}
+
+/**
+ * Reorders the triangles of each submesh so that consecutive triangles share
+ * vertices still in the post-transform cache, using Tipsify (Sander, Nehab
+ * and Barczak, 2007)
+ *
+ * From the current vertex all of its remaining triangles are drawn, then the
+ * next vertex is picked among the ones just drawn, preferring those that will
+ * still be in the cache after their remaining triangles are drawn
+ */
+pub fn optimize_vertex_cache(model: &mut ModelData) {
+    reorder_triangles(model, |indices| tipsify(indices, VERTEX_CACHE_SIZE));
+}
+
+/**
+ * Reorders clusters of cache-optimized triangles so that those facing out
+ * from the middle of each submesh are drawn first, hiding what's behind them
+ * with fewer overwritten pixels
+ *
+ * The triangles are split where the cache starts over anyway, and further
+ * wherever a cluster can end while its cache miss ratio stays within
+ * `threshold` times that of the whole run, so the cache order mostly survives
+ * (after Sander, Nehab and Barczak, 2007). Run `optimize_vertex_cache` first
+ */
+pub fn optimize_overdraw(model: &mut ModelData, threshold: f32) {
+    let positions: Vec<Vec3> = model
+        .vertices
+        .chunks_exact(3)
+        .map(Vec3::from_slice)
+        .collect();
+    reorder_triangles(model, |indices| {
+        overdraw_order(indices, &positions, threshold)
+    });
+}
+
+/**
+ * Renumbers the vertices in the order the triangles first use them, so the
+ * GPU fetches vertex data from memory front to back; vertices only lines and
+ * points use come next and unused vertices are dropped
+ */
+pub fn optimize_vertex_fetch(model: &mut ModelData) {
+    let mut remap = vec![u32::MAX; model.vertex_count()];
+    let mut kept = Vec::with_capacity(model.vertex_count());
+    for index in model
+        .indices
+        .iter_mut()
+        .chain(&mut model.line_indices)
+        .chain(&mut model.point_indices)
+    {
+        let slot = &mut remap[*index as usize];
+        if *slot == u32::MAX {
+            *slot = kept.len() as u32;
+            kept.push(*index);
+        }
+        *index = *slot;
+    }
+    keep_vertices(model, &kept);
+}
+
+/**
+ * Applies `order`, which maps the indices of a run of triangles to the order
+ * to draw them in, separately to each submesh and to the runs between them,
+ * carrying the smoothing groups along
+ */
+fn reorder_triangles(model: &mut ModelData, mut order: impl FnMut(&[u32]) -> Vec<usize>) {
+    let triangle_count = model.indices.len() / 3;
+    let mut bounds: Vec<usize> = model
+        .submeshes
+        .iter()
+        .flat_map(|submesh| [submesh.index_range.start / 3, submesh.index_range.end / 3])
+        .chain([0, triangle_count])
+        .filter(|&bound| bound <= triangle_count)
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let has_groups = model.smoothing_groups.len() == triangle_count;
+    for run in bounds.windows(2).map(|pair| pair[0]..pair[1]) {
+        let Range { start, end } = run;
+        let indices = &model.indices[start * 3..end * 3];
+        let triangles = order(indices);
+        let reordered: Vec<u32> = triangles
+            .iter()
+            .flat_map(|&t| indices[t * 3..t * 3 + 3].iter().copied())
+            .collect();
+        model.indices[start * 3..end * 3].copy_from_slice(&reordered);
+        if has_groups {
+            let groups = &model.smoothing_groups[start..end];
+            let reordered: Vec<u32> = triangles.iter().map(|&t| groups[t]).collect();
+            model.smoothing_groups[start..end].copy_from_slice(&reordered);
+        }
+    }
+}
+
+/// the triangle order Tipsify picks for `indices`
+fn tipsify(indices: &[u32], cache_size: usize) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    // numbered from 0 within the run, so a small submesh of a large model stays cheap
+    let mut local_ids: AHashMap<u32, u32> = AHashMap::new();
+    let indices: Vec<u32> = indices
+        .iter()
+        .map(|v| {
+            let next = local_ids.len() as u32;
+            *local_ids.entry(*v).or_insert(next)
+        })
+        .collect();
+    let vertex_count = local_ids.len();
+
+    // triangles around each vertex, as offsets into one list
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for &v in &indices {
+        offsets[v as usize + 1] += 1;
+    }
+    for v in 0..vertex_count {
+        offsets[v + 1] += offsets[v];
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    let mut filled = offsets.clone();
+    for (corner, &v) in indices.iter().enumerate() {
+        adjacency[filled[v as usize]] = corner / 3;
+        filled[v as usize] += 1;
+    }
+
+    let mut live: Vec<usize> = (0..vertex_count)
+        .map(|v| offsets[v + 1] - offsets[v])
+        .collect();
+    let mut cache_time = vec![0usize; vertex_count];
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_ends: Vec<u32> = Vec::new();
+    let mut order = Vec::with_capacity(triangle_count);
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+
+    let mut current = indices.first().map(|&v| v as usize);
+    while let Some(vertex) = current {
+        let mut candidates: Vec<u32> = Vec::new();
+        for &triangle in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+            if emitted[triangle] {
+                continue;
+            }
+            emitted[triangle] = true;
+            order.push(triangle);
+            for &v in &indices[triangle * 3..triangle * 3 + 3] {
+                dead_ends.push(v);
+                candidates.push(v);
+                live[v as usize] -= 1;
+                if time - cache_time[v as usize] > cache_size {
+                    cache_time[v as usize] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // the candidate that stays longest in the cache once its triangles are drawn
+        let mut best = None;
+        let mut best_priority = -1isize;
+        for &v in &candidates {
+            let v = v as usize;
+            if live[v] == 0 {
+                continue;
+            }
+            let age = time - cache_time[v];
+            let priority = if age + 2 * live[v] <= cache_size {
+                age as isize
+            } else {
+                0
+            };
+            if priority > best_priority {
+                best_priority = priority;
+                best = Some(v);
+            }
+        }
+        current = best.or_else(|| {
+            // nothing left around here: back to a recent vertex, or the next in order
+            while let Some(v) = dead_ends.pop() {
+                if live[v as usize] > 0 {
+                    return Some(v as usize);
+                }
+            }
+            while cursor < vertex_count {
+                if live[cursor] > 0 {
+                    return Some(cursor);
+                }
+                cursor += 1;
+            }
+            None
+        });
+    }
+    order
+}
+
+/// the cluster order that draws outward-facing clusters of `indices` first
+fn overdraw_order(indices: &[u32], positions: &[Vec3], threshold: f32) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    let clusters = overdraw_clusters(indices, threshold);
+
+    let triangle = |t: usize| [0, 1, 2].map(|i| positions[indices[t * 3 + i] as usize]);
+    let mut area_sum = 0.0;
+    let mut centroid_sum = Vec3::ZERO;
+    for t in 0..triangle_count {
+        let [a, b, c] = triangle(t);
+        let area = (b - a).cross(c - a).length();
+        area_sum += area;
+        centroid_sum += (a + b + c) * area;
+    }
+    let mesh_centroid = if area_sum > 0.0 {
+        centroid_sum / (3.0 * area_sum)
+    } else {
+        Vec3::ZERO
+    };
+
+    let mut keyed: Vec<(f32, Range<usize>)> = clusters
+        .into_iter()
+        .map(|cluster| {
+            let mut area_sum = 0.0;
+            let mut centroid_sum = Vec3::ZERO;
+            let mut normal_sum = Vec3::ZERO;
+            for t in cluster.clone() {
+                let [a, b, c] = triangle(t);
+                let normal = (b - a).cross(c - a);
+                let area = normal.length();
+                area_sum += area;
+                centroid_sum += (a + b + c) * area;
+                normal_sum += normal;
+            }
+            let centroid = if area_sum > 0.0 {
+                centroid_sum / (3.0 * area_sum)
+            } else {
+                mesh_centroid
+            };
+            let key = (centroid - mesh_centroid).dot(normal_sum.normalize_or_zero());
+            (key, cluster)
+        })
+        .collect();
+    // facing furthest out first; the sort is stable so ties keep the cache order
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().flat_map(|(_, cluster)| cluster).collect()
+}
+
+/// splits cache-ordered triangles into runs that can be drawn in any order
+fn overdraw_clusters(indices: &[u32], threshold: f32) -> Vec<Range<usize>> {
+    let triangle_count = indices.len() / 3;
+    let misses = |cache: &mut FifoCache, t: usize| {
+        indices[t * 3..t * 3 + 3]
+            .iter()
+            .filter(|&&v| cache.miss(v))
+            .count()
+    };
+
+    // where all three vertices miss the cache starts over anyway
+    let mut cache = FifoCache::new(VERTEX_CACHE_SIZE);
+    let mut hard_starts: Vec<usize> = (0..triangle_count)
+        .filter(|&t| misses(&mut cache, t) == 3)
+        .collect();
+    if hard_starts.first() != Some(&0) {
+        hard_starts.insert(0, 0);
+    }
+    hard_starts.push(triangle_count);
+
+    let mut clusters = Vec::new();
+    for run in hard_starts.windows(2) {
+        let (start, end) = (run[0], run[1]);
+        if start == end {
+            continue;
+        }
+        let mut cache = FifoCache::new(VERTEX_CACHE_SIZE);
+        let run_misses: usize = (start..end).map(|t| misses(&mut cache, t)).sum();
+        let run_threshold = threshold * run_misses as f32 / (end - start) as f32;
+
+        // a cluster may end once its own miss ratio, counted from an empty
+        // cache, is no worse than the threshold
+        let mut cache = FifoCache::new(VERTEX_CACHE_SIZE);
+        let first_cluster = clusters.len();
+        let mut cluster_start = start;
+        let mut cluster_misses = 0;
+        let mut cluster_acmr = 0.0;
+        for t in start..end {
+            cluster_misses += misses(&mut cache, t);
+            cluster_acmr = cluster_misses as f32 / (t + 1 - cluster_start) as f32;
+            if cluster_acmr <= run_threshold && t + 1 < end {
+                clusters.push(cluster_start..t + 1);
+                cluster_start = t + 1;
+                cluster_misses = 0;
+                cache = FifoCache::new(VERTEX_CACHE_SIZE);
+            }
+        }
+        // a tail that never got down to the threshold stays with the cluster before it
+        if clusters.len() > first_cluster && cluster_acmr > run_threshold {
+            if let Some(previous) = clusters.last_mut() {
+                previous.end = end;
+            }
+        } else {
+            clusters.push(cluster_start..end);
+        }
+    }
+    clusters
+}
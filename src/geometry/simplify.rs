@@ -0,0 +1,512 @@
+use std::ops::AddAssign;
+
+use ahash::{AHashMap, AHashSet};
+use glam::DVec3;
+
+use super::weld::{keep_vertices, remove_collapsed_triangles};
+use crate::loader::ModelData;
+
+/// how far `simplify` may go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyOptions {
+    /// stop once the model has this many triangles or fewer
+    pub target_triangles: usize,
+    /// stop before a collapse would move the surface further than this, in model units
+    pub max_error: f32,
+}
+
+/// what `simplify` reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyReport {
+    /// triangles left in the model
+    pub triangles: usize,
+    /// the largest error of any collapse made, in model units
+    pub error: f32,
+}
+
+/// how much more the distance from an open or seam edge counts than from a face
+const BOUNDARY_WEIGHT: f64 = 10.0;
+/// each pass takes collapses up to this many times the cost of the one that
+/// would reach the target, since many of the cheaper ones get skipped
+const PASS_ERROR_FACTOR: f64 = 1.5;
+/// marks a vertex without an open edge on that side
+const NONE: u32 = u32::MAX;
+
+/**
+ * Sum of squared distances to a set of weighted planes, as the symmetric
+ * 4x4 matrix of Garland and Heckbert's quadric error metric
+ */
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    /// upper triangle of the 3x3 part: xx, xy, xz, yy, yz, zz
+    a: [f64; 6],
+    b: DVec3,
+    c: f64,
+    weight: f64,
+}
+
+impl Quadric {
+    /// the plane through `point` facing `normal` (a unit vector), counting `weight` times
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let d = -normal.dot(point);
+        let n = normal * weight;
+        Self {
+            a: [
+                n.x * normal.x,
+                n.x * normal.y,
+                n.x * normal.z,
+                n.y * normal.y,
+                n.y * normal.z,
+                n.z * normal.z,
+            ],
+            b: n * d,
+            c: d * d * weight,
+            weight,
+        }
+    }
+
+    /// weighted mean of the squared distances from `p` to the planes
+    fn error(&self, p: DVec3) -> f64 {
+        let [xx, xy, xz, yy, yz, zz] = self.a;
+        let squared = p.x * (xx * p.x + 2.0 * (xy * p.y + xz * p.z + self.b.x))
+            + p.y * (yy * p.y + 2.0 * (yz * p.z + self.b.y))
+            + p.z * (zz * p.z + 2.0 * self.b.z)
+            + self.c;
+        if self.weight > 0.0 {
+            squared.max(0.0) / self.weight
+        } else {
+            0.0
+        }
+    }
+}
+
+impl AddAssign for Quadric {
+    fn add_assign(&mut self, other: Self) {
+        for (a, b) in self.a.iter_mut().zip(other.a) {
+            *a += b;
+        }
+        self.b += other.b;
+        self.c += other.c;
+        self.weight += other.weight;
+    }
+}
+
+/// how a vertex may move, decided from the triangles around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    /// surrounded by triangles on every side, can collapse onto any neighbour
+    Manifold,
+    /// on an open boundary, can only slide along it
+    Border,
+    /// one of two copies of a position whose attributes differ across an
+    /// edge, can only slide along that edge together with its copy
+    Seam,
+    /// anything else (corners of several submeshes, non-manifold vertices), never moves
+    Locked,
+}
+
+impl VertexKind {
+    fn can_collapse_onto(self, target: VertexKind) -> bool {
+        match self {
+            VertexKind::Manifold => true,
+            VertexKind::Border | VertexKind::Seam => self == target,
+            VertexKind::Locked => false,
+        }
+    }
+}
+
+/// the open edges going into and out of each vertex, in index space
+struct OpenEdges {
+    /// every directed edge of every triangle
+    edges: AHashSet<(u32, u32)>,
+    /// the vertex the open edge ending here starts at, `NONE` if there is
+    /// none, the vertex itself if there are several
+    incoming: Vec<u32>,
+    outgoing: Vec<u32>,
+}
+
+impl OpenEdges {
+    fn new(indices: &[u32], vertex_count: usize) -> Self {
+        let edges: AHashSet<(u32, u32)> = indices
+            .chunks_exact(3)
+            .flat_map(|triangle| {
+                (0..3).map(move |corner| (triangle[corner], triangle[(corner + 1) % 3]))
+            })
+            .collect();
+        let mut incoming = vec![NONE; vertex_count];
+        let mut outgoing = vec![NONE; vertex_count];
+        let record = |slot: &mut u32, vertex: u32, other: u32| {
+            *slot = if *slot == NONE { other } else { vertex };
+        };
+        for &(from, to) in &edges {
+            if !edges.contains(&(to, from)) {
+                record(&mut outgoing[from as usize], from, to);
+                record(&mut incoming[to as usize], to, from);
+            }
+        }
+        Self {
+            edges,
+            incoming,
+            outgoing,
+        }
+    }
+
+    /// whether no triangle has the edge from `to` back to `from`
+    fn is_open(&self, from: u32, to: u32) -> bool {
+        !self.edges.contains(&(to, from))
+    }
+
+    /// whether `from` and `to` are joined by the one open edge on either side of `from`
+    fn along(&self, from: u32, to: u32) -> bool {
+        let single = |other: u32| other != NONE && other != from;
+        let (incoming, outgoing) = (self.incoming[from as usize], self.outgoing[from as usize]);
+        single(incoming) && single(outgoing) && (incoming == to || outgoing == to)
+    }
+}
+
+/// a candidate move of one vertex onto a neighbour's position
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    from: u32,
+    to: u32,
+    /// squared error the merged quadric gives at the new position
+    cost: f64,
+}
+
+/**
+ * Reduces the triangle count of `model` by collapsing edges, cheapest first,
+ * under the quadric error metric
+ *
+ * Every vertex is moved onto one of its neighbours, so no new positions or
+ * attributes are made up. Vertices on open boundaries only slide along the
+ * boundary and attribute seams (a position with two sets of UVs or normals)
+ * only slide along the seam, both copies together, so neither tears; corners
+ * shared between submeshes and non-manifold vertices stay put. Collapses
+ * that would flip a triangle over are skipped
+ *
+ * Models whose every vertex sits on a seam (flat shading, where each face
+ * has normals of its own) can't be simplified much; weld them without
+ * respecting normal seams and regenerate the normals afterwards
+ *
+ * Lines and points are left as they are, and vertices nothing uses any more
+ * are removed at the end
+ */
+pub fn simplify(model: &mut ModelData, options: &SimplifyOptions) -> SimplifyReport {
+    let vertex_count = model.vertex_count();
+    let positions: Vec<DVec3> = model
+        .vertices
+        .chunks_exact(3)
+        .map(|p| DVec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+        .collect();
+
+    // `position_of` is the first vertex at the same position, `wedge` the
+    // next one there, in a ring
+    let mut first_at: AHashMap<[u32; 3], u32> = AHashMap::with_capacity(vertex_count);
+    let mut position_of: Vec<u32> = Vec::with_capacity(vertex_count);
+    let mut wedge: Vec<u32> = (0..vertex_count as u32).collect();
+    for (v, p) in model.vertices.chunks_exact(3).enumerate() {
+        let first = *first_at
+            .entry([p[0].to_bits(), p[1].to_bits(), p[2].to_bits()])
+            .or_insert(v as u32);
+        position_of.push(first);
+        if first != v as u32 {
+            wedge.swap(first as usize, v);
+        }
+    }
+
+    let kinds = classify(model, &position_of, &wedge);
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    let open = OpenEdges::new(&model.indices, vertex_count);
+    for triangle in model.indices.chunks_exact(3) {
+        let corners = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let area = normal.length() * 0.5;
+        if area > 0.0 {
+            let plane = Quadric::plane(normal.normalize(), corners[0], area);
+            for &v in triangle {
+                quadrics[position_of[v as usize] as usize] += plane;
+            }
+        }
+        // a plane through each open edge, at right angles to the triangle,
+        // keeps boundaries and seams from shrinking
+        for corner in 0..3 {
+            let (from, to) = (triangle[corner], triangle[(corner + 1) % 3]);
+            if !open.is_open(from, to) {
+                continue;
+            }
+            let edge = corners[(corner + 1) % 3] - corners[corner];
+            let length = edge.length();
+            let side = edge.cross(normal).normalize_or_zero();
+            if length > 0.0 && side != DVec3::ZERO {
+                let plane =
+                    Quadric::plane(side, corners[corner], length * length * BOUNDARY_WEIGHT);
+                quadrics[position_of[from as usize] as usize] += plane;
+                quadrics[position_of[to as usize] as usize] += plane;
+            }
+        }
+    }
+
+    let target = options.target_triangles;
+    let max_cost = (options.max_error as f64).powi(2);
+    let mut error = 0.0f64;
+    let mut collapse_to: Vec<u32> = (0..vertex_count as u32).collect();
+    while model.indices.len() / 3 > target {
+        let open = OpenEdges::new(&model.indices, vertex_count);
+        let mut candidates = pick_collapses(
+            model,
+            &kinds,
+            &wedge,
+            &open,
+            &position_of,
+            &positions,
+            &quadrics,
+        );
+        candidates.sort_by(|a, b| a.cost.total_cmp(&b.cost));
+        let triangle_goal = model.indices.len() / 3 - target;
+        let pass_cost = candidates
+            .get(triangle_goal / 2)
+            .map_or(f64::INFINITY, |c| c.cost * PASS_ERROR_FACTOR);
+
+        let triangles_around =
+            triangles_around_positions(&model.indices, &position_of, vertex_count);
+        let mut moved = vec![false; vertex_count];
+        let mut removed = 0;
+        for collapse in candidates {
+            if collapse.cost > max_cost || collapse.cost > pass_cost {
+                break;
+            }
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            let (from_position, to_position) =
+                (position_of[from] as usize, position_of[to] as usize);
+            if moved[from_position] || moved[to_position] {
+                continue;
+            }
+            if flips_triangles(
+                &model.indices,
+                &triangles_around[from_position],
+                &position_of,
+                &positions,
+                from_position as u32,
+                to_position as u32,
+            ) {
+                continue;
+            }
+            collapse_to[from] = collapse.to;
+            if kinds[from] == VertexKind::Seam {
+                collapse_to[wedge[from] as usize] = wedge[to];
+            }
+            let merged = quadrics[from_position];
+            quadrics[to_position] += merged;
+            moved[from_position] = true;
+            moved[to_position] = true;
+            error = error.max(collapse.cost);
+            removed += if kinds[from] == VertexKind::Border {
+                1
+            } else {
+                2
+            };
+            if removed >= triangle_goal {
+                break;
+            }
+        }
+        if removed == 0 {
+            break;
+        }
+        for index in &mut model.indices {
+            *index = collapse_to[*index as usize];
+        }
+        remove_collapsed_triangles(model);
+    }
+
+    remove_unused_vertices(model);
+    SimplifyReport {
+        triangles: model.indices.len() / 3,
+        error: error.sqrt() as f32,
+    }
+}
+
+/// decides once, before any collapse, how each vertex may move
+fn classify(model: &ModelData, position_of: &[u32], wedge: &[u32]) -> Vec<VertexKind> {
+    let vertex_count = model.vertex_count();
+    let open = OpenEdges::new(&model.indices, vertex_count);
+
+    // vertices used by more than one submesh keep the border between them
+    let mut submesh_of: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut used = vec![false; vertex_count];
+    let mut shared = vec![false; vertex_count];
+    for (submesh, range) in model.submeshes.iter().map(|s| &s.index_range).enumerate() {
+        for &v in &model.indices[range.clone()] {
+            let v = v as usize;
+            match submesh_of[v] {
+                Some(other) if other != submesh => shared[v] = true,
+                _ => submesh_of[v] = Some(submesh),
+            }
+        }
+    }
+    for &v in &model.indices {
+        used[v as usize] = true;
+    }
+
+    let single = |v: usize, other: u32| other != NONE && other != v as u32;
+    (0..vertex_count)
+        .map(|v| {
+            let (incoming, outgoing) = (open.incoming[v], open.outgoing[v]);
+            if !used[v] || shared[v] {
+                VertexKind::Locked
+            } else if wedge[v] as usize == v {
+                if incoming == NONE && outgoing == NONE {
+                    VertexKind::Manifold
+                } else if single(v, incoming) && single(v, outgoing) {
+                    VertexKind::Border
+                } else {
+                    VertexKind::Locked
+                }
+            } else if wedge[wedge[v] as usize] as usize == v {
+                // the copy on the other side of the seam runs the other way
+                let other = wedge[v] as usize;
+                let (other_incoming, other_outgoing) = (open.incoming[other], open.outgoing[other]);
+                let is_seam = single(v, incoming)
+                    && single(v, outgoing)
+                    && single(other, other_incoming)
+                    && single(other, other_outgoing)
+                    && position_of[incoming as usize] == position_of[other_outgoing as usize]
+                    && position_of[outgoing as usize] == position_of[other_incoming as usize];
+                if is_seam {
+                    VertexKind::Seam
+                } else {
+                    VertexKind::Locked
+                }
+            } else {
+                VertexKind::Locked
+            }
+        })
+        .collect()
+}
+
+/// the cheaper allowed direction of every edge
+fn pick_collapses(
+    model: &ModelData,
+    kinds: &[VertexKind],
+    wedge: &[u32],
+    open: &OpenEdges,
+    position_of: &[u32],
+    positions: &[DVec3],
+    quadrics: &[Quadric],
+) -> Vec<Collapse> {
+    let allowed = |from: u32, to: u32| {
+        let (from_kind, to_kind) = (kinds[from as usize], kinds[to as usize]);
+        from_kind.can_collapse_onto(to_kind)
+            && match from_kind {
+                VertexKind::Border => open.along(from, to),
+                // the copy has to have a seam edge to the target's copy as well
+                VertexKind::Seam => {
+                    open.along(from, to) && open.along(wedge[from as usize], wedge[to as usize])
+                }
+                _ => true,
+            }
+    };
+    let cost = |from: u32, to: u32| {
+        let mut merged = quadrics[position_of[from as usize] as usize];
+        merged += quadrics[position_of[to as usize] as usize];
+        merged.error(positions[to as usize])
+    };
+
+    let mut candidates = Vec::with_capacity(model.indices.len());
+    for triangle in model.indices.chunks_exact(3) {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            // each interior edge shows up in two triangles, take it from one
+            if a > b && !open.is_open(a, b) {
+                continue;
+            }
+            let best = [(a, b), (b, a)]
+                .iter()
+                .filter(|&&(from, to)| allowed(from, to))
+                .map(|&(from, to)| Collapse {
+                    from,
+                    to,
+                    cost: cost(from, to),
+                })
+                .min_by(|x, y| x.cost.total_cmp(&y.cost));
+            candidates.extend(best);
+        }
+    }
+    candidates
+}
+
+/// the triangles touching each position
+fn triangles_around_positions(
+    indices: &[u32],
+    position_of: &[u32],
+    vertex_count: usize,
+) -> Vec<Vec<u32>> {
+    let mut around = vec![Vec::new(); vertex_count];
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &v in corners {
+            around[position_of[v as usize] as usize].push(triangle as u32);
+        }
+    }
+    around
+}
+
+/// whether moving position `from` onto `to` turns any of the triangles around it over
+fn flips_triangles(
+    indices: &[u32],
+    around: &[u32],
+    position_of: &[u32],
+    positions: &[DVec3],
+    from: u32,
+    to: u32,
+) -> bool {
+    around.iter().any(|&triangle| {
+        let corners = &indices[triangle as usize * 3..triangle as usize * 3 + 3];
+        let position = |v: u32| position_of[v as usize];
+        if corners.iter().any(|&v| position(v) == to) {
+            // collapses away along with the edge
+            return false;
+        }
+        let before = [0, 1, 2].map(|i| positions[corners[i] as usize]);
+        let after = [0, 1, 2].map(|i| {
+            if position(corners[i]) == from {
+                positions[to as usize]
+            } else {
+                before[i]
+            }
+        });
+        let normal_before = (before[1] - before[0]).cross(before[2] - before[0]);
+        let normal_after = (after[1] - after[0]).cross(after[2] - after[0]);
+        normal_before.dot(normal_after) <= 1e-2 * normal_before.length() * normal_after.length()
+    })
+}
+
+/// drops vertices no triangle, line or point refers to, keeping the others in order
+fn remove_unused_vertices(model: &mut ModelData) {
+    let mut used = vec![false; model.vertex_count()];
+    for &index in model
+        .indices
+        .iter()
+        .chain(&model.line_indices)
+        .chain(&model.point_indices)
+    {
+        used[index as usize] = true;
+    }
+    let kept: Vec<u32> = (0..model.vertex_count() as u32)
+        .filter(|&v| used[v as usize])
+        .collect();
+    if kept.len() == model.vertex_count() {
+        return;
+    }
+    let mut remap = vec![NONE; model.vertex_count()];
+    for (new, &old) in kept.iter().enumerate() {
+        remap[old as usize] = new as u32;
+    }
+    for index in model
+        .indices
+        .iter_mut()
+        .chain(&mut model.line_indices)
+        .chain(&mut model.point_indices)
+    {
+        *index = remap[*index as usize];
+    }
+    keep_vertices(model, &kept);
+}
@@ -0,0 +1,114 @@
+use ahash::AHashMap;
+use glam::Vec3;
+
+use crate::loader::ModelData;
+
+/// the model's triangles as the reference MikkTSpace implementation sees them
+struct Corners<'a> {
+    model: &'a ModelData,
+    normals: &'a [f32],
+    texcoords: &'a [f32],
+    /// xyzw tangent of each corner, in index order
+    tangents: Vec<[f32; 4]>,
+}
+
+impl Corners<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> usize {
+        self.model.indices[face * 3 + vert] as usize
+    }
+}
+
+impl bevy_mikktspace::Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.model.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let v = self.vertex(face, vert);
+        [0, 1, 2].map(|i| self.model.vertices[v * 3 + i])
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let v = self.vertex(face, vert);
+        [0, 1, 2].map(|i| self.normals[v * 3 + i])
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let v = self.vertex(face, vert);
+        [0, 1].map(|i| self.texcoords[v * 2 + i])
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+/**
+ * Fills in a tangent for every vertex, with the bitangent's sign in w, using
+ * the reference MikkTSpace implementation so that normal maps baked by other
+ * tools shade without seams
+ *
+ * MikkTSpace gives each triangle corner a tangent of its own. Corners of the
+ * same vertex usually agree, but where a mirrored UV island meets the original
+ * or the tangents on either side of a vertex point too far apart, they don't:
+ * those vertices are split so that each keeps its corners' tangent
+ *
+ * Does nothing for models without texture coordinates or normals
+ */
+pub fn generate_tangents(model: &mut ModelData) {
+    let (texcoords, normals) = match (&model.texcoords, &model.normals) {
+        (Some(texcoords), Some(normals)) => (texcoords, normals),
+        _ => return,
+    };
+    let mut corners = Corners {
+        model,
+        normals,
+        texcoords,
+        tangents: vec![[0.0; 4]; model.indices.len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut corners) {
+        // only happens without any triangles; points and lines still get a tangent
+        corners.tangents.clear();
+    }
+    let corner_tangents = corners.tangents;
+
+    let mut tangents: Vec<Option<[f32; 4]>> = vec![None; model.vertex_count()];
+    let mut splits: AHashMap<(u32, [u32; 4]), u32> = AHashMap::new();
+    for (corner, tangent) in corner_tangents.into_iter().enumerate() {
+        let vertex = model.indices[corner];
+        let output = match tangents[vertex as usize] {
+            None => {
+                tangents[vertex as usize] = Some(tangent);
+                vertex
+            }
+            Some(assigned) if assigned == tangent => vertex,
+            Some(_) => *splits
+                .entry((vertex, tangent.map(f32::to_bits)))
+                .or_insert_with(|| {
+                    let copy = model.duplicate_vertex(vertex);
+                    tangents.push(Some(tangent));
+                    copy
+                }),
+        };
+        model.indices[corner] = output;
+    }
+
+    let normals = model.normals.as_ref().expect("normals were checked above");
+    let mut flat_tangents = Vec::with_capacity(model.vertex_count() * 4);
+    for (v, tangent) in tangents.iter().enumerate() {
+        let [x, y, z, sign] = tangent.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+        let tangent = Vec3::new(x, y, z).try_normalize().unwrap_or_else(|| {
+            // MikkTSpace leaves vertices without triangles or UV area at zero,
+            // any direction in the normal's plane will do for those
+            Vec3::from_slice(&normals[v * 3..v * 3 + 3])
+                .try_normalize()
+                .map_or(Vec3::X, |n| n.any_orthonormal_vector())
+        });
+        flat_tangents.extend_from_slice(&tangent.extend(sign).to_array());
+    }
+    model.tangents = Some(flat_tangents);
+}
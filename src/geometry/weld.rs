@@ -0,0 +1,194 @@
+use ahash::AHashMap;
+use glam::{Vec2, Vec3};
+
+use crate::loader::ModelData;
+
+/// how close vertices have to be for `weld_vertices` to merge them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeldOptions {
+    /// largest distance between merged positions; 0 only merges exact duplicates
+    pub epsilon: f32,
+    /// keep vertices apart whose texture coordinates differ by more than `epsilon`
+    pub respect_uv_seams: bool,
+    /// keep vertices apart whose normals differ by more than `epsilon`; turn
+    /// this off and regenerate normals to smooth across cracks
+    pub respect_normal_seams: bool,
+}
+
+impl Default for WeldOptions {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-5,
+            respect_uv_seams: true,
+            respect_normal_seams: true,
+        }
+    }
+}
+
+/// what `weld_vertices` changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WeldReport {
+    /// vertices that were folded into another one and removed
+    pub merged_vertices: usize,
+    /// triangles that lost their area because two of their corners were merged
+    pub removed_triangles: usize,
+}
+
+/// grid cell a position falls in, the size of a cell being the weld epsilon
+type Cell = [i64; 3];
+
+fn cell(position: Vec3, epsilon: f32) -> Cell {
+    if epsilon > 0.0 {
+        (position / epsilon).floor().to_array().map(|c| c as i64)
+    } else {
+        // exact duplicates only: the bits are the cell, once adding 0.0 has
+        // turned -0.0 into 0.0
+        position.to_array().map(|c| (c + 0.0).to_bits() as i64)
+    }
+}
+
+/**
+ * Merges vertices whose positions lie within `options.epsilon` of each other,
+ * closing the cracks left by exporters that duplicate positions
+ *
+ * Positions are hashed into a grid of `epsilon` sized cells, so each vertex is
+ * only compared with those in its own and the neighbouring cells. The first
+ * vertex of a cluster is kept with all of its attributes and the others are
+ * removed; triangles, lines and points are remapped to it. Triangles left with
+ * two corners on the same vertex are removed along with their smoothing group,
+ * and submesh ranges are shrunk to match
+ */
+pub fn weld_vertices(model: &mut ModelData, options: &WeldOptions) -> WeldReport {
+    let epsilon = options.epsilon.max(0.0);
+    let position = |v: usize| Vec3::from_slice(&model.vertices[v * 3..v * 3 + 3]);
+    let texcoords = model
+        .texcoords
+        .as_ref()
+        .filter(|_| options.respect_uv_seams);
+    let normals = model
+        .normals
+        .as_ref()
+        .filter(|_| options.respect_normal_seams);
+    let compatible = |a: usize, b: usize| {
+        let uvs_match = texcoords.is_none_or(|uvs| {
+            let uv = |v: usize| Vec2::from_slice(&uvs[v * 2..v * 2 + 2]);
+            uv(a).distance(uv(b)) <= epsilon
+        });
+        let normals_match = normals.is_none_or(|normals| {
+            let normal = |v: usize| Vec3::from_slice(&normals[v * 3..v * 3 + 3]);
+            normal(a).distance(normal(b)) <= epsilon
+        });
+        position(a).distance(position(b)) <= epsilon && uvs_match && normals_match
+    };
+
+    // the vertices kept so far, by the cell they fall in
+    let mut grid: AHashMap<Cell, Vec<u32>> = AHashMap::new();
+    let mut remap: Vec<u32> = Vec::with_capacity(model.vertex_count());
+    let mut kept: Vec<u32> = Vec::new();
+    for v in 0..model.vertex_count() {
+        let home = cell(position(v), epsilon);
+        let mut found = None;
+        let neighbours = if epsilon > 0.0 { -1..=1 } else { 0..=0 };
+        'search: for dx in neighbours.clone() {
+            for dy in neighbours.clone() {
+                for dz in neighbours.clone() {
+                    let key = [home[0] + dx, home[1] + dy, home[2] + dz];
+                    if let Some(candidates) = grid.get(&key) {
+                        if let Some(&other) = candidates
+                            .iter()
+                            .find(|&&other| compatible(other as usize, v))
+                        {
+                            found = Some(remap[other as usize]);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        match found {
+            Some(output) => remap.push(output),
+            None => {
+                remap.push(kept.len() as u32);
+                kept.push(v as u32);
+                grid.entry(home).or_default().push(v as u32);
+            }
+        }
+    }
+
+    let merged_vertices = model.vertex_count() - kept.len();
+    if merged_vertices > 0 {
+        keep_vertices(model, &kept);
+    }
+    for index in model
+        .indices
+        .iter_mut()
+        .chain(&mut model.line_indices)
+        .chain(&mut model.point_indices)
+    {
+        *index = remap[*index as usize];
+    }
+    let removed_triangles = remove_collapsed_triangles(model);
+    WeldReport {
+        merged_vertices,
+        removed_triangles,
+    }
+}
+
+/// keeps only the vertices listed in `kept`, in that order
+pub(super) fn keep_vertices(model: &mut ModelData, kept: &[u32]) {
+    let gather = |values: &[f32], components: usize| -> Vec<f32> {
+        kept.iter()
+            .flat_map(|&v| &values[v as usize * components..(v as usize + 1) * components])
+            .copied()
+            .collect()
+    };
+    model.vertices = gather(&model.vertices, 3);
+    for (stream, components) in [
+        (&mut model.texcoords, 2),
+        (&mut model.normals, 3),
+        (&mut model.tangents, 4),
+        (&mut model.colors, 3),
+    ] {
+        if let Some(values) = stream {
+            *values = gather(values, components);
+        }
+    }
+    for attribute in &mut model.attributes {
+        attribute.values = gather(&attribute.values, 1);
+    }
+}
+
+/// drops triangles with a repeated corner, returning how many there were
+pub(super) fn remove_collapsed_triangles(model: &mut ModelData) -> usize {
+    let triangle_count = model.indices.len() / 3;
+    let collapsed = |t: usize| {
+        let [a, b, c] = [0, 1, 2].map(|i| model.indices[t * 3 + i]);
+        a == b || b == c || c == a
+    };
+    if !(0..triangle_count).any(collapsed) {
+        return 0;
+    }
+
+    // new start of each triangle's indices, for moving the submesh ranges
+    let mut new_starts = Vec::with_capacity(triangle_count + 1);
+    let mut indices = Vec::with_capacity(model.indices.len());
+    let mut smoothing_groups = Vec::with_capacity(model.smoothing_groups.len());
+    for t in 0..triangle_count {
+        new_starts.push(indices.len());
+        if !collapsed(t) {
+            indices.extend_from_slice(&model.indices[t * 3..t * 3 + 3]);
+            if let Some(&group) = model.smoothing_groups.get(t) {
+                smoothing_groups.push(group);
+            }
+        }
+    }
+    new_starts.push(indices.len());
+    for submesh in &mut model.submeshes {
+        let range = &mut submesh.index_range;
+        *range = new_starts[range.start / 3]..new_starts[range.end / 3];
+    }
+    let removed = triangle_count - indices.len() / 3;
+    model.indices = indices;
+    model.smoothing_groups = smoothing_groups;
+    removed
+}
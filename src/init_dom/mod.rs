@@ -6,7 +6,7 @@ use std::{cell::RefCell, mem, rc::Rc};
 use wasm_bindgen::prelude::*;
 use web_sys::{HtmlCanvasElement, HtmlInputElement, MouseEvent, WheelEvent};
 
-use crate::{log, SharedState};
+use crate::{loader::load_obj, log, SharedState, CANVAS_HEIGHT, CANVAS_WIDTH};
 
 extern crate web_sys;
 
@@ -29,6 +29,8 @@ impl Dom {
             .dyn_into::<HtmlInputElement>()?;
         file_input.set_attribute("type", "file")?;
         file_input.set_attribute("id", "file_upload_input")?;
+        // a model can come with its MTL libraries and textures
+        file_input.set_attribute("multiple", "")?;
         container.append_child(&file_input)?;
 
         let canvas = document
@@ -71,27 +73,33 @@ impl Dom {
         let mouse_wheel_shared_state = shared_state.clone();
         let mouse_wheel_event_callback = Closure::wrap(Box::new(move |e: WheelEvent| {
             e.prevent_default();
-            let prev_offset = mouse_wheel_shared_state.borrow_mut().camera_offset;
             let scroll_delta = e.delta_y();
             log!("mouse wheel delta: {:?}", scroll_delta);
-            let scaling_factor: f64 = 0.25;
-            let new_camera_offset = prev_offset + (scaling_factor * scroll_delta) as f32;
-            let new_camera_offset = new_camera_offset.clamp(1.0, 1000.0);
-            let _ = mem::replace(
-                &mut mouse_wheel_shared_state.borrow_mut().camera_offset,
-                new_camera_offset,
-            );
+            mouse_wheel_shared_state
+                .borrow_mut()
+                .camera
+                .zoom(scroll_delta);
             mouse_wheel_shared_state.borrow().web_gl_state.draw(
-                800,
-                600,
+                CANVAS_WIDTH,
+                CANVAS_HEIGHT,
                 mouse_wheel_shared_state.borrow().current_rotation[0],
                 mouse_wheel_shared_state.borrow().current_rotation[1],
-                mouse_wheel_shared_state.borrow().z_near,
-                mouse_wheel_shared_state.borrow().z_far,
-                mouse_wheel_shared_state.borrow().camera_offset,
+                &mouse_wheel_shared_state.borrow().camera,
             );
         }) as Box<dyn FnMut(WheelEvent)>);
 
+        let upload_shared_state = shared_state.clone();
+        let upload_file_input = self.file_input.clone();
+        let upload_event_callback = Closure::wrap(Box::new(move || {
+            // parsing happens in a worker, the model shows up once it is done
+            let model_shared_state = upload_shared_state.clone();
+            load_obj(&upload_file_input, move |lods| {
+                let mut state = model_shared_state.borrow_mut();
+                state.set_model_data(lods);
+                state.draw();
+            });
+        }) as Box<dyn FnMut()>);
+
         let mouse_drag_shared_state = shared_state;
         let mouse_drag_event_callback = Closure::wrap(Box::new(move |e: MouseEvent| {
             if mouse_drag_shared_state.borrow().canvas_cursor_is_dragging {
@@ -118,13 +126,11 @@ impl Dom {
                     [new_rotation_x, new_rotation_y],
                 );
                 mouse_drag_shared_state.borrow().web_gl_state.draw(
-                    800,
-                    600,
+                    CANVAS_WIDTH,
+                    CANVAS_HEIGHT,
                     mouse_drag_shared_state.borrow().current_rotation[0],
                     mouse_drag_shared_state.borrow().current_rotation[1],
-                    mouse_drag_shared_state.borrow().z_near,
-                    mouse_drag_shared_state.borrow().z_far,
-                    mouse_drag_shared_state.borrow().camera_offset,
+                    &mouse_drag_shared_state.borrow().camera,
                 );
             }
 
@@ -156,9 +162,15 @@ impl Dom {
             mouse_wheel_event_callback.as_ref().unchecked_ref(),
         );
 
+        let _ = self.file_input.add_event_listener_with_callback(
+            "change",
+            upload_event_callback.as_ref().unchecked_ref(),
+        );
+
         mouse_down_event_callback.forget();
         mouse_up_event_callback.forget();
         mouse_drag_event_callback.forget();
         mouse_wheel_event_callback.forget();
+        upload_event_callback.forget();
     }
 }
@@ -1,45 +1,29 @@
+pub mod camera;
+pub mod geometry;
 mod init_dom;
-mod loader;
+pub mod loader;
 mod wasm_utils;
 mod web_gl_state;
 
-use std::{
-    cell::RefCell,
-    io::{BufReader, Cursor},
-    rc::Rc,
-};
+use std::{cell::RefCell, rc::Rc};
 
-use glam::Vec3;
+use camera::Camera;
+use geometry::LodLevel;
 use init_dom::Dom;
-use loader::load_model;
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 use web_gl_state::WebGLState;
 use web_sys::HtmlCanvasElement;
 
-// minicooper camera preset
-const CAMERA_TARGET: Vec3 = Vec3 {
-    x: -30.0,
-    y: 100.0,
-    z: 30.0,
-};
-const INITIAL_CAMERA_OFFSET: f32 = 200.0;
-
-// small model camera preset
-// const CAMERA_TARGET: Vec3 = Vec3 {
-//     x: 0.0,
-//     y: 0.0,
-//     z: 0.0,
-// };
-// const INITIAL_CAMERA_OFFSET: f32 = 5.0;
+// size of the viewport every frame is drawn at
+const CANVAS_WIDTH: u32 = 800;
+const CANVAS_HEIGHT: u32 = 600;
 
 pub struct SharedState {
     canvas_cursor_is_dragging: bool,
     canvas_cursor_xy_coordinates: [i32; 2],
     current_rotation: [f32; 2],
     web_gl_state: WebGLState,
-    z_near: f32,
-    z_far: f32,
-    camera_offset: f32,
+    camera: Camera,
 }
 
 impl SharedState {
@@ -51,11 +35,34 @@ impl SharedState {
             canvas_cursor_xy_coordinates: [0, 0],
             current_rotation: [90.0, 90.0],
             web_gl_state: WebGLState::new(canvas).unwrap(),
-            z_near: 0.1,
-            z_far: 1000.0,
-            camera_offset: INITIAL_CAMERA_OFFSET,
+            camera: Camera::default(),
+        }
+    }
+
+    /// shows the model with levels of detail `lods` and frames the camera around it
+    pub fn set_model_data(&mut self, lods: Vec<LodLevel>) {
+        let aspect = CANVAS_WIDTH as f32 / CANVAS_HEIGHT as f32;
+        if let Some(camera) = lods
+            .first()
+            .and_then(|full| Camera::framing_model(&full.model, aspect))
+        {
+            self.camera = camera;
+        }
+        if let Err(e) = self.web_gl_state.set_model_data(lods) {
+            log!("Failed to upload the model to the GPU: {}", e);
         }
     }
+
+    /// draws a frame with the current rotation and camera
+    pub fn draw(&self) {
+        self.web_gl_state.draw(
+            CANVAS_WIDTH,
+            CANVAS_HEIGHT,
+            self.current_rotation[0],
+            self.current_rotation[1],
+            &self.camera,
+        );
+    }
 }
 
 #[wasm_bindgen(start)]
@@ -63,37 +70,23 @@ pub fn main() -> Result<(), JsValue> {
     // // register a panic hook that forwards Rust panics to JS console
     // panic::set_hook(Box::new(console_error_panic_hook::hook));
 
+    // the model worker loads this same module, but has no page to set up
+    if loader::is_model_worker() {
+        loader::run_model_worker();
+        return Ok(());
+    }
+
     // init DOM and shared mutable state
     let dom = Dom::new()?;
     let shared_state = Rc::new(RefCell::new(SharedState::new(&dom.canvas)));
 
-    // register DOM callbacks for mouse events
+    // register DOM callbacks for mouse events and uploads
     let dom_shared_state = shared_state.clone();
     dom.register_dom_event_callbacks(dom_shared_state);
 
-    // TODO: reintegrate user-uploaded files.  As a temporary workaround, load local file
-    let dummy_file = include_str!("../minicooper.obj");
-    let mut reader = BufReader::new(Cursor::new(dummy_file));
-    let model_data_collection = load_model(&mut reader).unwrap();
-
-    // add loaded model's vertices to shared state
-    shared_state
-        .borrow_mut()
-        .web_gl_state
-        .set_model_data(Some(model_data_collection));
-
-    // render one initial frame (all future frame draws are driven by user mouse inputs)
-    let initial_render_state_rc = shared_state;
-    let initial_render_state = initial_render_state_rc.borrow_mut();
-    initial_render_state.web_gl_state.draw(
-        800,
-        600,
-        90.0,
-        90.0,
-        initial_render_state.z_near,
-        initial_render_state.z_far,
-        initial_render_state.camera_offset,
-    );
+    // render one initial, empty frame (all future frame draws are driven by
+    // uploads and user mouse inputs)
+    shared_state.borrow().draw();
 
     Ok(())
 }
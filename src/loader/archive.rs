@@ -0,0 +1,172 @@
+use std::io::{self, Cursor, Read};
+
+use flate2::read::GzDecoder;
+
+use super::{
+    resolver::RelativeResolver, FileResolver, LoadLimits, MemoryResolver, ModelData, ModelFormat,
+    ObjError,
+};
+
+/// containers a model can be uploaded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Archive {
+    /// any number of files, e.g. an OBJ with its MTL and textures
+    Zip,
+    /// a single compressed file, e.g. `model.stl.gz`
+    Gzip,
+}
+
+impl Archive {
+    pub fn from_extension(file_name: &str) -> Option<Self> {
+        let extension = file_name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "zip" => Some(Archive::Zip),
+            "gz" | "gzip" => Some(Archive::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Some(Archive::Zip)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Some(Archive::Gzip)
+        } else {
+            None
+        }
+    }
+
+    /// magic bytes win over the extension, like `ModelFormat::detect`
+    pub fn detect(file_name: &str, head: &[u8]) -> Option<Self> {
+        Self::from_magic(head).or_else(|| Self::from_extension(file_name))
+    }
+}
+
+fn archive_error(message: String) -> ObjError {
+    ObjError::Archive { message }
+}
+
+/**
+ * Decompresses at most `max` bytes and one more, so that going over a limit
+ * can be noticed without decompressing the rest
+ *
+ * Sizes stored in archive headers can't be trusted, so limits are enforced on
+ * the bytes actually produced
+ */
+fn read_limited(reader: impl Read, max: u64) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    reader
+        .take(max.saturating_add(1))
+        .read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/**
+ * Extracts every file of a zip archive into a resolver keyed by its path
+ * inside the archive
+ *
+ * Directories and macOS resource forks (`__MACOSX/`) are skipped, and entries
+ * whose path would escape the archive are rejected with `UnsafePath`. Each
+ * file and the archive as a whole may unpack to at most `max_file_bytes`
+ */
+pub fn unzip(bytes: &[u8], limits: &LoadLimits) -> Result<MemoryResolver, ObjError> {
+    limits.check_file_size(bytes.len() as u64)?;
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| archive_error(e.to_string()))?;
+    let mut files = MemoryResolver::new();
+    let mut unpacked = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| archive_error(e.to_string()))?;
+        if entry.is_dir() || entry.name().starts_with("__MACOSX/") {
+            continue;
+        }
+        let name = entry.name().to_string();
+        // the whole archive is held to the same limit as each file in it
+        let contents = read_limited(&mut entry, limits.max_file_bytes - unpacked)
+            .map_err(|e| archive_error(format!("failed to extract `{}`: {}", name, e)))?;
+        unpacked += contents.len() as u64;
+        limits.check_file_size(unpacked)?;
+        files.insert(&name, contents)?;
+    }
+    Ok(files)
+}
+
+/// decompresses a gzip file, returning its contents and the name stored in its header
+pub fn gunzip(bytes: &[u8], limits: &LoadLimits) -> Result<(Vec<u8>, Option<String>), ObjError> {
+    limits.check_file_size(bytes.len() as u64)?;
+    let mut decoder = GzDecoder::new(bytes);
+    let contents = read_limited(&mut decoder, limits.max_file_bytes)
+        .map_err(|e| archive_error(e.to_string()))?;
+    limits.check_file_size(contents.len() as u64)?;
+    let name = decoder
+        .header()
+        .and_then(|header| header.filename())
+        .map(|name| String::from_utf8_lossy(name).into_owned());
+    Ok((contents, name))
+}
+
+/**
+ * Picks the model among the files of an archive
+ *
+ * Only files with a model extension are considered. The one closest to the
+ * root of the archive wins, since that is where people put the model they
+ * mean and where its MTL references usually start from; ties are broken by
+ * name so the choice is stable
+ */
+pub fn find_primary_model(files: &MemoryResolver) -> Option<String> {
+    files
+        .paths()
+        .filter(|path| {
+            let file_name = path.rsplit('/').next().unwrap_or(path);
+            !file_name.starts_with('.') && ModelFormat::from_extension(file_name).is_some()
+        })
+        .min_by_key(|path| (path.matches('/').count(), path.to_string()))
+        .map(str::to_string)
+}
+
+/**
+ * Loads the model from an uploaded archive
+ *
+ * For a zip the primary model is loaded with the rest of the archive as its
+ * files, resolved relative to the model's own directory. A gzip file holds
+ * just the model, whose format comes from its magic bytes or the file name
+ * without `.gz`, and whose references are resolved through `resolver`
+ */
+pub fn load_archive(
+    archive: Archive,
+    file_name: &str,
+    bytes: &[u8],
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+) -> Result<ModelData, ObjError> {
+    match archive {
+        Archive::Zip => {
+            let files = unzip(bytes, limits)?;
+            let model_path = find_primary_model(&files)
+                .ok_or_else(|| archive_error(String::from("no model file in the archive")))?;
+            let contents = files
+                .resolve(&model_path)?
+                .expect("the model was found among the archive's files");
+            let format = ModelFormat::detect(&model_path, &contents, contents.len() as u64);
+            format.load(
+                &contents,
+                &RelativeResolver::new(&model_path, &files),
+                limits,
+            )
+        }
+        Archive::Gzip => {
+            let (contents, stored_name) = gunzip(bytes, limits)?;
+            let stem = file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(stem, _)| stem);
+            let inner_name = match ModelFormat::from_extension(stem) {
+                Some(_) => stem,
+                None => stored_name.as_deref().unwrap_or(stem),
+            };
+            let format = ModelFormat::detect(inner_name, &contents, contents.len() as u64);
+            format.load(&contents, resolver, limits)
+        }
+    }
+}
@@ -0,0 +1,867 @@
+use std::{collections::BTreeMap, convert::TryInto, ops::Range};
+
+use ahash::AHashMap;
+
+use crate::geometry::LodLevel;
+
+use super::{EmbeddedFile, Material, ModelData, ObjError, PbrMaterial, Submesh, VertexAttribute};
+
+/// first bytes of every cached model
+pub const CACHE_MAGIC: [u8; 4] = *b"WCMD";
+
+/// bumped whenever the layout changes or the loaders would produce a
+/// different `ModelData` for the same upload, so stale entries are ignored
+pub const CACHE_VERSION: u32 = 4;
+
+/// magic, version, key, payload length, checksum and stream count
+const HEADER_BYTES: usize = 32;
+/// one entry of the stream table
+const STREAM_ENTRY_BYTES: usize = 16;
+
+/// which `ModelData` list a stream of a cached model holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CachedStream {
+    Vertices,
+    Texcoords,
+    Normals,
+    Tangents,
+    Colors,
+    Indices,
+    SmoothingGroups,
+    LineIndices,
+    PointIndices,
+    /// the `VertexAttribute` at this position in `ModelData::attributes`
+    Attribute(usize),
+}
+
+impl CachedStream {
+    /// the scalar type and number of components `encode_model` writes it with
+    fn encoding(self) -> (ScalarType, usize) {
+        match self {
+            CachedStream::Vertices | CachedStream::Normals | CachedStream::Colors => {
+                (ScalarType::F32, 3)
+            }
+            CachedStream::Texcoords => (ScalarType::F32, 2),
+            CachedStream::Tangents => (ScalarType::F32, 4),
+            CachedStream::Attribute(_) => (ScalarType::F32, 1),
+            CachedStream::Indices
+            | CachedStream::SmoothingGroups
+            | CachedStream::LineIndices
+            | CachedStream::PointIndices => (ScalarType::U32, 1),
+        }
+    }
+}
+
+/// the values of a stream, all little endian like wasm and WebGL buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    F32,
+    U32,
+}
+
+/// where one stream of a cached model lies, starting on a 4 byte boundary
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedStreamLayout {
+    stream: CachedStream,
+    scalar_type: ScalarType,
+    /// values per vertex (1 for indices and per-triangle values)
+    components: usize,
+    /// byte range of the values within the cached model
+    bytes: Range<usize>,
+}
+
+/**
+ * Cache key of an upload: a 64-bit FNV-1a hash of `CACHE_VERSION`, the name
+ * and contents of the model, and those of every other file in it, in name
+ * order
+ *
+ * The model is hashed apart from the other files, so another file with the
+ * same name can't take its place, and files with the same name are all kept.
+ * Unlike the hashers used for maps it doesn't depend on the platform or a
+ * random seed, so it stays the same from one visit to the next
+ */
+pub fn cache_key<'a>(
+    model: (&str, &[u8]),
+    files: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> u64 {
+    let mut files: Vec<(&str, &[u8])> = files.into_iter().collect();
+    files.sort_unstable();
+    let mut key = CacheKeyBuilder::new();
+    key.start_model(model.0, model.1.len() as u64);
+    key.update(model.1);
+    for (name, contents) in files {
+        key.start_file(name, contents.len() as u64);
+        key.update(contents);
+    }
+    key.finish()
+}
+
+/**
+ * Computes a `cache_key` from files that are read a slice at a time, so a
+ * large model doesn't have to be in memory all at once to be looked up
+ *
+ * The model has to be started first and the other files then in name order
+ * (by contents for equal names), each followed by all of its contents, for
+ * the key to match `cache_key`
+ */
+#[derive(Debug, Clone)]
+pub struct CacheKeyBuilder {
+    state: u64,
+}
+
+impl CacheKeyBuilder {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    /// what each entry is hashed as, so the model can't be mistaken for another file
+    const MODEL_TAG: u8 = 0;
+    const FILE_TAG: u8 = 1;
+
+    pub fn new() -> Self {
+        let mut key = Self {
+            state: Self::OFFSET_BASIS,
+        };
+        key.update(&CACHE_VERSION.to_le_bytes());
+        key
+    }
+
+    /// begins the model, `len` being the size of all of its contents
+    pub fn start_model(&mut self, name: &str, len: u64) {
+        self.start(Self::MODEL_TAG, name, len);
+    }
+
+    /// begins the next of the other files, `len` being the size of all of its contents
+    pub fn start_file(&mut self, name: &str, len: u64) {
+        self.start(Self::FILE_TAG, name, len);
+    }
+
+    fn start(&mut self, tag: u8, name: &str, len: u64) {
+        self.update(&[tag]);
+        // lengths keep the boundaries between names and contents unambiguous
+        self.update(&(name.len() as u64).to_le_bytes());
+        self.update(name.as_bytes());
+        self.update(&len.to_le_bytes());
+    }
+
+    /// hashes the next slice of the current file
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state = (self.state ^ byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Default for CacheKeyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Serializes `model` into the cached model format, tagged with `key`
+ *
+ * The layout is a 32 byte header (magic, version, key, payload length, CRC-32
+ * of the payload, stream count), a table of the vertex and index streams,
+ * the submeshes, materials and other metadata, and finally the streams
+ * themselves, starting on a 4 byte boundary
+ */
+pub fn encode_model(model: &ModelData, key: u64) -> Vec<u8> {
+    let mut streams: Vec<(CachedStream, StreamValues, usize)> = Vec::new();
+    streams.push((
+        CachedStream::Vertices,
+        StreamValues::F32(&model.vertices),
+        3,
+    ));
+    if let Some(texcoords) = &model.texcoords {
+        streams.push((CachedStream::Texcoords, StreamValues::F32(texcoords), 2));
+    }
+    if let Some(normals) = &model.normals {
+        streams.push((CachedStream::Normals, StreamValues::F32(normals), 3));
+    }
+    if let Some(tangents) = &model.tangents {
+        streams.push((CachedStream::Tangents, StreamValues::F32(tangents), 4));
+    }
+    if let Some(colors) = &model.colors {
+        streams.push((CachedStream::Colors, StreamValues::F32(colors), 3));
+    }
+    for (index, attribute) in model.attributes.iter().enumerate() {
+        let values = StreamValues::F32(&attribute.values);
+        streams.push((CachedStream::Attribute(index), values, 1));
+    }
+    streams.push((CachedStream::Indices, StreamValues::U32(&model.indices), 1));
+    let smoothing_groups = StreamValues::U32(&model.smoothing_groups);
+    streams.push((CachedStream::SmoothingGroups, smoothing_groups, 1));
+    let line_indices = StreamValues::U32(&model.line_indices);
+    streams.push((CachedStream::LineIndices, line_indices, 1));
+    let point_indices = StreamValues::U32(&model.point_indices);
+    streams.push((CachedStream::PointIndices, point_indices, 1));
+
+    let mut metadata = Writer::default();
+    metadata.write_metadata(model);
+    let table_end = HEADER_BYTES + streams.len() * STREAM_ENTRY_BYTES;
+    let streams_start = align(table_end + metadata.bytes.len());
+    let streams_len: usize = streams.iter().map(|(_, values, _)| values.len() * 4).sum();
+
+    let mut bytes = Vec::with_capacity(streams_start + streams_len);
+    bytes.extend_from_slice(&CACHE_MAGIC);
+    bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&key.to_le_bytes());
+    // payload length and checksum are filled in once the payload is written
+    bytes.resize(HEADER_BYTES - 4, 0);
+    bytes.extend_from_slice(&(streams.len() as u32).to_le_bytes());
+
+    let mut offset = streams_start;
+    for (stream, values, components) in &streams {
+        let (kind, attribute) = match stream {
+            CachedStream::Vertices => (0u8, 0u32),
+            CachedStream::Texcoords => (1, 0),
+            CachedStream::Normals => (2, 0),
+            CachedStream::Colors => (3, 0),
+            CachedStream::Indices => (4, 0),
+            CachedStream::SmoothingGroups => (5, 0),
+            CachedStream::LineIndices => (6, 0),
+            CachedStream::PointIndices => (7, 0),
+            CachedStream::Attribute(index) => (8, *index as u32),
+            CachedStream::Tangents => (9, 0),
+        };
+        bytes.push(kind);
+        bytes.push(match values {
+            StreamValues::F32(_) => 0,
+            StreamValues::U32(_) => 1,
+        });
+        bytes.extend_from_slice(&(*components as u16).to_le_bytes());
+        bytes.extend_from_slice(&attribute.to_le_bytes());
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        offset += values.len() * 4;
+    }
+    bytes.extend_from_slice(&metadata.bytes);
+    bytes.resize(streams_start, 0);
+    for (_, values, _) in &streams {
+        match values {
+            StreamValues::F32(values) => {
+                bytes.extend(values.iter().flat_map(|value| value.to_le_bytes()))
+            }
+            StreamValues::U32(values) => {
+                bytes.extend(values.iter().flat_map(|value| value.to_le_bytes()))
+            }
+        }
+    }
+
+    let payload_len = (bytes.len() - HEADER_BYTES) as u64;
+    let checksum = crc32fast::hash(&bytes[HEADER_BYTES..]);
+    bytes[16..24].copy_from_slice(&payload_len.to_le_bytes());
+    bytes[24..28].copy_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+/// the values of a stream being encoded
+enum StreamValues<'a> {
+    F32(&'a [f32]),
+    U32(&'a [u32]),
+}
+
+impl StreamValues<'_> {
+    fn len(&self) -> usize {
+        match self {
+            StreamValues::F32(values) => values.len(),
+            StreamValues::U32(values) => values.len(),
+        }
+    }
+}
+
+/**
+ * Checks the header and checksum of a cached model and returns where each of
+ * its streams lies
+ */
+fn read_cache_layout(bytes: &[u8]) -> Result<Vec<CachedStreamLayout>, ObjError> {
+    let header = read_header(bytes)?;
+    let payload = &bytes[HEADER_BYTES..];
+    if crc32fast::hash(payload) != header.checksum {
+        return Err(cache_error("checksum mismatch"));
+    }
+
+    let mut reader = Reader::new(bytes, HEADER_BYTES);
+    let mut layout =
+        Vec::with_capacity(header.stream_count.min(payload.len() / STREAM_ENTRY_BYTES));
+    for _ in 0..header.stream_count {
+        let kind = reader.u8()?;
+        let scalar_type = match reader.u8()? {
+            0 => ScalarType::F32,
+            1 => ScalarType::U32,
+            other => return Err(cache_error(format!("unknown scalar type {}", other))),
+        };
+        let components = reader.u16()? as usize;
+        let attribute = reader.u32()? as usize;
+        let offset = reader.u32()? as usize;
+        let count = reader.u32()? as usize;
+        let stream = match kind {
+            0 => CachedStream::Vertices,
+            1 => CachedStream::Texcoords,
+            2 => CachedStream::Normals,
+            3 => CachedStream::Colors,
+            4 => CachedStream::Indices,
+            5 => CachedStream::SmoothingGroups,
+            6 => CachedStream::LineIndices,
+            7 => CachedStream::PointIndices,
+            8 => CachedStream::Attribute(attribute),
+            9 => CachedStream::Tangents,
+            other => return Err(cache_error(format!("unknown stream {}", other))),
+        };
+        if (scalar_type, components) != stream.encoding() {
+            return Err(cache_error(format!(
+                "{:?} stream stored as {} {:?} per vertex",
+                stream, components, scalar_type
+            )));
+        }
+        if layout
+            .iter()
+            .any(|other: &CachedStreamLayout| other.stream == stream)
+        {
+            return Err(cache_error(format!("{:?} stream appears twice", stream)));
+        }
+        let end = count
+            .checked_mul(4)
+            .and_then(|len| offset.checked_add(len))
+            .filter(|&end| end <= bytes.len() && offset.is_multiple_of(4))
+            .ok_or_else(|| cache_error("a stream lies outside the file"))?;
+        layout.push(CachedStreamLayout {
+            stream,
+            scalar_type,
+            components,
+            bytes: offset..end,
+        });
+    }
+    Ok(layout)
+}
+
+/// reads a model written by `encode_model`, checking its version and checksum
+pub fn decode_model(bytes: &[u8]) -> Result<ModelData, ObjError> {
+    let layout = read_cache_layout(bytes)?;
+    let metadata_start = HEADER_BYTES + layout.len() * STREAM_ENTRY_BYTES;
+    let mut model = Reader::new(bytes, metadata_start).read_metadata()?;
+
+    for stream in layout {
+        let values = &bytes[stream.bytes];
+        match stream.stream {
+            CachedStream::Vertices => model.vertices = read_f32s(values),
+            CachedStream::Texcoords => model.texcoords = Some(read_f32s(values)),
+            CachedStream::Normals => model.normals = Some(read_f32s(values)),
+            CachedStream::Tangents => model.tangents = Some(read_f32s(values)),
+            CachedStream::Colors => model.colors = Some(read_f32s(values)),
+            CachedStream::Indices => model.indices = read_u32s(values),
+            CachedStream::SmoothingGroups => model.smoothing_groups = read_u32s(values),
+            CachedStream::LineIndices => model.line_indices = read_u32s(values),
+            CachedStream::PointIndices => model.point_indices = read_u32s(values),
+            CachedStream::Attribute(index) => {
+                let attribute = model
+                    .attributes
+                    .get_mut(index)
+                    .ok_or_else(|| cache_error("values for an attribute without a name"))?;
+                attribute.values = read_f32s(values);
+            }
+        }
+    }
+    check_decoded_model(&model)?;
+    Ok(model)
+}
+
+/**
+ * Serializes a chain of levels of detail, the full model first: the number of
+ * levels, then each level's error followed by the level as `encode_model`
+ * writes it. Materials and embedded files are only on the full model, so
+ * they are stored once
+ */
+pub fn encode_lods(levels: &[LodLevel], key: u64) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.u32(levels.len() as u32);
+    for level in levels {
+        writer.f32s(&[level.error]);
+        writer.bytes(&encode_model(&level.model, key));
+    }
+    writer.bytes
+}
+
+/// reads a chain written by `encode_lods`, checking every level like `decode_model`
+pub fn decode_lods(bytes: &[u8]) -> Result<Vec<LodLevel>, ObjError> {
+    let mut reader = Reader::new(bytes, 0);
+    let count = reader.count(4 + 8 + HEADER_BYTES)?;
+    if count == 0 {
+        return Err(cache_error("no levels of detail"));
+    }
+    let mut levels = Vec::with_capacity(count);
+    for _ in 0..count {
+        let [error] = reader.f32s()?;
+        let model = decode_model(reader.bytes()?)?;
+        levels.push(LodLevel { model, error });
+    }
+    if reader.offset != bytes.len() {
+        return Err(cache_error("trailing bytes after the last level"));
+    }
+    Ok(levels)
+}
+
+/**
+ * Checks that the streams of a decoded model fit together, so that a cached
+ * model that passed the checksum but was written wrongly can't index out of
+ * bounds later on
+ */
+fn check_decoded_model(model: &ModelData) -> Result<(), ObjError> {
+    if !model.vertices.len().is_multiple_of(3) {
+        return Err(cache_error("positions don't come in threes"));
+    }
+    let vertex_count = model.vertex_count();
+    let streams = [
+        ("texture coordinates", &model.texcoords, 2),
+        ("normals", &model.normals, 3),
+        ("tangents", &model.tangents, 4),
+        ("colors", &model.colors, 3),
+    ];
+    for (name, values, components) in streams {
+        if values
+            .as_ref()
+            .is_some_and(|values| values.len() != vertex_count * components)
+        {
+            return Err(cache_error(format!("{} don't match the positions", name)));
+        }
+    }
+    if let Some(attribute) = model
+        .attributes
+        .iter()
+        .find(|attribute| attribute.values.len() != vertex_count)
+    {
+        return Err(cache_error(format!(
+            "attribute `{}` doesn't match the positions",
+            attribute.name
+        )));
+    }
+
+    if !model.indices.len().is_multiple_of(3) || !model.line_indices.len().is_multiple_of(2) {
+        return Err(cache_error("an incomplete triangle or line"));
+    }
+    let out_of_range = model
+        .indices
+        .iter()
+        .chain(&model.line_indices)
+        .chain(&model.point_indices)
+        .find(|&&index| index as usize >= vertex_count);
+    if let Some(index) = out_of_range {
+        return Err(cache_error(format!(
+            "vertex index {} is out of range, only {} vertices",
+            index, vertex_count
+        )));
+    }
+    if let Some(submesh) = model.submeshes.iter().find(|submesh| {
+        submesh.index_range.start > submesh.index_range.end
+            || submesh.index_range.end > model.indices.len()
+    }) {
+        return Err(cache_error(format!(
+            "submesh `{}` lies outside the indices",
+            submesh.name
+        )));
+    }
+    Ok(())
+}
+
+struct Header {
+    checksum: u32,
+    stream_count: usize,
+}
+
+fn read_header(bytes: &[u8]) -> Result<Header, ObjError> {
+    if bytes.len() < HEADER_BYTES {
+        return Err(ObjError::Truncated {
+            expected: HEADER_BYTES,
+            actual: bytes.len(),
+        });
+    }
+    let mut reader = Reader::new(bytes, 0);
+    if reader.take(4)? != CACHE_MAGIC {
+        return Err(cache_error("not a cached model"));
+    }
+    let version = reader.u32()?;
+    if version != CACHE_VERSION {
+        return Err(cache_error(format!(
+            "version {} can't be read, expected {}",
+            version, CACHE_VERSION
+        )));
+    }
+    // the key is only for whoever stored the entry to look it up by
+    reader.u64()?;
+    let payload_len = reader.u64()?;
+    let checksum = reader.u32()?;
+    let stream_count = reader.u32()? as usize;
+    let expected = (HEADER_BYTES as u64).saturating_add(payload_len);
+    if expected != bytes.len() as u64 {
+        return Err(ObjError::Truncated {
+            expected: expected.try_into().unwrap_or(usize::MAX),
+            actual: bytes.len(),
+        });
+    }
+    Ok(Header {
+        checksum,
+        stream_count,
+    })
+}
+
+fn cache_error(message: impl Into<String>) -> ObjError {
+    ObjError::Cache {
+        message: message.into(),
+    }
+}
+
+fn align(len: usize) -> usize {
+    len.next_multiple_of(4)
+}
+
+fn read_f32s(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes(value.try_into().expect("chunks are 4 bytes")))
+        .collect()
+}
+
+fn read_u32s(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|value| u32::from_le_bytes(value.try_into().expect("chunks are 4 bytes")))
+        .collect()
+}
+
+/// builds the metadata section
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn optional_str(&mut self, value: &Option<String>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn write_metadata(&mut self, model: &ModelData) {
+        self.u32(model.attributes.len() as u32);
+        for attribute in &model.attributes {
+            self.str(&attribute.name);
+        }
+
+        self.u32(model.submeshes.len() as u32);
+        for submesh in &model.submeshes {
+            self.str(&submesh.name);
+            self.optional_str(&submesh.material);
+            self.u64(submesh.index_range.start as u64);
+            self.u64(submesh.index_range.end as u64);
+            self.u8(submesh.visible as u8);
+        }
+
+        self.u32(model.materials.len() as u32);
+        for material in &model.materials {
+            self.write_material(material);
+        }
+
+        self.u32(model.embedded_files.len() as u32);
+        for file in &model.embedded_files {
+            self.str(&file.path);
+            self.optional_str(&file.mime_type);
+            self.bytes(&file.contents);
+        }
+
+        self.u32(model.missing_files.len() as u32);
+        for path in &model.missing_files {
+            self.str(path);
+        }
+
+        self.u32(model.untriangulated_faces.len() as u32);
+        for &line in &model.untriangulated_faces {
+            self.u64(line as u64);
+        }
+    }
+
+    fn write_material(&mut self, material: &Material) {
+        self.str(&material.name);
+        self.f32s(&material.ambient);
+        self.f32s(&material.diffuse);
+        self.f32s(&material.specular);
+        self.f32s(&[
+            material.shininess,
+            material.dissolve,
+            material.optical_density,
+        ]);
+        match material.illumination_model {
+            Some(model) => {
+                self.u8(1);
+                self.u8(model);
+            }
+            None => self.u8(0),
+        }
+        for texture in [
+            &material.ambient_texture,
+            &material.diffuse_texture,
+            &material.specular_texture,
+            &material.normal_texture,
+            &material.shininess_texture,
+            &material.dissolve_texture,
+        ] {
+            self.optional_str(texture);
+        }
+        // sorted, so the same material always encodes to the same bytes
+        let unknown_params: BTreeMap<_, _> = material.unknown_params.iter().collect();
+        self.u32(unknown_params.len() as u32);
+        for (key, value) in unknown_params {
+            self.str(key);
+            self.str(value);
+        }
+
+        match &material.pbr {
+            Some(pbr) => {
+                self.u8(1);
+                self.f32s(&pbr.base_color);
+                self.f32s(&[pbr.metallic, pbr.roughness]);
+                self.f32s(&pbr.emissive);
+                self.optional_str(&pbr.metallic_roughness_texture);
+                self.optional_str(&pbr.occlusion_texture);
+                self.optional_str(&pbr.emissive_texture);
+                self.u8(pbr.double_sided as u8);
+            }
+            None => self.u8(0),
+        }
+    }
+}
+
+/// reads the header, stream table and metadata section
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ObjError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(ObjError::Truncated {
+                expected: self.offset.saturating_add(len),
+                actual: self.bytes.len(),
+            })?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ObjError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ObjError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ObjError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ObjError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ObjError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, ObjError> {
+        self.u64()?
+            .try_into()
+            .map_err(|_| cache_error("a size is too large for this platform"))
+    }
+
+    fn f32s<const N: usize>(&mut self) -> Result<[f32; N], ObjError> {
+        let mut values = [0.0; N];
+        for value in &mut values {
+            *value = f32::from_le_bytes(self.array()?);
+        }
+        Ok(values)
+    }
+
+    fn bool(&mut self) -> Result<bool, ObjError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(cache_error(format!("{} is not a flag", other))),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ObjError> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, ObjError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| cache_error("a name isn't valid UTF-8"))
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, ObjError> {
+        Ok(if self.bool()? {
+            Some(self.string()?)
+        } else {
+            None
+        })
+    }
+
+    /// a count of entries that each take at least `min_bytes`, checked
+    /// against what is left so a corrupt count can't cause a huge allocation
+    fn count(&mut self, min_bytes: usize) -> Result<usize, ObjError> {
+        let count = self.u32()? as usize;
+        let remaining = self.bytes.len() - self.offset;
+        if count.saturating_mul(min_bytes) > remaining {
+            return Err(ObjError::Truncated {
+                expected: self.offset.saturating_add(count.saturating_mul(min_bytes)),
+                actual: self.bytes.len(),
+            });
+        }
+        Ok(count)
+    }
+
+    fn read_metadata(&mut self) -> Result<ModelData, ObjError> {
+        let mut model = ModelData::default();
+
+        for _ in 0..self.count(8)? {
+            model.attributes.push(VertexAttribute {
+                name: self.string()?,
+                values: Vec::new(),
+            });
+        }
+
+        for _ in 0..self.count(26)? {
+            let name = self.string()?;
+            let material = self.optional_string()?;
+            let start = self.usize()?;
+            let end = self.usize()?;
+            model.submeshes.push(Submesh {
+                name,
+                material,
+                index_range: start..end,
+                visible: self.bool()?,
+            });
+        }
+
+        for _ in 0..self.count(57)? {
+            let material = self.read_material()?;
+            model.materials.push(material);
+        }
+
+        for _ in 0..self.count(17)? {
+            model.embedded_files.push(EmbeddedFile {
+                path: self.string()?,
+                mime_type: self.optional_string()?,
+                contents: self.bytes()?.to_vec(),
+            });
+        }
+
+        for _ in 0..self.count(8)? {
+            model.missing_files.push(self.string()?);
+        }
+
+        for _ in 0..self.count(8)? {
+            model.untriangulated_faces.push(self.usize()?);
+        }
+        Ok(model)
+    }
+
+    fn read_material(&mut self) -> Result<Material, ObjError> {
+        let name = self.string()?;
+        let ambient = self.f32s()?;
+        let diffuse = self.f32s()?;
+        let specular = self.f32s()?;
+        let [shininess, dissolve, optical_density] = self.f32s()?;
+        let illumination_model = if self.bool()? { Some(self.u8()?) } else { None };
+        let ambient_texture = self.optional_string()?;
+        let diffuse_texture = self.optional_string()?;
+        let specular_texture = self.optional_string()?;
+        let normal_texture = self.optional_string()?;
+        let shininess_texture = self.optional_string()?;
+        let dissolve_texture = self.optional_string()?;
+        let mut unknown_params = AHashMap::new();
+        for _ in 0..self.count(16)? {
+            let key = self.string()?;
+            unknown_params.insert(key, self.string()?);
+        }
+
+        let pbr = if self.bool()? {
+            let base_color = self.f32s()?;
+            let [metallic, roughness] = self.f32s()?;
+            Some(PbrMaterial {
+                base_color,
+                metallic,
+                roughness,
+                emissive: self.f32s()?,
+                metallic_roughness_texture: self.optional_string()?,
+                occlusion_texture: self.optional_string()?,
+                emissive_texture: self.optional_string()?,
+                double_sided: self.bool()?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Material {
+            name,
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            dissolve,
+            optical_density,
+            illumination_model,
+            ambient_texture,
+            diffuse_texture,
+            specular_texture,
+            normal_texture,
+            shininess_texture,
+            dissolve_texture,
+            unknown_params,
+            pbr,
+        })
+    }
+}
@@ -0,0 +1,196 @@
+use std::{error::Error, fmt, io};
+
+use super::Limit;
+
+/**
+ * Everything that can go wrong while parsing an uploaded model file
+ *
+ * Parse failures carry the 1-based line and column of the offending token so
+ * that a malformed upload can be reported back to the user instead of
+ * aborting the wasm module
+ */
+#[derive(Debug)]
+pub enum ObjError {
+    /// a statement ended before all of its required coordinates were read
+    MissingCoordinate {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// a coordinate could not be parsed into an f32
+    BadFloat {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// a face vertex reference could not be parsed into an index
+    BadIndex {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// a face vertex reference resolved to an element that does not exist
+    IndexOutOfRange {
+        line: usize,
+        column: usize,
+        token: String,
+        /// which kind of element the index refers to, e.g. "positions"
+        element: &'static str,
+        /// how many of that element had been declared at this point
+        count: usize,
+    },
+    /// a line contained bytes that are not valid UTF-8
+    InvalidUtf8 {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// a keyword other than the one the format requires at this point
+    UnexpectedToken {
+        line: usize,
+        column: usize,
+        token: String,
+        expected: &'static str,
+    },
+    /// an element of a file without lines (e.g. a PLY face) is invalid
+    BadElement {
+        element: String,
+        /// 0-based position among the elements of its kind
+        index: usize,
+        message: String,
+    },
+    /// an OBJ free-form curve or surface can't be evaluated, e.g. its
+    /// parameters don't match its control points
+    Freeform { line: usize, message: String },
+    /// a binary file is shorter than its header says it should be
+    Truncated { expected: usize, actual: usize },
+    /// a referenced file path is absolute or escapes the upload root
+    UnsafePath { path: String },
+    /// an MTL library could not be parsed
+    Material { path: String, message: String },
+    /// a file the model needs wasn't part of the upload
+    MissingFile { path: String },
+    /// a glTF document or GLB container is malformed
+    Gltf { message: String },
+    /// a zip or gzip upload could not be unpacked
+    Archive { message: String },
+    /// a cached model is corrupt or was written by another version
+    Cache { message: String },
+    /// the file went over one of the `LoadLimits` it was loaded with
+    LimitExceeded { limit: Limit, value: u64, max: u64 },
+    /// the load was cancelled through its `CancelToken`
+    Cancelled,
+    /// the underlying reader failed
+    Io(io::Error),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::MissingCoordinate {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "line {}, column {}: `{}` statement is missing a coordinate",
+                line, column, token
+            ),
+            ObjError::BadFloat {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "line {}, column {}: `{}` is not a valid number",
+                line, column, token
+            ),
+            ObjError::BadIndex {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "line {}, column {}: `{}` is not a valid vertex index",
+                line, column, token
+            ),
+            ObjError::IndexOutOfRange {
+                line,
+                column,
+                token,
+                element,
+                count,
+            } => write!(
+                f,
+                "line {}, column {}: `{}` is out of range, only {} {} declared so far",
+                line, column, token, count, element
+            ),
+            ObjError::InvalidUtf8 {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "line {}, column {}: invalid UTF-8 in `{}`",
+                line, column, token
+            ),
+            ObjError::UnexpectedToken {
+                line,
+                column,
+                token,
+                expected,
+            } => write!(
+                f,
+                "line {}, column {}: expected `{}`, found `{}`",
+                line, column, expected, token
+            ),
+            ObjError::BadElement {
+                element,
+                index,
+                message,
+            } => write!(f, "{} {}: {}", element, index, message),
+            ObjError::Freeform { line, message } => write!(f, "line {}: {}", line, message),
+            ObjError::Truncated { expected, actual } => write!(
+                f,
+                "file is truncated: expected {} bytes, found {}",
+                expected, actual
+            ),
+            ObjError::UnsafePath { path } => {
+                write!(f, "refusing to load `{}`: path is outside the upload", path)
+            }
+            ObjError::Material { path, message } => {
+                write!(
+                    f,
+                    "failed to parse material library `{}`: {}",
+                    path, message
+                )
+            }
+            ObjError::MissingFile { path } => {
+                write!(f, "`{}` is needed by the model but wasn't uploaded", path)
+            }
+            ObjError::Gltf { message } => write!(f, "invalid glTF: {}", message),
+            ObjError::Archive { message } => write!(f, "invalid archive: {}", message),
+            ObjError::Cache { message } => write!(f, "invalid cached model: {}", message),
+            ObjError::LimitExceeded { limit, value, max } => {
+                write!(f, "{} of {} is over the limit of {}", limit, value, max)
+            }
+            ObjError::Cancelled => write!(f, "loading was cancelled"),
+            ObjError::Io(e) => write!(f, "failed to read model file: {}", e),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
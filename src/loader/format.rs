@@ -0,0 +1,74 @@
+use super::{gltf, ply, stl, FileResolver, LoadLimits, ModelData, ObjError, ObjParser};
+
+/// the model file formats an upload can contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFormat {
+    Obj,
+    Stl,
+    Ply,
+    Gltf,
+}
+
+impl ModelFormat {
+    /// the format a file name's extension claims, if it is a model at all
+    pub fn from_extension(file_name: &str) -> Option<Self> {
+        let extension = file_name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => Some(ModelFormat::Obj),
+            "stl" => Some(ModelFormat::Stl),
+            "ply" => Some(ModelFormat::Ply),
+            "gltf" | "glb" => Some(ModelFormat::Gltf),
+            _ => None,
+        }
+    }
+
+    /**
+     * The format identified by the first bytes of a file
+     *
+     * `head` has to include at least the first 84 bytes to recognise binary
+     * STL, and `size` is the length of the whole file. OBJ and glTF JSON have
+     * no signature, so they are never recognised here
+     */
+    pub fn from_magic(head: &[u8], size: u64) -> Option<Self> {
+        if gltf::is_glb(head) {
+            return Some(ModelFormat::Gltf);
+        }
+        if ply::is_ply(head) {
+            return Some(ModelFormat::Ply);
+        }
+        let binary_stl = stl::binary_len(head).map(|len| len as u64) == Some(size);
+        if binary_stl || stl::is_ascii_stl(head) {
+            return Some(ModelFormat::Stl);
+        }
+        None
+    }
+
+    /// magic bytes win over the extension, anything unrecognised is read as OBJ
+    pub fn detect(file_name: &str, head: &[u8], size: u64) -> Self {
+        Self::from_magic(head, size)
+            .or_else(|| Self::from_extension(file_name))
+            .unwrap_or(ModelFormat::Obj)
+    }
+
+    /// loads a complete file in this format
+    pub fn load(
+        self,
+        bytes: &[u8],
+        resolver: &dyn FileResolver,
+        limits: &LoadLimits,
+    ) -> Result<ModelData, ObjError> {
+        match self {
+            ModelFormat::Obj => {
+                // the whole file is at hand, so it goes to the parser in one piece
+                let mut parser = ObjParser::new(resolver)
+                    .with_limits(*limits)
+                    .with_size_hint(bytes.len() as u64);
+                parser.push(bytes)?;
+                parser.finish()
+            }
+            ModelFormat::Stl => stl::load_stl_with_limits(bytes, limits),
+            ModelFormat::Ply => ply::load_ply_with_limits(bytes, limits),
+            ModelFormat::Gltf => gltf::load_gltf_with_limits(bytes, resolver, limits),
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use glam::{Vec3, Vec4};
+
+use super::{LoadLimits, ModelData, ObjError, Submesh};
+
+/// how finely free-form curves and surfaces are turned into lines and triangles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tessellation {
+    /// segments each knot span (each Bezier segment) is divided into, along
+    /// a curve and along both directions of a surface
+    pub segments_per_span: usize,
+}
+
+impl Default for Tessellation {
+    fn default() -> Self {
+        Self {
+            segments_per_span: 8,
+        }
+    }
+}
+
+/// the bases of `cstype` that can be evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Basis {
+    Bezier,
+    BSpline,
+}
+
+/// which of a surface's parameters a `parm` statement is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Direction {
+    U = 0,
+    V = 1,
+}
+
+/// a `curv` or `surf` statement and the body statements read since
+struct Element {
+    line_number: usize,
+    is_surface: bool,
+    /// `None` for a `cstype` that isn't supported, the element is skipped
+    basis: Option<Basis>,
+    degree: [usize; 2],
+    /// parameter range in u and, for surfaces, v
+    range: [[f32; 2]; 2],
+    /// homogeneous (wx, wy, wz, w), u varying fastest for surfaces
+    control_points: Vec<Vec4>,
+    parameters: [Vec<f32>; 2],
+    smoothing_group: u32,
+    name: String,
+    material: Option<String>,
+}
+
+/**
+ * Reads the free-form statements of an OBJ file (`cstype`, `deg`, `curv`,
+ * `surf`, `parm`, `end`) and tessellates each curve and surface once its
+ * `end` is reached
+ *
+ * Bezier and B-spline bases are evaluated, either of them rational using the
+ * `w` of the control vertices. Trimming loops (`trim`, `hole`) are ignored,
+ * surfaces are tessellated over their whole parameter range
+ */
+#[derive(Default)]
+pub(super) struct FreeformBuilder {
+    basis: Option<Basis>,
+    rational: bool,
+    degree: [usize; 2],
+    element: Option<Element>,
+    geometry: FreeformGeometry,
+}
+
+/// the tessellated curves and surfaces of a file, merged into its model at the end
+#[derive(Default)]
+struct FreeformGeometry {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    texcoords: Vec<[f32; 2]>,
+    triangles: Vec<[u32; 3]>,
+    smoothing_groups: Vec<u32>,
+    lines: Vec<[u32; 2]>,
+    /// name, material and triangle range of each surface
+    surfaces: Vec<(String, Option<String>, std::ops::Range<usize>)>,
+    /// line numbers of elements with a basis that can't be evaluated
+    skipped: Vec<usize>,
+}
+
+fn freeform_error(line: usize, message: impl Into<String>) -> ObjError {
+    ObjError::Freeform {
+        line,
+        message: message.into(),
+    }
+}
+
+impl FreeformBuilder {
+    /// `cstype [rat] type`; bases other than Bezier and B-spline are skipped
+    pub(super) fn set_type(&mut self, rational: bool, basis: &str) {
+        self.rational = rational;
+        self.basis = match basis {
+            "bezier" => Some(Basis::Bezier),
+            "bspline" => Some(Basis::BSpline),
+            _ => None,
+        };
+    }
+
+    /// `deg degu [degv]`
+    pub(super) fn set_degree(&mut self, degree_u: usize, degree_v: Option<usize>) {
+        self.degree = [degree_u, degree_v.unwrap_or(degree_u)];
+    }
+
+    /**
+     * Starts a `curv` or `surf` element
+     *
+     * `control_points` are (x, y, z, w), `w` only counts for a rational
+     * `cstype`. The name and material are those of the submesh the element is
+     * read into
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn start(
+        &mut self,
+        line_number: usize,
+        is_surface: bool,
+        range: [[f32; 2]; 2],
+        control_points: &[[f32; 4]],
+        smoothing_group: u32,
+        name: &str,
+        material: Option<&str>,
+    ) -> Result<(), ObjError> {
+        if self.element.is_some() {
+            return Err(freeform_error(
+                line_number,
+                "the previous curve or surface has no `end`",
+            ));
+        }
+        let rational = self.rational;
+        let control_points = control_points
+            .iter()
+            .map(|&[x, y, z, w]| {
+                let w = if rational { w } else { 1.0 };
+                Vec4::new(x * w, y * w, z * w, w)
+            })
+            .collect();
+        self.element = Some(Element {
+            line_number,
+            is_surface,
+            basis: self.basis,
+            degree: self.degree,
+            range,
+            control_points,
+            parameters: [Vec::new(), Vec::new()],
+            smoothing_group,
+            name: name.to_string(),
+            material: material.map(str::to_string),
+        });
+        Ok(())
+    }
+
+    /// `parm u|v p1 p2 ...` of the current element
+    pub(super) fn set_parameters(
+        &mut self,
+        line_number: usize,
+        direction: Direction,
+        values: Vec<f32>,
+    ) -> Result<(), ObjError> {
+        let element = self
+            .element
+            .as_mut()
+            .ok_or_else(|| freeform_error(line_number, "`parm` outside of a curve or surface"))?;
+        if values.iter().any(|value| !value.is_finite())
+            || values.windows(2).any(|pair| pair[0] > pair[1])
+        {
+            return Err(freeform_error(
+                line_number,
+                "parameters have to be increasing numbers",
+            ));
+        }
+        element.parameters[direction as usize] = values;
+        Ok(())
+    }
+
+    /// `end`: tessellates the current element
+    pub(super) fn end(
+        &mut self,
+        line_number: usize,
+        tessellation: &Tessellation,
+        limits: &LoadLimits,
+    ) -> Result<(), ObjError> {
+        let element = self
+            .element
+            .take()
+            .ok_or_else(|| freeform_error(line_number, "`end` without a curve or surface"))?;
+        let basis = match element.basis {
+            Some(basis) => basis,
+            None => {
+                self.geometry.skipped.push(element.line_number);
+                return Ok(());
+            }
+        };
+        let segments = tessellation.segments_per_span.max(1);
+        let line = element.line_number;
+        let directions = if element.is_surface { 2 } else { 1 };
+
+        // the control points have to fill the grid the parameters describe,
+        // checked before any knot vector is built from them
+        let mut counts = [1; 2];
+        for (direction, count) in counts.iter_mut().enumerate().take(directions) {
+            *count = control_point_count(&element, basis, direction)?;
+        }
+        let expected = counts[0].checked_mul(counts[1]);
+        if expected != Some(element.control_points.len()) {
+            return Err(freeform_error(
+                line,
+                format!(
+                    "expected {} control points for the parameters, found {}",
+                    counts[0].saturating_mul(counts[1]),
+                    element.control_points.len()
+                ),
+            ));
+        }
+
+        let mut knot_vectors = Vec::with_capacity(directions);
+        let mut samples = Vec::with_capacity(directions);
+        for (direction, &count) in counts.iter().enumerate().take(directions) {
+            let degree = element.degree[direction];
+            let knots = match basis {
+                Basis::BSpline => element.parameters[direction].clone(),
+                Basis::Bezier if element.parameters[direction].is_empty() => {
+                    // segments one apart, only curves get here
+                    let breaks: Vec<f32> = (0..=(count - 1) / degree).map(|b| b as f32).collect();
+                    bezier_knots(&breaks, degree)
+                }
+                Basis::Bezier => bezier_knots(&element.parameters[direction], degree),
+            };
+            samples.push(
+                sample_parameters(&knots, degree, element.range[direction], segments, limits)
+                    .ok_or_else(|| {
+                        freeform_error(line, "the parameter range is outside the curve")
+                    })??,
+            );
+            knot_vectors.push(knots);
+        }
+
+        let geometry = &mut self.geometry;
+        let first_vertex = geometry.positions.len();
+        let new_vertices = samples
+            .iter()
+            .fold(1usize, |total, samples| total.saturating_mul(samples.len()));
+        limits.check_vertices(first_vertex.saturating_add(new_vertices))?;
+
+        if !element.is_surface {
+            let degree = element.degree[0];
+            let u_samples = &samples[0];
+            for (i, &u) in u_samples.iter().enumerate() {
+                let point = de_boor(&element.control_points, &knot_vectors[0], degree, u);
+                geometry.positions.push(point);
+                geometry.normals.push(Vec3::ZERO);
+                geometry.texcoords.push([normalized(u, u_samples), 0.0]);
+                if i > 0 {
+                    let vertex = (first_vertex + i) as u32;
+                    geometry.lines.push([vertex - 1, vertex]);
+                }
+            }
+            return Ok(());
+        }
+
+        let [degree_u, degree_v] = element.degree;
+        let (u_samples, v_samples) = (&samples[0], &samples[1]);
+        let (count_u, count_v) = (counts[0], counts[1]);
+        limits.check_faces(
+            geometry.triangles.len() + 2 * (u_samples.len() - 1) * (v_samples.len() - 1),
+        )?;
+
+        // each column of control points collapses to one point per v sample,
+        // those are then evaluated along u
+        let mut column = Vec::with_capacity(count_v);
+        let mut row = Vec::with_capacity(count_u);
+        let mut grid = Vec::with_capacity(new_vertices);
+        for &v in v_samples {
+            row.clear();
+            for i in 0..count_u {
+                column.clear();
+                column.extend((0..count_v).map(|j| element.control_points[i + j * count_u]));
+                row.push(de_boor_homogeneous(&column, &knot_vectors[1], degree_v, v));
+            }
+            for &u in u_samples {
+                let point = de_boor_homogeneous(&row, &knot_vectors[0], degree_u, u);
+                grid.push(project(point));
+            }
+        }
+
+        let width = u_samples.len();
+        let height = v_samples.len();
+        let at = |i: usize, j: usize| grid[i + j * width];
+        for j in 0..height {
+            for i in 0..width {
+                // central differences inside the grid, one-sided on its edges
+                let du = at((i + 1).min(width - 1), j) - at(i.saturating_sub(1), j);
+                let dv = at(i, (j + 1).min(height - 1)) - at(i, j.saturating_sub(1));
+                geometry.positions.push(at(i, j));
+                // left as zero at poles, for `generate_normals` to fill in
+                geometry.normals.push(du.cross(dv).normalize_or_zero());
+                geometry.texcoords.push([
+                    normalized(u_samples[i], u_samples),
+                    normalized(v_samples[j], v_samples),
+                ]);
+            }
+        }
+
+        let first_triangle = geometry.triangles.len();
+        let vertex = |i: usize, j: usize| (first_vertex + i + j * width) as u32;
+        for j in 0..height - 1 {
+            for i in 0..width - 1 {
+                let corners = [
+                    vertex(i, j),
+                    vertex(i + 1, j),
+                    vertex(i + 1, j + 1),
+                    vertex(i, j + 1),
+                ];
+                geometry
+                    .triangles
+                    .push([corners[0], corners[1], corners[2]]);
+                geometry
+                    .triangles
+                    .push([corners[0], corners[2], corners[3]]);
+            }
+        }
+        geometry
+            .smoothing_groups
+            .resize(geometry.triangles.len(), element.smoothing_group);
+        geometry.surfaces.push((
+            element.name,
+            element.material,
+            first_triangle..geometry.triangles.len(),
+        ));
+        Ok(())
+    }
+
+    /**
+     * Appends the tessellated geometry to `model`
+     *
+     * Surfaces become submeshes of their own after those of the faces, curves
+     * are added to the line primitives. Elements with an unsupported basis are
+     * reported through `untriangulated_faces`
+     */
+    pub(super) fn finish(self, model: &mut ModelData, limits: &LoadLimits) -> Result<(), ObjError> {
+        if let Some(element) = self.element {
+            return Err(freeform_error(
+                element.line_number,
+                "the file ends before the `end` of this curve or surface",
+            ));
+        }
+        let geometry = self.geometry;
+        model.untriangulated_faces.extend(geometry.skipped);
+        if geometry.positions.is_empty() {
+            return Ok(());
+        }
+
+        let first_vertex = model.vertex_count();
+        let new_vertices = geometry.positions.len();
+        limits.check_vertices(first_vertex + new_vertices)?;
+        limits.check_faces(model.indices.len() / 3 + geometry.triangles.len())?;
+        model
+            .vertices
+            .extend(geometry.positions.iter().flat_map(|p| p.to_array()));
+        model
+            .normals
+            .get_or_insert_with(|| vec![0.0; first_vertex * 3])
+            .extend(geometry.normals.iter().flat_map(|n| n.to_array()));
+        // surfaces come with texture coordinates of their own
+        if model.texcoords.is_some() || !geometry.surfaces.is_empty() {
+            model
+                .texcoords
+                .get_or_insert_with(|| vec![0.0; first_vertex * 2])
+                .extend(geometry.texcoords.iter().flatten());
+        }
+        if let Some(colors) = &mut model.colors {
+            colors.resize(colors.len() + new_vertices * 3, 1.0);
+        }
+        for attribute in &mut model.attributes {
+            attribute
+                .values
+                .resize(attribute.values.len() + new_vertices, 0.0);
+        }
+
+        let offset = first_vertex as u32;
+        let first_index = model.indices.len();
+        model.indices.extend(
+            geometry
+                .triangles
+                .iter()
+                .flatten()
+                .map(|vertex| vertex + offset),
+        );
+        model.smoothing_groups.extend(geometry.smoothing_groups);
+        model.line_indices.extend(
+            geometry
+                .lines
+                .iter()
+                .flatten()
+                .map(|vertex| vertex + offset),
+        );
+        for (name, material, triangles) in geometry.surfaces {
+            model.submeshes.push(Submesh {
+                name,
+                material,
+                index_range: first_index + triangles.start * 3..first_index + triangles.end * 3,
+                visible: true,
+            });
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Number of control points along `direction` that the degree and `parm`
+ * values of `element` call for
+ *
+ * A B-spline has one fewer than its knots minus its degree, a Bezier curve
+ * `degree` for each segment plus one. Without `parm` a Bezier curve is split
+ * into as many segments as its control points make up
+ */
+fn control_point_count(
+    element: &Element,
+    basis: Basis,
+    direction: usize,
+) -> Result<usize, ObjError> {
+    let line = element.line_number;
+    let degree = element.degree[direction];
+    if degree == 0 {
+        return Err(freeform_error(line, "the degree has to be at least 1"));
+    }
+    let parameters = &element.parameters[direction];
+    let count = match basis {
+        Basis::BSpline => parameters.len().checked_sub(degree + 1),
+        Basis::Bezier if parameters.is_empty() && !element.is_surface => {
+            let count = element.control_points.len();
+            (count > degree && (count - 1).is_multiple_of(degree)).then_some(count)
+        }
+        Basis::Bezier => parameters
+            .len()
+            .checked_sub(1)
+            .filter(|&segments| segments > 0)
+            .and_then(|segments| segments.checked_mul(degree))
+            .map(|count| count + 1),
+    };
+    match count {
+        Some(count) if count > degree => Ok(count),
+        _ if parameters.is_empty() => Err(freeform_error(
+            line,
+            format!("missing `parm {}`", ["u", "v"][direction]),
+        )),
+        _ => Err(freeform_error(
+            line,
+            format!(
+                "{} parameters don't fit a degree {} curve",
+                parameters.len(),
+                degree
+            ),
+        )),
+    }
+}
+
+/**
+ * The knot vector of a piecewise Bezier curve whose segments meet at the
+ * global parameters `breaks`, so that it can be evaluated as a B-spline
+ */
+fn bezier_knots(breaks: &[f32], degree: usize) -> Vec<f32> {
+    let mut knots = Vec::with_capacity(breaks.len() * degree + 2);
+    for (i, &value) in breaks.iter().enumerate() {
+        let multiplicity = if i == 0 || i + 1 == breaks.len() {
+            degree + 1
+        } else {
+            degree
+        };
+        knots.extend(std::iter::repeat_n(value, multiplicity));
+    }
+    knots
+}
+
+/**
+ * Parameters to evaluate at: the ends of `range` and every distinct knot in
+ * between, with each span divided into `segments`
+ *
+ * `None` if the range doesn't overlap the domain of the knot vector
+ */
+fn sample_parameters(
+    knots: &[f32],
+    degree: usize,
+    range: [f32; 2],
+    segments: usize,
+    limits: &LoadLimits,
+) -> Option<Result<Vec<f32>, ObjError>> {
+    let control_count = knots.len().checked_sub(degree + 1)?;
+    if control_count <= degree {
+        return None;
+    }
+    let (low, high) = (knots[degree], knots[control_count]);
+    let (start, end) = (range[0].min(range[1]), range[0].max(range[1]));
+    let (start, end) = (start.max(low), end.min(high));
+    if start.is_nan() || end.is_nan() || start >= end {
+        return None;
+    }
+
+    let mut breaks = vec![start];
+    for &knot in &knots[degree + 1..control_count] {
+        if knot > start && knot < end && Some(&knot) != breaks.last() {
+            breaks.push(knot);
+        }
+    }
+    breaks.push(end);
+
+    let sample_count = (breaks.len() - 1)
+        .saturating_mul(segments)
+        .saturating_add(1);
+    // don't allocate for more samples than the vertex limit lets through
+    if let Err(e) = limits.check_vertices(sample_count) {
+        return Some(Err(e));
+    }
+    let mut samples = Vec::with_capacity(sample_count);
+    for span in breaks.windows(2) {
+        for step in 0..segments {
+            samples.push(span[0] + (span[1] - span[0]) * step as f32 / segments as f32);
+        }
+    }
+    samples.push(end);
+    Some(Ok(samples))
+}
+
+/// position of a sample between the first and last, for texture coordinates
+fn normalized(value: f32, samples: &[f32]) -> f32 {
+    let (first, last) = (samples[0], samples[samples.len() - 1]);
+    (value - first) / (last - first)
+}
+
+fn project(point: Vec4) -> Vec3 {
+    if point.w != 0.0 {
+        point.truncate() / point.w
+    } else {
+        point.truncate()
+    }
+}
+
+fn de_boor(control_points: &[Vec4], knots: &[f32], degree: usize, t: f32) -> Vec3 {
+    project(de_boor_homogeneous(control_points, knots, degree, t))
+}
+
+/**
+ * Evaluates a B-spline with homogeneous control points at `t` using de
+ * Boor's algorithm, `knots` has `control_points.len() + degree + 1` entries
+ */
+fn de_boor_homogeneous(control_points: &[Vec4], knots: &[f32], degree: usize, t: f32) -> Vec4 {
+    let count = control_points.len();
+    // the span holding t, the last one for t at the very end of the domain
+    let span = (degree..count)
+        .rev()
+        .find(|&k| knots[k] <= t && knots[k] < knots[k + 1])
+        .unwrap_or(degree);
+
+    let mut points: Vec<Vec4> = control_points[span - degree..=span].to_vec();
+    for r in 1..=degree {
+        for j in (r..=degree).rev() {
+            let left = knots[j + span - degree];
+            let right = knots[j + 1 + span - r];
+            let alpha = if right > left {
+                (t - left) / (right - left)
+            } else {
+                0.0
+            };
+            points[j] = points[j - 1] * (1.0 - alpha) + points[j] * alpha;
+        }
+    }
+    points[degree]
+}
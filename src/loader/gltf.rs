@@ -0,0 +1,498 @@
+use std::convert::TryInto;
+
+use ::gltf::{
+    buffer, image,
+    mesh::{util::ReadIndices, Mode},
+    texture, Gltf, Node, Primitive, Semantic,
+};
+use base64::Engine;
+use glam::{Mat3, Mat4, Vec3};
+
+use super::{
+    normalize_path, EmbeddedFile, FileResolver, LoadLimits, Material, ModelData, ObjError,
+    PbrMaterial, Submesh,
+};
+use crate::geometry::{generate_normals, NormalWeighting};
+
+/// magic, version and total length
+const GLB_HEADER_BYTES: u32 = 12;
+
+/// whether `bytes` start with the GLB container magic
+pub(super) fn is_glb(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"glTF")
+}
+
+/**
+ * Loads a glTF 2.0 document, either a `.gltf` JSON file or a `.glb` binary
+ * container
+ *
+ * Every mesh instance in the default scene becomes a submesh per primitive,
+ * with the node transforms baked into its vertices. Buffers and images come
+ * from the GLB binary chunk, `data:` URIs or `resolver`; a URI the resolver
+ * doesn't have fails the load with `ObjError::MissingFile`. Embedded images
+ * are returned in `ModelData::embedded_files` under the path the materials
+ * refer to them by
+ */
+pub fn load_gltf(bytes: &[u8], resolver: &dyn FileResolver) -> Result<ModelData, ObjError> {
+    load_gltf_with_limits(bytes, resolver, &LoadLimits::default())
+}
+
+/// loads a glTF document, failing with `LimitExceeded` as soon as it goes over `limits`
+pub fn load_gltf_with_limits(
+    bytes: &[u8],
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+) -> Result<ModelData, ObjError> {
+    limits.check_file_size(bytes.len() as u64)?;
+    // the gltf crate underflows on a GLB whose length doesn't cover its own header
+    if is_glb(bytes) {
+        let length = bytes
+            .get(8..12)
+            .map(|length| u32::from_le_bytes(length.try_into().expect("4 byte slice")));
+        if length.is_none_or(|length| length < GLB_HEADER_BYTES) {
+            return Err(gltf_error(String::from("GLB header is truncated")));
+        }
+    }
+    let gltf = Gltf::from_slice(bytes).map_err(|e| ObjError::Gltf {
+        message: e.to_string(),
+    })?;
+    let Gltf { document, blob } = gltf;
+    let buffers = document
+        .buffers()
+        .map(|buffer| load_buffer(&buffer, blob.as_deref(), resolver, limits))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut model = ModelData::default();
+    let image_paths = document
+        .images()
+        .map(|image| {
+            load_image(
+                &image,
+                &buffers,
+                resolver,
+                limits,
+                &mut model.embedded_files,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    model.materials = document
+        .materials()
+        .map(|material| convert_material(&material, &image_paths))
+        .collect();
+
+    let mut builder = GltfBuilder {
+        model,
+        buffers: &buffers,
+        limits,
+        node_count: document.nodes().count(),
+        has_texcoords: false,
+        has_colors: false,
+    };
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                builder.add_node(&node, Mat4::IDENTITY, 0)?;
+            }
+        }
+        // a document without scenes is a library of meshes, show them all
+        None => {
+            for mesh in document.meshes() {
+                for primitive in mesh.primitives() {
+                    builder.add_primitive(&primitive, mesh_name(&mesh), Mat4::IDENTITY)?;
+                }
+            }
+        }
+    }
+
+    let mut model = builder.model;
+    generate_normals(&mut model, NormalWeighting::Angle);
+    Ok(model)
+}
+
+fn gltf_error(message: String) -> ObjError {
+    ObjError::Gltf { message }
+}
+
+/// decodes a `data:[<mime type>];base64,<data>` URI, `None` if `uri` isn't one
+fn decode_data_uri(uri: &str) -> Option<Result<Vec<u8>, ObjError>> {
+    let data = uri.strip_prefix("data:")?;
+    Some(match data.split_once(";base64,") {
+        Some((_, encoded)) => base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| gltf_error(format!("bad base64 data URI: {}", e))),
+        None => Err(gltf_error(String::from(
+            "only base64 data URIs are supported",
+        ))),
+    })
+}
+
+/// the path an external URI refers to, with `%xx` escapes decoded
+fn uri_path(uri: &str) -> Result<String, ObjError> {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| uri.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let decoded = String::from_utf8(decoded)
+        .map_err(|_| gltf_error(format!("`{}` is not a valid URI", uri)))?;
+    normalize_path(&decoded)
+}
+
+/// fetches a file the document refers to by URI, failing if the upload doesn't have it
+fn resolve_uri(uri: &str, resolver: &dyn FileResolver) -> Result<Vec<u8>, ObjError> {
+    if let Some(data) = decode_data_uri(uri) {
+        return data;
+    }
+    let path = uri_path(uri)?;
+    resolver
+        .resolve(&path)?
+        .ok_or(ObjError::MissingFile { path })
+}
+
+fn load_buffer(
+    buffer: &buffer::Buffer,
+    blob: Option<&[u8]>,
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+) -> Result<Vec<u8>, ObjError> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob
+            .ok_or_else(|| gltf_error(String::from("GLB binary chunk is missing")))?
+            .to_vec(),
+        buffer::Source::Uri(uri) => resolve_uri(uri, resolver)?,
+    };
+    limits.check_file_size(data.len() as u64)?;
+    if data.len() < buffer.length() {
+        return Err(ObjError::Truncated {
+            expected: buffer.length(),
+            actual: data.len(),
+        });
+    }
+    Ok(data)
+}
+
+/**
+ * Returns the path materials should use for `image`
+ *
+ * Images stored in a buffer view or a data URI are copied out into
+ * `embedded_files`, external images have to be available from the resolver
+ */
+fn load_image(
+    image: &image::Image,
+    buffers: &[Vec<u8>],
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+    embedded_files: &mut Vec<EmbeddedFile>,
+) -> Result<String, ObjError> {
+    let (contents, mime_type) = match image.source() {
+        image::Source::View { view, mime_type } => {
+            let buffer = &buffers[view.buffer().index()];
+            let contents = buffer
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or_else(|| {
+                    gltf_error(format!("image {} is outside its buffer", image.index()))
+                })?;
+            (contents.to_vec(), Some(mime_type))
+        }
+        image::Source::Uri { uri, mime_type } => match decode_data_uri(uri) {
+            Some(contents) => {
+                let mime_type = mime_type.or_else(|| {
+                    uri.strip_prefix("data:")
+                        .and_then(|data| data.split(';').next())
+                });
+                (contents?, mime_type)
+            }
+            None => {
+                let path = uri_path(uri)?;
+                match resolver.resolve(&path)? {
+                    Some(contents) => limits.check_texture(&contents)?,
+                    None => return Err(ObjError::MissingFile { path }),
+                }
+                return Ok(path);
+            }
+        },
+    };
+
+    limits.check_texture(&contents)?;
+    let extension = match mime_type {
+        Some("image/png") => "png",
+        Some("image/jpeg") => "jpg",
+        _ => "bin",
+    };
+    let path = format!("embedded/image{}.{}", image.index(), extension);
+    embedded_files.push(EmbeddedFile {
+        path: path.clone(),
+        mime_type: mime_type.map(str::to_string),
+        contents,
+    });
+    Ok(path)
+}
+
+fn convert_material(material: &::gltf::Material, image_paths: &[String]) -> Material {
+    let texture_path = |texture: texture::Texture| image_paths[texture.source().index()].clone();
+    let pbr = material.pbr_metallic_roughness();
+    let base_color = pbr.base_color_factor();
+    Material {
+        name: material_name(material),
+        diffuse: [base_color[0], base_color[1], base_color[2]],
+        dissolve: base_color[3],
+        diffuse_texture: pbr
+            .base_color_texture()
+            .map(|info| texture_path(info.texture())),
+        normal_texture: material
+            .normal_texture()
+            .map(|normal| texture_path(normal.texture())),
+        pbr: Some(PbrMaterial {
+            base_color,
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            emissive: material.emissive_factor(),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(|info| texture_path(info.texture())),
+            occlusion_texture: material
+                .occlusion_texture()
+                .map(|occlusion| texture_path(occlusion.texture())),
+            emissive_texture: material
+                .emissive_texture()
+                .map(|info| texture_path(info.texture())),
+            double_sided: material.double_sided(),
+        }),
+        ..Material::default()
+    }
+}
+
+/// materials and meshes are optional to name, unnamed ones are called after their index
+fn material_name(material: &::gltf::Material) -> String {
+    match (material.name(), material.index()) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(index)) => format!("material{}", index),
+        (None, None) => String::from("default"),
+    }
+}
+
+fn mesh_name(mesh: &::gltf::Mesh) -> String {
+    mesh.name()
+        .map_or_else(|| format!("mesh{}", mesh.index()), str::to_string)
+}
+
+struct GltfBuilder<'a> {
+    model: ModelData,
+    buffers: &'a [Vec<u8>],
+    limits: &'a LoadLimits,
+    /// a hierarchy deeper than this must contain a cycle
+    node_count: usize,
+    has_texcoords: bool,
+    has_colors: bool,
+}
+
+impl GltfBuilder<'_> {
+    fn add_node(&mut self, node: &Node, parent: Mat4, depth: usize) -> Result<(), ObjError> {
+        if depth >= self.node_count {
+            return Err(gltf_error(format!(
+                "node {} is its own ancestor",
+                node.index()
+            )));
+        }
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            let name = node.name().map_or_else(|| mesh_name(&mesh), str::to_string);
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, name.clone(), transform)?;
+            }
+        }
+        for child in node.children() {
+            self.add_node(&child, transform, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn add_primitive(
+        &mut self,
+        primitive: &Primitive,
+        name: String,
+        transform: Mat4,
+    ) -> Result<(), ObjError> {
+        if let Some(accessor) = primitive.get(&Semantic::Positions) {
+            self.limits
+                .check_vertices(self.model.vertex_count() + accessor.count())?;
+        }
+        let buffers = self.buffers;
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(positions) => positions.collect(),
+            // a primitive without positions has nothing to draw
+            None => return Ok(()),
+        };
+        let first_vertex = self.model.vertex_count() as u32;
+        let vertex_count = positions.len();
+        for semantic in [
+            Semantic::Normals,
+            Semantic::TexCoords(0),
+            Semantic::Colors(0),
+        ] {
+            if let Some(accessor) = primitive.get(&semantic) {
+                if accessor.count() != vertex_count {
+                    return Err(gltf_error(format!(
+                        "{} accessor has {} elements but there are {} positions",
+                        semantic.to_string(),
+                        accessor.count(),
+                        vertex_count
+                    )));
+                }
+            }
+        }
+
+        for position in &positions {
+            let position = transform.transform_point3(Vec3::from(*position));
+            self.model.vertices.extend_from_slice(&position.to_array());
+        }
+
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+        let normals = self.model.normals.get_or_insert_with(Vec::new);
+        match reader.read_normals() {
+            Some(read_normals) => {
+                for normal in read_normals {
+                    let normal = (normal_matrix * Vec3::from(normal)).normalize_or_zero();
+                    normals.extend_from_slice(&normal.to_array());
+                }
+            }
+            // left as zero for `generate_normals`
+            None => normals.resize(normals.len() + vertex_count * 3, 0.0),
+        }
+
+        let texcoords = reader.read_tex_coords(0).map(|t| t.into_f32());
+        if texcoords.is_some() && !self.has_texcoords {
+            self.has_texcoords = true;
+            self.model.texcoords = Some(vec![0.0; first_vertex as usize * 2]);
+        }
+        if let Some(all_texcoords) = &mut self.model.texcoords {
+            match texcoords {
+                Some(texcoords) => all_texcoords.extend(texcoords.flatten()),
+                None => all_texcoords.resize(all_texcoords.len() + vertex_count * 2, 0.0),
+            }
+        }
+
+        let colors = reader.read_colors(0).map(|c| c.into_rgb_f32());
+        if colors.is_some() && !self.has_colors {
+            self.has_colors = true;
+            self.model.colors = Some(vec![1.0; first_vertex as usize * 3]);
+        }
+        if let Some(all_colors) = &mut self.model.colors {
+            match colors {
+                Some(colors) => all_colors.extend(colors.flatten()),
+                None => all_colors.resize(all_colors.len() + vertex_count * 3, 1.0),
+            }
+        }
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(ReadIndices::U8(indices)) => indices.map(u32::from).collect(),
+            Some(ReadIndices::U16(indices)) => indices.map(u32::from).collect(),
+            Some(ReadIndices::U32(indices)) => indices.collect(),
+            None => (0..vertex_count as u32).collect(),
+        };
+        if let Some(&out_of_range) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+            return Err(gltf_error(format!(
+                "index {} is out of range, the primitive has {} vertices",
+                out_of_range, vertex_count
+            )));
+        }
+        let global = |i: &u32| first_vertex + i;
+
+        // a mirroring transform flips the winding, so flip it back
+        let mirrored = transform.determinant() < 0.0;
+        let mut triangles: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => indices
+                .chunks_exact(3)
+                .map(|t| [global(&t[0]), global(&t[1]), global(&t[2])])
+                .collect(),
+            Mode::TriangleStrip => (2..indices.len())
+                .map(|i| {
+                    let t = [
+                        global(&indices[i - 2]),
+                        global(&indices[i - 1]),
+                        global(&indices[i]),
+                    ];
+                    // every other triangle of a strip is wound the other way
+                    if i % 2 == 0 {
+                        t
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+            Mode::TriangleFan => (2..indices.len())
+                .map(|i| {
+                    [
+                        global(&indices[0]),
+                        global(&indices[i - 1]),
+                        global(&indices[i]),
+                    ]
+                })
+                .collect(),
+            Mode::Lines => {
+                // a trailing index without a partner is dropped, like a
+                // trailing partial triangle
+                for segment in indices.chunks_exact(2) {
+                    self.model.line_indices.extend(segment.iter().map(global));
+                }
+                Vec::new()
+            }
+            Mode::LineStrip | Mode::LineLoop => {
+                let mut strip: Vec<u32> = indices.iter().map(global).collect();
+                if primitive.mode() == Mode::LineLoop && !strip.is_empty() {
+                    strip.push(strip[0]);
+                }
+                for segment in strip.windows(2) {
+                    self.model.line_indices.extend_from_slice(segment);
+                }
+                Vec::new()
+            }
+            Mode::Points => {
+                self.model.point_indices.extend(indices.iter().map(global));
+                Vec::new()
+            }
+        };
+        self.limits
+            .check_faces(self.model.indices.len() / 3 + triangles.len())?;
+        if mirrored {
+            triangles.iter_mut().for_each(|t| t.swap(1, 2));
+        }
+
+        let first_index = self.model.indices.len();
+        self.model.indices.extend(triangles.iter().flatten());
+        // glTF has no smoothing groups: generated normals are flat
+        self.model
+            .smoothing_groups
+            .resize(self.model.indices.len() / 3, 0);
+        if self.model.indices.len() > first_index {
+            self.model.submeshes.push(Submesh {
+                name,
+                material: primitive
+                    .material()
+                    .index()
+                    .map(|_| material_name(&primitive.material())),
+                index_range: first_index..self.model.indices.len(),
+                visible: true,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{convert::TryInto, fmt};
+
+use super::ObjError;
+
+/// which of the `LoadLimits` a file went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    FileSize,
+    LineLength,
+    Vertices,
+    Faces,
+    PolygonArity,
+    TextureDimension,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::FileSize => "file size (bytes)",
+            Limit::LineLength => "line length (bytes)",
+            Limit::Vertices => "vertex count",
+            Limit::Faces => "face count",
+            Limit::PolygonArity => "vertices per polygon",
+            Limit::TextureDimension => "texture width/height (pixels)",
+        })
+    }
+}
+
+/**
+ * Upper bounds on what the loaders will accept from an uploaded file
+ *
+ * The loaders check these as they go, before allocating for counts a header
+ * declares, so a hostile or corrupt upload fails with `LimitExceeded`
+ * instead of exhausting the memory of the wasm module. File size also
+ * applies to every file unpacked from an archive and to their total
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadLimits {
+    pub max_file_bytes: u64,
+    pub max_line_bytes: usize,
+    /// positions declared by the file, and output vertices after de-duplication
+    pub max_vertices: usize,
+    pub max_faces: usize,
+    /// corners of a single polygon before triangulation
+    pub max_polygon_arity: usize,
+    /// largest width or height of a PNG or JPEG texture
+    pub max_texture_dimension: u32,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 512 * 1024 * 1024,
+            max_line_bytes: 64 * 1024,
+            max_vertices: 16 * 1024 * 1024,
+            max_faces: 16 * 1024 * 1024,
+            max_polygon_arity: 1024,
+            max_texture_dimension: 16384,
+        }
+    }
+}
+
+impl LoadLimits {
+    /// no limits at all, for trusted input
+    pub fn unlimited() -> Self {
+        Self {
+            max_file_bytes: u64::MAX,
+            max_line_bytes: usize::MAX,
+            max_vertices: usize::MAX,
+            max_faces: usize::MAX,
+            max_polygon_arity: usize::MAX,
+            max_texture_dimension: u32::MAX,
+        }
+    }
+
+    pub fn check_file_size(&self, size: u64) -> Result<(), ObjError> {
+        check(Limit::FileSize, size, self.max_file_bytes)
+    }
+
+    pub fn check_line_length(&self, length: usize) -> Result<(), ObjError> {
+        check(Limit::LineLength, length as u64, self.max_line_bytes as u64)
+    }
+
+    pub fn check_vertices(&self, count: usize) -> Result<(), ObjError> {
+        check(Limit::Vertices, count as u64, self.max_vertices as u64)
+    }
+
+    pub fn check_faces(&self, count: usize) -> Result<(), ObjError> {
+        check(Limit::Faces, count as u64, self.max_faces as u64)
+    }
+
+    pub fn check_polygon_arity(&self, corners: usize) -> Result<(), ObjError> {
+        check(
+            Limit::PolygonArity,
+            corners as u64,
+            self.max_polygon_arity as u64,
+        )
+    }
+
+    /// checks the dimensions of a PNG or JPEG, anything else is let through
+    pub fn check_texture(&self, bytes: &[u8]) -> Result<(), ObjError> {
+        match image_dimensions(bytes) {
+            Some((width, height)) => check(
+                Limit::TextureDimension,
+                width.max(height) as u64,
+                self.max_texture_dimension as u64,
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+fn check(limit: Limit, value: u64, max: u64) -> Result<(), ObjError> {
+    if value > max {
+        Err(ObjError::LimitExceeded { limit, value, max })
+    } else {
+        Ok(())
+    }
+}
+
+/**
+ * Width and height of a PNG or JPEG read from its header, without decoding
+ * the image
+ *
+ * For JPEG this walks the marker segments up to the first start-of-frame
+ */
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 = |at: usize| Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // the IHDR chunk always comes first
+        if bytes.get(12..16)? != b"IHDR" {
+            return None;
+        }
+        let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        let mut at = 2;
+        loop {
+            if *bytes.get(at)? != 0xff {
+                return None;
+            }
+            let marker = *bytes.get(at + 1)?;
+            match marker {
+                // padding between segments
+                0xff => at += 1,
+                // standalone markers without a length
+                0x01 | 0xd0..=0xd7 => at += 2,
+                // start of frame, except DHT (c4), JPG (c8) and DAC (cc)
+                0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                    let height = be_u16(at + 5)?;
+                    let width = be_u16(at + 7)?;
+                    return Some((width as u32, height as u32));
+                }
+                // start of scan before any frame header
+                0xda | 0xd9 => return None,
+                _ => at += 2 + be_u16(at + 2)? as usize,
+            }
+        }
+    }
+
+    None
+}
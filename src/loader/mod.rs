@@ -1,32 +1,75 @@
-use core::panic;
-use std::{
-    error::Error,
-    io::{BufRead, BufReader},
-};
+mod archive;
+mod cache;
+mod error;
+mod format;
+mod freeform;
+mod gltf;
+mod limits;
+mod mtl;
+mod obj;
+mod ply;
+mod resolver;
+mod stl;
+mod tokens;
+mod triangulate;
+mod upload;
+mod worker;
 
-use ahash::AHashMap;
-use obj::ObjMaterial;
-use tobj::{load_mtl_buf, MTLLoadResult};
-use wasm_bindgen::{
-    prelude::{wasm_bindgen, Closure},
-    JsCast,
-};
+use std::ops::Range;
+
+use crate::geometry::LodLevel;
 
 type Verts = Vec<f32>;
 type Indices = Vec<u32>;
 
-use crate::{loader, log};
+use crate::log;
+
+pub use archive::{find_primary_model, gunzip, load_archive, unzip, Archive};
+pub use cache::{
+    cache_key, decode_lods, decode_model, encode_lods, encode_model, CacheKeyBuilder, CACHE_MAGIC,
+    CACHE_VERSION,
+};
+pub use error::ObjError;
+pub use format::ModelFormat;
+pub use freeform::Tessellation;
+pub use gltf::{load_gltf, load_gltf_with_limits};
+pub use limits::{image_dimensions, Limit, LoadLimits};
+pub use mtl::{Material, PbrMaterial};
+pub use obj::{
+    load_model, load_model_with_limits, load_model_with_resolver, CancelToken, ObjParser, Progress,
+};
+pub use ply::{load_ply, load_ply_with_limits};
+pub use resolver::{normalize_path, FileResolver, MemoryResolver, NoFiles, RelativeResolver};
+pub use stl::{is_binary_stl, load_stl, load_stl_with_limits};
+pub use triangulate::triangulate;
+pub use upload::{parse_upload, UploadParser};
+pub use worker::{is_model_worker, run_model_worker};
+
+use worker::spawn_model_worker;
 
 /**
- * When a user uploads a file, first we evaluate the list of uploaded files by name
+ * When a user uploads files, first we evaluate the list of uploaded files by name
+ *
+ * The model and every other file (MTL libraries, textures) are then handed to
+ * a worker, so that reading and parsing them doesn't hold up the page. The
+ * worker reads the other files into memory so the model can refer to them,
+ * then reads the model in slices, never holding all of it at once: a first
+ * pass hashes it for the model cache, and only on a miss does a second pass
+ * parse it. Its format is taken from the magic bytes of the first slice,
+ * falling back to the file extension; OBJ slices are pushed through the
+ * parser as soon as they are read, other formats are collected and parsed
+ * once the whole file has been read
  *
- * If at least one file is present in the upload list, we register a callback function
- * that fires when the file has finished uploading
+ * A zip upload brings its own MTL libraries and textures: the primary model
+ * is picked from the archive and its references are resolved from it
  *
- * This callback parses the file contents into a list of vertices
+ * The model's levels of detail, the full model first, are handed to
+ * `on_model` back on the main thread
  */
-#[wasm_bindgen]
-pub fn load_obj(file_input: web_sys::HtmlInputElement) {
+pub(crate) fn load_obj(
+    file_input: &web_sys::HtmlInputElement,
+    on_model: impl Fn(Vec<LodLevel>) + 'static,
+) {
     //Check the file list from the input
     let filelist = match file_input.files() {
         Some(files) => files,
@@ -36,145 +79,251 @@ pub fn load_obj(file_input: web_sys::HtmlInputElement) {
         }
     };
 
-    let file = filelist.get(0).expect("Failed to get File from filelist!");
-    let file_reader: web_sys::FileReader = match web_sys::FileReader::new() {
-        Ok(f) => f,
-        Err(_) => web_sys::FileReader::new().expect(""),
+    let files: Vec<web_sys::File> = (0..filelist.length())
+        .map(|i| filelist.get(i).expect("Failed to get File from filelist!"))
+        .collect();
+    // oversized files are turned away before anything is read into memory
+    let limits = LoadLimits::default();
+    for file in &files {
+        if let Err(e) = limits.check_file_size(file.size() as u64) {
+            log!("Refusing to load `{}`: {}", file.name(), e);
+            return;
+        }
+    }
+    let (mut models, mut others): (Vec<_>, Vec<_>) = files.into_iter().partition(|file| {
+        let name = file.name();
+        ModelFormat::from_extension(&name).is_some() || Archive::from_extension(&name).is_some()
+    });
+    if models.is_empty() && others.len() == 1 {
+        // a lone file without a model extension is identified by its contents
+        models.append(&mut others);
+    }
+    let model = match models.into_iter().next() {
+        Some(model) => model,
+        None => {
+            log!("No model file in upload");
+            return;
+        }
     };
 
-    let fr_c = file_reader.clone();
+    let on_parsed = move |levels: Vec<LodLevel>| {
+        if let Some(full) = levels.first() {
+            report_model(&full.model);
+        }
+        on_model(levels);
+    };
+    if let Err(e) = spawn_model_worker(model, others, on_parsed) {
+        log!("Failed to start the model worker: {:?}", e);
+    }
+}
 
-    // create onLoadEnd callback
-    let onloadend_cb = Closure::wrap(Box::new(move |_e: web_sys::ProgressEvent| {
-        let array = js_sys::Uint8Array::new(&fr_c.result().unwrap());
-        let arr_slice = array.to_vec();
-        let mut reader = BufReader::new(&arr_slice[..]);
-        match loader::load_model(&mut reader) {
-            Err(e) => {
-                log!("Failed to parse into verts, tris, normals {:?}", e);
-            }
-            Ok(_vertices) => {
-                // TODO: do something with the list of vertices
-            }
-        };
-    }) as Box<dyn Fn(web_sys::ProgressEvent)>);
-
-    file_reader.set_onloadend(Some(onloadend_cb.as_ref().unchecked_ref()));
-    file_reader
-        .read_as_array_buffer(&file)
-        .expect("blob not readable");
-    onloadend_cb.forget();
+/// logs what the loader had to leave out of `model_data`
+fn report_model(model_data: &ModelData) {
+    if !model_data.untriangulated_faces.is_empty() {
+        log!(
+            "Skipped faces that could not be triangulated on lines {:?}",
+            model_data.untriangulated_faces
+        );
+    }
+    if !model_data.missing_files.is_empty() {
+        log!("Missing referenced files {:?}", model_data.missing_files);
+    }
 }
 
-#[derive(Debug)]
+/**
+ * De-indexed model geometry: every attribute stream has one entry per vertex,
+ * and `indices` refers into all of them at once
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ModelData {
+    /// xyz positions
     pub vertices: Verts,
+    /// uv texture coordinates, present if any face referenced a `vt`
+    pub texcoords: Option<Verts>,
+    /// xyz normals, always present after loading: vertices the file gave no
+    /// normal get one from `geometry::generate_normals`, which splits vertices
+    /// along smoothing group seams, so `vertex_count` can be larger than the
+    /// file's own vertex count. OBJ files without `s` statements have every
+    /// face in `s off` and come out flat shaded
+    pub normals: Option<Verts>,
+    /// xyzw tangents, the w being the bitangent's sign; only present once
+    /// `geometry::generate_tangents` has run
+    pub tangents: Option<Verts>,
+    /// rgb vertex colors in 0-1, present if any `v` statement had colors;
+    /// vertices without one are white. Files with any component above 1 are
+    /// taken to be 0-255 throughout
+    pub colors: Option<Verts>,
+    /// extra per-vertex scalars that have no stream of their own
+    pub attributes: Vec<VertexAttribute>,
+    /// triangle list
     pub indices: Indices,
+    /// smoothing group of each triangle, 0 for `s off`
+    pub smoothing_groups: Vec<u32>,
+    /// line segments (pairs of indices) from `l` polylines
+    pub line_indices: Indices,
+    /// single vertices from `p` elements
+    pub point_indices: Indices,
+    /// named parts of the model (OBJ objects, groups and material runs)
+    pub submeshes: Vec<Submesh>,
+    /// every material from the model's `mtllib` libraries
+    pub materials: Vec<Material>,
+    /// images stored inside the model file itself, e.g. in a GLB
+    pub embedded_files: Vec<EmbeddedFile>,
+    /// referenced MTL libraries and textures that the resolver didn't have
+    pub missing_files: Vec<String>,
+    /// line numbers (face numbers for binary formats) of faces that were
+    /// degenerate or could not be triangulated
+    pub untriangulated_faces: Vec<usize>,
+}
+
+/**
+ * A file that came packed inside the model rather than as a separate upload
+ *
+ * Materials refer to it by `path` just like they refer to uploaded textures
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedFile {
+    pub path: String,
+    pub mime_type: Option<String>,
+    pub contents: Vec<u8>,
+}
+
+/**
+ * A named per-vertex scalar read from the file, e.g. a PLY `confidence` or
+ * `intensity` property, kept so it can be visualized later
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexAttribute {
+    pub name: String,
+    /// one value per vertex
+    pub values: Verts,
 }
 
-pub fn load_model(reader: &mut impl BufRead) -> Result<ModelData, Box<dyn Error>> {
-    // minimal obj parser that ignores materials, normals, etc...
-    // only parses positions and index matches paths
-
-    let mut vertex_position_list = Vec::<[f32; 3]>::new();
-    // dummy coordinate to support 1 based indexing
-    vertex_position_list.push([0.0, 0.0, 0.0]);
-
-    let mut triangle_list = Vec::<[u32; 3]>::new();
-    //let mut vertex_index_offset: usize = 0;
-    triangle_list.push([0, 0, 0]);
-
-    let mut buf = String::new();
-    while reader.read_line(&mut buf).unwrap() != 0 {
-        let mut split = buf.split_whitespace();
-        let prefix = split.next();
-        match prefix {
-            Some(_) => match prefix {
-                Some(char) => {
-                    //log!("line: {}", buf);
-                    match char {
-                        "v" => {
-                            // assume we have x y and z data
-                            let x_coord = split
-                                .next()
-                                .expect("there is x data")
-                                .parse()
-                                .expect("x_coord should be parsable into f32");
-                            let y_coord = split
-                                .next()
-                                .expect("there is y data")
-                                .parse()
-                                .expect("y_coord should be parsable into f32");
-                            let z_coord = split
-                                .next()
-                                .expect("there is z data")
-                                .parse()
-                                .expect("z_coord should be parsable into f32");
-                            vertex_position_list.push([x_coord, y_coord, z_coord]);
-                        }
-                        "f" => {
-                            let vertex_1_index: u32 = split
-                                .next()
-                                .expect("there is data for vertex 1")
-                                .split("/")
-                                .next()
-                                .expect("there is a vertex number for vertex 1")
-                                .parse()
-                                .expect("there is a u32 parsable index for vertex 1");
-                            let vertex_2_index: u32 = split
-                                .next()
-                                .expect("there is data for vertex 2")
-                                .split("/")
-                                .next()
-                                .expect("there is a vertex number for vertex 2")
-                                .parse()
-                                .expect("there is a u32 parsable index for vertex 2");
-                            let vertex_3_index: u32 = split
-                                .next()
-                                .expect("there is data for vertex 3")
-                                .split("/")
-                                .next()
-                                .expect("there is a vertex number for vertex 3")
-                                .parse()
-                                .expect("there is a u32 parsable index for vertex 3");
-                            triangle_list.push([vertex_1_index, vertex_2_index, vertex_3_index]);
-                        }
-                        "g" => {
-                            // starts a new object (vertex numbering resets)
-                            // vertex_index_offset = vertex_list.len();
-                        }
-                        _ => {
-                            //log!("unreadable line: start with: {}", char);
-                        }
-                    }
-                }
-                None => (),
-            },
-            None => (),
+/**
+ * A contiguous run of `ModelData::indices` that shares an object/group name
+ * and a material, so it can be listed and drawn on its own
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submesh {
+    pub name: String,
+    pub material: Option<String>,
+    /// range into `ModelData::indices`
+    pub index_range: Range<usize>,
+    pub visible: bool,
+}
+
+/// tracks the submesh currently being filled while parsing
+struct SubmeshBuilder {
+    name: String,
+    material: Option<String>,
+    first_triangle: usize,
+}
+
+impl SubmeshBuilder {
+    fn new(first_triangle: usize) -> Self {
+        Self {
+            name: String::from("default"),
+            material: None,
+            first_triangle,
+        }
+    }
+
+    /// ends the current submesh at `triangle_count`, skipping it if it is empty
+    fn close(&mut self, submeshes: &mut Vec<Submesh>, triangle_count: usize) {
+        if triangle_count > self.first_triangle {
+            submeshes.push(Submesh {
+                name: self.name.clone(),
+                material: self.material.clone(),
+                index_range: self.first_triangle * 3..triangle_count * 3,
+                visible: true,
+            });
+        }
+        self.first_triangle = triangle_count;
+    }
+}
+
+impl ModelData {
+    pub fn material_for(&self, submesh: &Submesh) -> Option<&Material> {
+        let name = submesh.material.as_ref()?;
+        self.materials
+            .iter()
+            .find(|material| &material.name == name)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    /**
+     * A copy of the vertex streams, indices and submeshes, without the
+     * materials, embedded files and load reports that describe the file
+     * rather than the geometry
+     */
+    pub fn geometry(&self) -> ModelData {
+        ModelData {
+            vertices: self.vertices.clone(),
+            texcoords: self.texcoords.clone(),
+            normals: self.normals.clone(),
+            tangents: self.tangents.clone(),
+            colors: self.colors.clone(),
+            attributes: self.attributes.clone(),
+            indices: self.indices.clone(),
+            smoothing_groups: self.smoothing_groups.clone(),
+            line_indices: self.line_indices.clone(),
+            point_indices: self.point_indices.clone(),
+            submeshes: self.submeshes.clone(),
+            ..ModelData::default()
+        }
+    }
+
+    /// appends a copy of every attribute of `vertex`, returning the copy's index
+    pub fn duplicate_vertex(&mut self, vertex: u32) -> u32 {
+        let v = vertex as usize;
+        self.vertices.extend_from_within(v * 3..v * 3 + 3);
+        if let Some(texcoords) = &mut self.texcoords {
+            texcoords.extend_from_within(v * 2..v * 2 + 2);
+        }
+        if let Some(normals) = &mut self.normals {
+            normals.extend_from_within(v * 3..v * 3 + 3);
+        }
+        if let Some(tangents) = &mut self.tangents {
+            tangents.extend_from_within(v * 4..v * 4 + 4);
+        }
+        if let Some(colors) = &mut self.colors {
+            colors.extend_from_within(v * 3..v * 3 + 3);
+        }
+        for attribute in &mut self.attributes {
+            attribute.values.push(attribute.values[v]);
         }
-        buf.clear();
+        (self.vertex_count() - 1) as u32
     }
 
-    // log!("{:?}", vertex_position_list);
-    // log!("{:?}", triangle_list);
-
-    // flatten vertex data from polygon paths into a single list
-    let flat_triangle_vertex_indexes: Vec<u32> = triangle_list.into_iter().flatten().collect();
-    log!(
-        "{:?}\nlen: {}",
-        flat_triangle_vertex_indexes,
-        flat_triangle_vertex_indexes.len()
-    );
-
-    let flat_vertex_coordinates: Vec<f32> = vertex_position_list.into_iter().flatten().collect();
-    log!(
-        "{:?}\nlen: {}",
-        flat_vertex_coordinates,
-        flat_vertex_coordinates.len()
-    );
-
-    Ok(ModelData {
-        vertices: flat_vertex_coordinates,
-        indices: flat_triangle_vertex_indexes,
-    })
+    /// number of floats per vertex in the buffer returned by `interleaved`
+    pub fn stride(&self) -> usize {
+        3 + self.texcoords.as_ref().map_or(0, |_| 2)
+            + self.normals.as_ref().map_or(0, |_| 3)
+            + self.tangents.as_ref().map_or(0, |_| 4)
+            + self.colors.as_ref().map_or(0, |_| 3)
+    }
+
+    /// packs every attribute stream into a single `[position, uv, normal, tangent, color]` buffer
+    pub fn interleaved(&self) -> Verts {
+        let mut interleaved = Verts::with_capacity(self.vertex_count() * self.stride());
+        for i in 0..self.vertex_count() {
+            interleaved.extend_from_slice(&self.vertices[i * 3..i * 3 + 3]);
+            if let Some(texcoords) = &self.texcoords {
+                interleaved.extend_from_slice(&texcoords[i * 2..i * 2 + 2]);
+            }
+            if let Some(normals) = &self.normals {
+                interleaved.extend_from_slice(&normals[i * 3..i * 3 + 3]);
+            }
+            if let Some(tangents) = &self.tangents {
+                interleaved.extend_from_slice(&tangents[i * 4..i * 4 + 4]);
+            }
+            if let Some(colors) = &self.colors {
+                interleaved.extend_from_slice(&colors[i * 3..i * 3 + 3]);
+            }
+        }
+        interleaved
+    }
 }
@@ -0,0 +1,176 @@
+use std::io::BufReader;
+
+use ahash::AHashMap;
+use tobj::load_mtl_buf;
+
+use super::{
+    resolver::{join_relative, normalize_path},
+    FileResolver, LoadLimits, ObjError,
+};
+
+/**
+ * A material from an MTL library, referenced by submeshes through `usemtl`
+ *
+ * Texture maps are stored as normalized paths that can be handed back to the
+ * `FileResolver` the model was loaded with
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Ka
+    pub ambient: [f32; 3],
+    /// Kd
+    pub diffuse: [f32; 3],
+    /// Ks
+    pub specular: [f32; 3],
+    /// Ns
+    pub shininess: f32,
+    /// d
+    pub dissolve: f32,
+    /// Ni
+    pub optical_density: f32,
+    /// illum
+    pub illumination_model: Option<u8>,
+    /// map_Ka
+    pub ambient_texture: Option<String>,
+    /// map_Kd
+    pub diffuse_texture: Option<String>,
+    /// map_Ks
+    pub specular_texture: Option<String>,
+    /// map_Bump / bump
+    pub normal_texture: Option<String>,
+    /// map_Ns
+    pub shininess_texture: Option<String>,
+    /// map_d
+    pub dissolve_texture: Option<String>,
+    /// any statements we don't interpret, keyed by their keyword
+    pub unknown_params: AHashMap<String, String>,
+    /// metallic-roughness parameters of glTF materials, `None` for MTL
+    pub pbr: Option<PbrMaterial>,
+}
+
+/**
+ * The glTF metallic-roughness parameters that have no MTL equivalent
+ *
+ * The base color is also copied to `diffuse`/`dissolve`/`diffuse_texture`
+ * and the normal map to `normal_texture`, so code that only understands MTL
+ * materials still gets a sensible color
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PbrMaterial {
+    /// linear rgba
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    /// blue channel is metalness, green channel is roughness
+    pub metallic_roughness_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        let defaults = tobj::Material::default();
+        Self {
+            name: defaults.name,
+            ambient: defaults.ambient,
+            diffuse: defaults.diffuse,
+            specular: defaults.specular,
+            shininess: defaults.shininess,
+            dissolve: defaults.dissolve,
+            optical_density: defaults.optical_density,
+            illumination_model: defaults.illumination_model,
+            ambient_texture: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            shininess_texture: None,
+            dissolve_texture: None,
+            unknown_params: defaults.unknown_param,
+            pbr: None,
+        }
+    }
+}
+
+impl Material {
+    /// `library` is the path of the MTL file, texture paths are relative to it
+    fn from_tobj(material: tobj::Material, library: &str) -> Result<Self, ObjError> {
+        let texture = |path: String| -> Result<Option<String>, ObjError> {
+            if path.is_empty() {
+                return Ok(None);
+            }
+            normalize_path(&join_relative(library, &path)).map(Some)
+        };
+        Ok(Self {
+            name: material.name,
+            ambient: material.ambient,
+            diffuse: material.diffuse,
+            specular: material.specular,
+            shininess: material.shininess,
+            dissolve: material.dissolve,
+            optical_density: material.optical_density,
+            illumination_model: material.illumination_model,
+            ambient_texture: texture(material.ambient_texture)?,
+            diffuse_texture: texture(material.diffuse_texture)?,
+            specular_texture: texture(material.specular_texture)?,
+            normal_texture: texture(material.normal_texture)?,
+            shininess_texture: texture(material.shininess_texture)?,
+            dissolve_texture: texture(material.dissolve_texture)?,
+            unknown_params: material.unknown_param,
+            pbr: None,
+        })
+    }
+
+    /// every texture path this material refers to
+    pub fn textures(&self) -> Vec<&str> {
+        let mut textures = vec![
+            &self.ambient_texture,
+            &self.diffuse_texture,
+            &self.specular_texture,
+            &self.normal_texture,
+            &self.shininess_texture,
+            &self.dissolve_texture,
+        ];
+        if let Some(pbr) = &self.pbr {
+            textures.extend(&[
+                &pbr.metallic_roughness_texture,
+                &pbr.occlusion_texture,
+                &pbr.emissive_texture,
+            ]);
+        }
+        textures
+            .into_iter()
+            .filter_map(|texture| texture.as_deref())
+            .collect()
+    }
+}
+
+/**
+ * Parses the MTL library at `path` (already normalized), failing with
+ * `LimitExceeded` if it's bigger than `limits` allow a file to be
+ *
+ * Returns `Ok(None)` if the resolver doesn't have the file
+ */
+pub fn load_mtl(
+    path: &str,
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+) -> Result<Option<Vec<Material>>, ObjError> {
+    let contents = match resolver.resolve(path)? {
+        Some(contents) => contents,
+        None => return Ok(None),
+    };
+    limits.check_file_size(contents.len() as u64)?;
+    let (materials, _) =
+        load_mtl_buf(&mut BufReader::new(&contents[..])).map_err(|e| ObjError::Material {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
+    materials
+        .into_iter()
+        .map(|material| Material::from_tobj(material, path))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
@@ -0,0 +1,724 @@
+use std::{
+    collections::hash_map::Entry,
+    convert::TryFrom,
+    io::BufRead,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use ahash::AHashMap;
+use memchr::{memchr, memchr2};
+
+use super::{
+    freeform::{Direction, FreeformBuilder, Tessellation},
+    mtl, normalize_path,
+    tokens::{parse_i64, LineTokens},
+    triangulate::triangulate_corners,
+    FileResolver, LoadLimits, Material, ModelData, NoFiles, ObjError, Submesh, SubmeshBuilder,
+    Verts,
+};
+use crate::geometry::{generate_normals, NormalWeighting};
+
+/// bytes of OBJ per vertex (and per triangle) when sizing lists up front,
+/// about what a file with `v`, `vt`, `vn` and `f` lines takes
+const BYTES_PER_VERTEX_ESTIMATE: u64 = 64;
+
+/// color of vertices without one when some other vertex in the file has colors
+const DEFAULT_VERTEX_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// weight of vertices without a `w`, which only rational curves and surfaces use
+const DEFAULT_VERTEX_WEIGHT: f32 = 1.0;
+
+pub fn load_model(reader: &mut impl BufRead) -> Result<ModelData, ObjError> {
+    load_model_with_resolver(reader, &NoFiles)
+}
+
+/// loads a model whose MTL libraries and textures are supplied by `resolver`
+pub fn load_model_with_resolver(
+    reader: &mut impl BufRead,
+    resolver: &dyn FileResolver,
+) -> Result<ModelData, ObjError> {
+    load_model_with_limits(reader, resolver, &LoadLimits::default())
+}
+
+/// loads a model, failing with `LimitExceeded` as soon as it goes over `limits`
+pub fn load_model_with_limits(
+    reader: &mut impl BufRead,
+    resolver: &dyn FileResolver,
+    limits: &LoadLimits,
+) -> Result<ModelData, ObjError> {
+    let mut parser = ObjParser::new(resolver).with_limits(*limits);
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk_len = chunk.len();
+        parser.push(chunk)?;
+        reader.consume(chunk_len);
+    }
+    parser.finish()
+}
+
+/// how far an `ObjParser` has got through its input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes: u64,
+    pub lines: usize,
+    pub vertices: usize,
+    pub faces: usize,
+}
+
+/**
+ * Shared flag that stops an `ObjParser` at the next chunk it is given
+ *
+ * Clones refer to the same flag, so the token can be handed to whoever
+ * decides the load is no longer wanted (e.g. a newer upload)
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/**
+ * Push-style OBJ parser: feed it chunks of the file as they arrive with
+ * `push`, then call `finish` once the input is exhausted
+ *
+ * Chunks may split lines anywhere, the incomplete tail of each chunk is kept
+ * until the rest of the line arrives
+ */
+pub struct ObjParser<R: FileResolver> {
+    resolver: R,
+    cancel_token: CancelToken,
+    limits: LoadLimits,
+    tessellation: Tessellation,
+    partial_line: Vec<u8>,
+    after_carriage_return: bool,
+    /// first line number and contents of a line ending in `\`
+    continued_line: Option<(usize, Vec<u8>)>,
+    progress: Progress,
+
+    vertex_position_list: Vec<[f32; 3]>,
+    vertex_texcoord_list: Vec<[f32; 2]>,
+    vertex_normal_list: Vec<[f32; 3]>,
+    /// only as long as the last vertex that had a color, as written in the file
+    vertex_color_list: Vec<Option<[f32; 3]>>,
+    /// largest color component so far, which tells 0-255 files from 0-1 ones
+    max_color_component: f32,
+    /// only as long as the last vertex that had a weight
+    vertex_weight_list: Vec<f32>,
+
+    // every unique (v, vt, vn) triple becomes one output vertex
+    unique_vertex_refs: Vec<VertexRef>,
+    unique_vertex_indexes: AHashMap<VertexRef, u32>,
+
+    triangle_list: Vec<[u32; 3]>,
+    smoothing_groups: Vec<u32>,
+    current_smoothing_group: u32,
+    line_list: Vec<[u32; 2]>,
+    point_list: Vec<u32>,
+    submeshes: Vec<Submesh>,
+    submesh_builder: SubmeshBuilder,
+    materials: Vec<Material>,
+    missing_files: Vec<String>,
+    untriangulated_faces: Vec<usize>,
+    freeform: FreeformBuilder,
+
+    /// output vertices and positions of the face being read; polylines
+    /// reuse the vertices
+    polygon: Vec<u32>,
+    corner_positions: Vec<[f32; 3]>,
+}
+
+impl<R: FileResolver> ObjParser<R> {
+    pub fn new(resolver: R) -> Self {
+        Self {
+            resolver,
+            cancel_token: CancelToken::default(),
+            limits: LoadLimits::default(),
+            tessellation: Tessellation::default(),
+            partial_line: Vec::new(),
+            after_carriage_return: false,
+            continued_line: None,
+            progress: Progress::default(),
+            vertex_position_list: Vec::new(),
+            vertex_texcoord_list: Vec::new(),
+            vertex_normal_list: Vec::new(),
+            vertex_color_list: Vec::new(),
+            max_color_component: 0.0,
+            vertex_weight_list: Vec::new(),
+            unique_vertex_refs: Vec::new(),
+            unique_vertex_indexes: AHashMap::new(),
+            triangle_list: Vec::new(),
+            smoothing_groups: Vec::new(),
+            current_smoothing_group: 0,
+            line_list: Vec::new(),
+            point_list: Vec::new(),
+            submeshes: Vec::new(),
+            submesh_builder: SubmeshBuilder::new(0),
+            materials: Vec::new(),
+            missing_files: Vec::new(),
+            untriangulated_faces: Vec::new(),
+            freeform: FreeformBuilder::default(),
+            polygon: Vec::new(),
+            corner_positions: Vec::new(),
+        }
+    }
+
+    /// replaces the default `LoadLimits`
+    pub fn with_limits(mut self, limits: LoadLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// sets how finely free-form curves and surfaces are tessellated
+    pub fn with_tessellation(mut self, tessellation: Tessellation) -> Self {
+        self.tessellation = tessellation;
+        self
+    }
+
+    /**
+     * Reserves room for the elements a file of `file_size` bytes is likely to
+     * hold, so that the lists don't have to keep growing while it is parsed
+     *
+     * The estimate is capped by the vertex and face limits, so a wrong size
+     * can't make the parser reserve more than it would accept
+     */
+    pub fn with_size_hint(mut self, file_size: u64) -> Self {
+        let estimate = (file_size / BYTES_PER_VERTEX_ESTIMATE) as usize;
+        let vertices = estimate.min(self.limits.max_vertices);
+        let triangles = estimate.min(self.limits.max_faces);
+        self.vertex_position_list.reserve(vertices);
+        self.unique_vertex_refs.reserve(vertices);
+        self.unique_vertex_indexes.reserve(vertices);
+        self.triangle_list.reserve(triangles);
+        self.smoothing_groups.reserve(triangles);
+        self
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// parses every complete line in `chunk`, holding on to any trailing partial line
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Progress, ObjError> {
+        if self.cancel_token.is_cancelled() {
+            return Err(ObjError::Cancelled);
+        }
+        self.progress.bytes += chunk.len() as u64;
+        self.limits.check_file_size(self.progress.bytes)?;
+
+        let mut rest = chunk;
+        // the `\n` of a `\r\n` pair that was split between chunks
+        if self.after_carriage_return && rest.first() == Some(&b'\n') {
+            rest = &rest[1..];
+        }
+        self.after_carriage_return = false;
+
+        // lines end in `\n`, `\r\n` or a lone `\r`
+        while let Some(end) = memchr2(b'\n', b'\r', rest) {
+            let (line, remainder) = rest.split_at(end);
+            rest = &remainder[1..];
+            if remainder[0] == b'\r' {
+                match rest.first() {
+                    Some(b'\n') => rest = &rest[1..],
+                    Some(_) => {}
+                    None => self.after_carriage_return = true,
+                }
+            }
+
+            if self.partial_line.is_empty() {
+                self.end_line(line)?;
+            } else {
+                let mut partial_line = std::mem::take(&mut self.partial_line);
+                partial_line.extend_from_slice(line);
+                self.end_line(&partial_line)?;
+                partial_line.clear();
+                self.partial_line = partial_line;
+            }
+        }
+        self.limits
+            .check_line_length(self.partial_line.len() + rest.len())?;
+        self.partial_line.extend_from_slice(rest);
+        Ok(self.progress)
+    }
+
+    /// parses the final unterminated line (if any) and assembles the model
+    pub fn finish(mut self) -> Result<ModelData, ObjError> {
+        if self.cancel_token.is_cancelled() {
+            return Err(ObjError::Cancelled);
+        }
+        if !self.partial_line.is_empty() {
+            let partial_line = std::mem::take(&mut self.partial_line);
+            self.end_line(&partial_line)?;
+        }
+        // a trailing `\` on the very last line has nothing left to continue onto
+        if let Some((line_number, continued_line)) = self.continued_line.take() {
+            self.parse_line(&continued_line, line_number)?;
+        }
+
+        self.submesh_builder
+            .close(&mut self.submeshes, self.triangle_list.len());
+
+        for material in &self.materials {
+            for texture in material.textures() {
+                match self.resolver.resolve(texture)? {
+                    Some(contents) => self.limits.check_texture(&contents)?,
+                    None if !self.missing_files.iter().any(|f| f == texture) => {
+                        self.missing_files.push(texture.to_string())
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        let unique_vertex_refs = self.unique_vertex_refs;
+        let flat_triangle_vertex_indexes = self.triangle_list.as_flattened().to_vec();
+
+        let vertex_position_list = self.vertex_position_list;
+        let flat_vertex_coordinates = gather(&unique_vertex_refs, |(position, _, _)| {
+            vertex_position_list[*position as usize]
+        });
+
+        let vertex_texcoord_list = self.vertex_texcoord_list;
+        let has_texcoords = unique_vertex_refs.iter().any(|(_, vt, _)| vt.is_some());
+        let flat_texcoords: Option<Verts> = has_texcoords.then(|| {
+            gather(&unique_vertex_refs, |(_, vt, _)| {
+                vt.map_or([0.0; 2], |vt| vertex_texcoord_list[vt as usize])
+            })
+        });
+
+        let vertex_normal_list = self.vertex_normal_list;
+        let has_normals = unique_vertex_refs.iter().any(|(_, _, vn)| vn.is_some());
+        let flat_normals: Option<Verts> = has_normals.then(|| {
+            gather(&unique_vertex_refs, |(_, _, vn)| {
+                vn.map_or([0.0; 3], |vn| vertex_normal_list[vn as usize])
+            })
+        });
+
+        let vertex_color_list = self.vertex_color_list;
+        // some exporters write 0-255 colors instead of 0-1
+        let color_scale = if self.max_color_component > 1.0 {
+            1.0 / 255.0
+        } else {
+            1.0
+        };
+        let flat_colors: Option<Verts> = (!vertex_color_list.is_empty()).then(|| {
+            gather(&unique_vertex_refs, |(position, _, _)| {
+                vertex_color_list
+                    .get(*position as usize)
+                    .copied()
+                    .flatten()
+                    .map_or(DEFAULT_VERTEX_COLOR, |color| color.map(|c| c * color_scale))
+            })
+        });
+
+        let mut model_data = ModelData {
+            vertices: flat_vertex_coordinates,
+            texcoords: flat_texcoords,
+            normals: flat_normals,
+            tangents: None,
+            colors: flat_colors,
+            attributes: Vec::new(),
+            embedded_files: Vec::new(),
+            indices: flat_triangle_vertex_indexes,
+            smoothing_groups: self.smoothing_groups,
+            line_indices: self.line_list.as_flattened().to_vec(),
+            point_indices: self.point_list,
+            submeshes: self.submeshes,
+            materials: self.materials,
+            missing_files: self.missing_files,
+            untriangulated_faces: self.untriangulated_faces,
+        };
+        self.freeform.finish(&mut model_data, &self.limits)?;
+        // files without `vn` still need normals to be shaded
+        generate_normals(&mut model_data, NormalWeighting::Angle);
+        Ok(model_data)
+    }
+
+    /// index of the output vertex for a (v, vt, vn) triple, adding it if it's new
+    fn output_vertex(&mut self, vertex_ref: VertexRef) -> Result<u32, ObjError> {
+        let next_index = self.unique_vertex_refs.len();
+        match self.unique_vertex_indexes.entry(vertex_ref) {
+            Entry::Occupied(entry) => Ok(*entry.get()),
+            Entry::Vacant(entry) => {
+                self.limits.check_vertices(next_index + 1)?;
+                self.unique_vertex_refs.push(vertex_ref);
+                Ok(*entry.insert(next_index as u32))
+            }
+        }
+    }
+
+    /// handles one physical line (without its terminator), joining lines
+    /// that end in a `\` continuation before parsing them
+    fn end_line(&mut self, line: &[u8]) -> Result<(), ObjError> {
+        self.progress.lines += 1;
+        let line_number = self.progress.lines;
+        let continued_len = self.continued_line.as_ref().map_or(0, |(_, l)| l.len());
+        self.limits.check_line_length(continued_len + line.len())?;
+
+        let trimmed_len = line.len()
+            - line
+                .iter()
+                .rev()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+        if trimmed_len > 0 && line[trimmed_len - 1] == b'\\' {
+            let (_, continued_line) = self
+                .continued_line
+                .get_or_insert_with(|| (line_number, Vec::new()));
+            continued_line.extend_from_slice(&line[..trimmed_len - 1]);
+            continued_line.push(b' ');
+            return Ok(());
+        }
+
+        match self.continued_line.take() {
+            Some((first_line_number, mut continued_line)) => {
+                continued_line.extend_from_slice(line);
+                self.parse_line(&continued_line, first_line_number)
+            }
+            None => self.parse_line(line, line_number),
+        }
+    }
+
+    fn parse_line(&mut self, line: &[u8], line_number: usize) -> Result<(), ObjError> {
+        // everything after a `#` is a comment, even part way through a line
+        let line = match memchr(b'#', line) {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut tokens = LineTokens::new(line, line_number);
+        let keyword = match tokens
+            .next_token()
+            .map(|(_, keyword)| std::str::from_utf8(keyword))
+        {
+            Some(Ok(keyword)) => keyword,
+            // not a keyword of any statement we read
+            Some(Err(_)) | None => return Ok(()),
+        };
+        let counts = ElementCounts {
+            positions: self.vertex_position_list.len(),
+            texcoords: self.vertex_texcoord_list.len(),
+            normals: self.vertex_normal_list.len(),
+        };
+
+        match keyword {
+            "v" => {
+                // `x y z`, optionally followed by a `w` weight or by `r g b` colors
+                let x_coord = tokens.next_f32(keyword)?;
+                let y_coord = tokens.next_f32(keyword)?;
+                let z_coord = tokens.next_f32(keyword)?;
+                let mut extra = [0.0; 3];
+                let mut extra_count = 0;
+                while extra_count < extra.len() {
+                    match tokens.next_optional_f32()? {
+                        Some(value) => extra[extra_count] = value,
+                        None => break,
+                    }
+                    extra_count += 1;
+                }
+                if extra_count == 2 {
+                    return Err(tokens.missing(keyword));
+                }
+                if let Some((column, token)) = tokens.next_token() {
+                    return Err(ObjError::UnexpectedToken {
+                        line: line_number,
+                        column,
+                        token: String::from_utf8_lossy(token).into_owned(),
+                        expected: "end of line",
+                    });
+                }
+                self.vertex_position_list.push([x_coord, y_coord, z_coord]);
+                if extra_count == 3 {
+                    self.max_color_component = extra
+                        .iter()
+                        .copied()
+                        .fold(self.max_color_component, f32::max);
+                    let vertex_count = self.vertex_position_list.len();
+                    self.vertex_color_list.resize(vertex_count - 1, None);
+                    self.vertex_color_list.push(Some(extra));
+                } else if extra_count == 1 {
+                    let vertex_count = self.vertex_position_list.len();
+                    self.vertex_weight_list
+                        .resize(vertex_count - 1, DEFAULT_VERTEX_WEIGHT);
+                    self.vertex_weight_list.push(extra[0]);
+                }
+                self.progress.vertices += 1;
+                self.limits.check_vertices(self.progress.vertices)?;
+            }
+            "vt" => {
+                let u = tokens.next_f32(keyword)?;
+                let v = tokens.next_optional_f32()?.unwrap_or(0.0);
+                self.vertex_texcoord_list.push([u, v]);
+            }
+            "vn" => {
+                let x = tokens.next_f32(keyword)?;
+                let y = tokens.next_f32(keyword)?;
+                let z = tokens.next_f32(keyword)?;
+                self.vertex_normal_list.push([x, y, z]);
+            }
+            "f" => {
+                // scratch buffers are reused so faces don't allocate
+                let mut polygon = std::mem::take(&mut self.polygon);
+                let mut corner_positions = std::mem::take(&mut self.corner_positions);
+                polygon.clear();
+                corner_positions.clear();
+                while let Some(vertex_ref) = tokens.next_vertex_ref(&counts)? {
+                    self.limits.check_polygon_arity(polygon.len() + 1)?;
+                    polygon.push(self.output_vertex(vertex_ref)?);
+                    corner_positions.push(self.vertex_position_list[vertex_ref.0 as usize]);
+                }
+                if polygon.len() < 3 {
+                    return Err(tokens.missing(keyword));
+                }
+                self.progress.faces += 1;
+                self.limits.check_faces(self.progress.faces)?;
+                // triangulate the corners locally, then map back to output vertices
+                let first_triangle = self.triangle_list.len();
+                if triangulate_corners(&corner_positions, &mut self.triangle_list) {
+                    for triangle in &mut self.triangle_list[first_triangle..] {
+                        *triangle = triangle.map(|corner| polygon[corner as usize]);
+                    }
+                    self.smoothing_groups
+                        .resize(self.triangle_list.len(), self.current_smoothing_group);
+                } else {
+                    self.untriangulated_faces.push(line_number);
+                }
+                self.polygon = polygon;
+                self.corner_positions = corner_positions;
+            }
+            "l" => {
+                // a polyline, drawn as one segment per pair of consecutive vertices
+                let mut polyline = std::mem::take(&mut self.polygon);
+                polyline.clear();
+                while let Some(vertex_ref) = tokens.next_vertex_ref(&counts)? {
+                    polyline.push(self.output_vertex(vertex_ref)?);
+                }
+                if polyline.len() < 2 {
+                    return Err(tokens.missing(keyword));
+                }
+                self.line_list
+                    .extend(polyline.windows(2).map(|segment| [segment[0], segment[1]]));
+                self.polygon = polyline;
+            }
+            "p" => {
+                let first_point_count = self.point_list.len();
+                while let Some(vertex_ref) = tokens.next_vertex_ref(&counts)? {
+                    let index = self.output_vertex(vertex_ref)?;
+                    self.point_list.push(index);
+                }
+                if self.point_list.len() == first_point_count {
+                    return Err(tokens.missing(keyword));
+                }
+            }
+            "s" => {
+                let (column, group) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                self.current_smoothing_group = match group {
+                    "off" => 0,
+                    group => group.parse().map_err(|_| ObjError::BadIndex {
+                        line: line_number,
+                        column,
+                        token: group.to_string(),
+                    })?,
+                };
+            }
+            "mtllib" => {
+                while let Some((_, library)) = tokens.next_str()? {
+                    let library = normalize_path(library)?;
+                    match mtl::load_mtl(&library, &self.resolver, &self.limits)? {
+                        Some(library_materials) => self.materials.extend(library_materials),
+                        None => self.missing_files.push(library),
+                    }
+                }
+            }
+            "o" | "g" | "usemtl" => {
+                // each object, group and material change starts a new submesh
+                self.submesh_builder
+                    .close(&mut self.submeshes, self.triangle_list.len());
+                let name = tokens.rest()?;
+                let name = if name.is_empty() { "default" } else { name };
+                match keyword {
+                    "usemtl" => self.submesh_builder.material = Some(name.to_string()),
+                    _ => self.submesh_builder.name = name.to_string(),
+                }
+            }
+            "cstype" => {
+                let (_, mut basis) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                let rational = basis == "rat";
+                if rational {
+                    basis = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?.1;
+                }
+                self.freeform.set_type(rational, basis);
+            }
+            "deg" => {
+                let degree_u = tokens
+                    .next_degree()?
+                    .ok_or_else(|| tokens.missing(keyword))?;
+                let degree_v = tokens.next_degree()?;
+                self.freeform.set_degree(degree_u, degree_v);
+            }
+            "curv" | "surf" => {
+                let is_surface = keyword == "surf";
+                let mut range = [[0.0; 2]; 2];
+                for direction in range.iter_mut().take(if is_surface { 2 } else { 1 }) {
+                    *direction = [tokens.next_f32(keyword)?, tokens.next_f32(keyword)?];
+                }
+                let mut control_points = Vec::new();
+                while let Some((position, _, _)) = tokens.next_vertex_ref(&counts)? {
+                    let [x, y, z] = self.vertex_position_list[position as usize];
+                    let w = self
+                        .vertex_weight_list
+                        .get(position as usize)
+                        .copied()
+                        .unwrap_or(DEFAULT_VERTEX_WEIGHT);
+                    control_points.push([x, y, z, w]);
+                }
+                if control_points.len() < 2 {
+                    return Err(tokens.missing(keyword));
+                }
+                self.freeform.start(
+                    line_number,
+                    is_surface,
+                    range,
+                    &control_points,
+                    self.current_smoothing_group,
+                    &self.submesh_builder.name,
+                    self.submesh_builder.material.as_deref(),
+                )?;
+            }
+            "parm" => {
+                let (column, direction) =
+                    tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                let direction = match direction {
+                    "u" => Direction::U,
+                    "v" => Direction::V,
+                    direction => {
+                        return Err(ObjError::UnexpectedToken {
+                            line: line_number,
+                            column,
+                            token: direction.to_string(),
+                            expected: "u",
+                        })
+                    }
+                };
+                let mut values = Vec::new();
+                while let Some(value) = tokens.next_optional_f32()? {
+                    values.push(value);
+                }
+                self.freeform
+                    .set_parameters(line_number, direction, values)?;
+            }
+            "end" => {
+                self.freeform
+                    .end(line_number, &self.tessellation, &self.limits)?;
+            }
+            _ => {
+                // includes the trimming statements of free-form surfaces
+                // (`trim`, `hole`, `scrv`, `curv2`), which aren't applied
+                //log!("unreadable line: start with: {}", keyword);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LineTokens<'_> {
+    /// the next `deg` value, if any
+    fn next_degree(&mut self) -> Result<Option<usize>, ObjError> {
+        let (column, token) = match self.next_token() {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        match parse_i64(token).and_then(|degree| usize::try_from(degree).ok()) {
+            Some(degree) => Ok(Some(degree)),
+            None => Err(ObjError::BadIndex {
+                line: self.line_number,
+                column,
+                token: String::from_utf8_lossy(token).into_owned(),
+            }),
+        }
+    }
+
+    /// parses the next `v`, `v/vt`, `v//vn` or `v/vt/vn` reference, resolving
+    /// relative (negative) indices against the elements read so far and
+    /// rejecting any index that doesn't refer to one of them
+    fn next_vertex_ref(&mut self, counts: &ElementCounts) -> Result<Option<VertexRef>, ObjError> {
+        let (column, token) = match self.next_token() {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        let line_number = self.line_number;
+        let token_text = || String::from_utf8_lossy(token).into_owned();
+        let resolve = |part: &[u8], count: usize, element: &'static str| -> Result<u32, ObjError> {
+            let index = parse_i64(part).ok_or_else(|| ObjError::BadIndex {
+                line: line_number,
+                column,
+                token: token_text(),
+            })?;
+            let resolved = if index < 0 {
+                count as i64 + 1 + index
+            } else {
+                index
+            };
+            if resolved < 1 || resolved > count as i64 {
+                return Err(ObjError::IndexOutOfRange {
+                    line: line_number,
+                    column,
+                    token: token_text(),
+                    element,
+                    count,
+                });
+            }
+            // OBJ indices are 1-based
+            Ok((resolved - 1) as u32)
+        };
+
+        let mut parts = token.split(|&b| b == b'/');
+        let position = resolve(parts.next().unwrap_or(token), counts.positions, "positions")?;
+        let texcoord = match parts.next().filter(|part| !part.is_empty()) {
+            Some(part) => Some(resolve(part, counts.texcoords, "texture coordinates")?),
+            None => None,
+        };
+        let normal = match parts.next().filter(|part| !part.is_empty()) {
+            Some(part) => Some(resolve(part, counts.normals, "normals")?),
+            None => None,
+        };
+        Ok(Some((position, texcoord, normal)))
+    }
+}
+
+/// one `N` component value per output vertex, flattened into a single stream
+fn gather<const N: usize>(
+    vertex_refs: &[VertexRef],
+    value: impl Fn(&VertexRef) -> [f32; N],
+) -> Vec<f32> {
+    let mut flat = Vec::with_capacity(vertex_refs.len() * N);
+    for vertex_ref in vertex_refs {
+        flat.extend_from_slice(&value(vertex_ref));
+    }
+    flat
+}
+
+/// a face vertex reference as 0-based (position, texcoord, normal) indices
+type VertexRef = (u32, Option<u32>, Option<u32>);
+
+/// number of each element type declared so far, used to resolve relative indices
+struct ElementCounts {
+    positions: usize,
+    texcoords: usize,
+    normals: usize,
+}
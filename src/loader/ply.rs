@@ -0,0 +1,670 @@
+use std::convert::TryInto;
+
+use super::{
+    tokens::{parse_f64, LineTokens},
+    triangulate, LoadLimits, ModelData, ObjError, SubmeshBuilder, VertexAttribute,
+};
+use crate::geometry::{generate_normals, NormalWeighting};
+
+/// whether `bytes` start with the `ply` magic line
+pub(super) fn is_ply(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    /// accepts both the original (`uchar`) and the sized (`uint8`) type names
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    /// factor that maps a color stored in this type to 0-1
+    fn color_scale(self) -> f32 {
+        match self {
+            ScalarType::I8 => 1.0 / i8::MAX as f32,
+            ScalarType::U8 => 1.0 / u8::MAX as f32,
+            ScalarType::I16 => 1.0 / i16::MAX as f32,
+            ScalarType::U16 => 1.0 / u16::MAX as f32,
+            ScalarType::I32 => 1.0 / i32::MAX as f32,
+            ScalarType::U32 => 1.0 / u32::MAX as f32,
+            ScalarType::F32 | ScalarType::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    /// a count followed by that many items, e.g. a face's vertex indices
+    List {
+        count: ScalarType,
+        item: ScalarType,
+    },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// fewest bytes one instance can take up: a binary list can be empty, and
+    /// every ASCII value is at least a digit and a separator
+    fn min_encoded_size(&self, encoding: Encoding) -> usize {
+        self.properties
+            .iter()
+            .map(|property| match (encoding, property.kind) {
+                (Encoding::Ascii, _) => 2,
+                (_, PropertyKind::Scalar(scalar_type)) => scalar_type.size(),
+                (_, PropertyKind::List { count, .. }) => count.size(),
+            })
+            .sum()
+    }
+
+    fn bad(&self, index: usize, message: String) -> ObjError {
+        ObjError::BadElement {
+            element: self.name.clone(),
+            index,
+            message,
+        }
+    }
+}
+
+struct Header {
+    encoding: Encoding,
+    elements: Vec<Element>,
+    /// byte offset and line number of the first line after `end_header`
+    body_offset: usize,
+    body_line: usize,
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, ObjError> {
+    let mut offset = 0;
+    let mut line_number = 0;
+    let mut encoding = None;
+    let mut elements = Vec::<Element>::new();
+    loop {
+        line_number += 1;
+        let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+            Some(end) => offset + end,
+            None => {
+                return Err(ObjError::UnexpectedToken {
+                    line: line_number,
+                    column: 1,
+                    token: String::new(),
+                    expected: "end_header",
+                })
+            }
+        };
+        let raw_line = &bytes[offset..end];
+        offset = end + 1;
+        let line = std::str::from_utf8(raw_line).map_err(|e| ObjError::InvalidUtf8 {
+            line: line_number,
+            column: e.valid_up_to() + 1,
+            token: String::from_utf8_lossy(raw_line).trim_end().to_string(),
+        })?;
+        let mut tokens = LineTokens::new(line.trim_end_matches('\r').as_bytes(), line_number);
+        if line_number == 1 {
+            tokens.expect("ply")?;
+            continue;
+        }
+
+        let (column, keyword) = match tokens.next_str()? {
+            Some(next) => next,
+            None => continue,
+        };
+        match keyword {
+            "comment" | "obj_info" => {}
+            "format" => {
+                let (column, name) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                encoding = Some(match name {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => {
+                        return Err(ObjError::UnexpectedToken {
+                            line: line_number,
+                            column,
+                            token: name.to_string(),
+                            expected: "ascii",
+                        })
+                    }
+                });
+            }
+            "element" => {
+                let (_, name) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                let (column, count) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| ObjError::BadIndex {
+                        line: line_number,
+                        column,
+                        token: count.to_string(),
+                    })?,
+                    properties: Vec::new(),
+                });
+            }
+            "property" => {
+                let is_list = tokens.rest()?.starts_with("list ");
+                if is_list {
+                    tokens.next_token();
+                }
+                let mut next_type = || -> Result<ScalarType, ObjError> {
+                    let (column, name) =
+                        tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                    ScalarType::parse(name).ok_or_else(|| ObjError::UnexpectedToken {
+                        line: line_number,
+                        column,
+                        token: name.to_string(),
+                        expected: "float",
+                    })
+                };
+                let kind = if is_list {
+                    PropertyKind::List {
+                        count: next_type()?,
+                        item: next_type()?,
+                    }
+                } else {
+                    PropertyKind::Scalar(next_type()?)
+                };
+                let (_, name) = tokens.next_str()?.ok_or_else(|| tokens.missing(keyword))?;
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| ObjError::UnexpectedToken {
+                        line: line_number,
+                        column,
+                        token: keyword.to_string(),
+                        expected: "element",
+                    })?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            "end_header" => break,
+            _ => {
+                return Err(ObjError::UnexpectedToken {
+                    line: line_number,
+                    column,
+                    token: keyword.to_string(),
+                    expected: "element",
+                })
+            }
+        }
+    }
+
+    let encoding = encoding.ok_or(ObjError::UnexpectedToken {
+        line: line_number,
+        column: 1,
+        token: String::from("end_header"),
+        expected: "format",
+    })?;
+    Ok(Header {
+        encoding,
+        elements,
+        body_offset: offset,
+        body_line: line_number + 1,
+    })
+}
+
+/// the values of the body in file order, whatever the encoding
+trait Values {
+    /// returns `Ok(None)` once the body has run out
+    fn next(&mut self, scalar_type: ScalarType) -> Result<Option<f64>, ObjError>;
+}
+
+struct AsciiValues<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    first_line: usize,
+    tokens: LineTokens<'a>,
+    limits: LoadLimits,
+}
+
+impl Values for AsciiValues<'_> {
+    fn next(&mut self, _: ScalarType) -> Result<Option<f64>, ObjError> {
+        loop {
+            if let Some((column, token)) = self.tokens.next_token() {
+                return parse_f64(token)
+                    .map(Some)
+                    .ok_or_else(|| self.tokens.bad_float(column, token));
+            }
+            match self.lines.next() {
+                Some((index, line)) => {
+                    self.limits.check_line_length(line.len())?;
+                    self.tokens = LineTokens::new(line.as_bytes(), self.first_line + index);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+struct BinaryValues<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Values for BinaryValues<'_> {
+    fn next(&mut self, scalar_type: ScalarType) -> Result<Option<f64>, ObjError> {
+        let size = scalar_type.size();
+        if self.bytes.len() < size {
+            return Ok(None);
+        }
+        let (value, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+
+        macro_rules! read {
+            ($type:ty) => {{
+                let value = value.try_into().expect("slice has the type's size");
+                (if self.big_endian {
+                    <$type>::from_be_bytes(value)
+                } else {
+                    <$type>::from_le_bytes(value)
+                }) as f64
+            }};
+        }
+        Ok(Some(match scalar_type {
+            ScalarType::I8 => read!(i8),
+            ScalarType::U8 => read!(u8),
+            ScalarType::I16 => read!(i16),
+            ScalarType::U16 => read!(u16),
+            ScalarType::I32 => read!(i32),
+            ScalarType::U32 => read!(u32),
+            ScalarType::F32 => read!(f32),
+            ScalarType::F64 => read!(f64),
+        }))
+    }
+}
+
+/**
+ * Reads every instance of `element`, handing `visit` the instance index, the
+ * property index and the property's values (one for a scalar, any number for
+ * a list)
+ *
+ * Lists longer than `max_polygon_arity` are rejected, whatever they hold
+ */
+fn read_element(
+    values: &mut dyn Values,
+    element: &Element,
+    limits: &LoadLimits,
+    mut visit: impl FnMut(usize, usize, &[f64]) -> Result<(), ObjError>,
+) -> Result<(), ObjError> {
+    let mut property_values = Vec::new();
+    for index in 0..element.count {
+        for (property_index, property) in element.properties.iter().enumerate() {
+            let mut next = |scalar_type| {
+                values.next(scalar_type)?.ok_or_else(|| {
+                    element.bad(
+                        index,
+                        String::from("the file ends before all of its values"),
+                    )
+                })
+            };
+            property_values.clear();
+            match property.kind {
+                PropertyKind::Scalar(scalar_type) => property_values.push(next(scalar_type)?),
+                PropertyKind::List { count, item } => {
+                    let len = next(count)?;
+                    if len < 0.0 || len.fract() != 0.0 {
+                        return Err(element.bad(
+                            index,
+                            format!("`{}` has an invalid length of {}", property.name, len),
+                        ));
+                    }
+                    limits.check_polygon_arity(len as usize)?;
+                    for _ in 0..len as usize {
+                        property_values.push(next(item)?);
+                    }
+                }
+            }
+            visit(index, property_index, &property_values)?;
+        }
+    }
+    Ok(())
+}
+
+/// a face or edge vertex index, checked to be a whole non-negative number
+fn vertex_index(element: &Element, index: usize, value: f64) -> Result<u32, ObjError> {
+    if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+        return Err(element.bad(index, format!("{} is not a valid vertex index", value)));
+    }
+    Ok(value as u32)
+}
+
+/// a vertex property's name, type and values
+type Column = (String, ScalarType, Vec<f32>);
+
+/// removes the named columns if all of them are present
+fn take_columns<const N: usize>(
+    columns: &mut Vec<Column>,
+    names: [&str; N],
+) -> Option<[(ScalarType, Vec<f32>); N]> {
+    if !names
+        .iter()
+        .all(|name| columns.iter().any(|(n, _, _)| n == name))
+    {
+        return None;
+    }
+    Some(names.map(|name| {
+        let position = columns
+            .iter()
+            .position(|(n, _, _)| n == name)
+            .expect("checked above");
+        let (_, scalar_type, values) = columns.remove(position);
+        (scalar_type, values)
+    }))
+}
+
+/// zips per-property columns into a single `[a, b, c, a, b, c, ...]` stream
+fn interleave(vertex_count: usize, columns: &[(ScalarType, Vec<f32>)]) -> Vec<f32> {
+    (0..vertex_count)
+        .flat_map(|i| columns.iter().map(move |(_, values)| values[i]))
+        .collect()
+}
+
+/**
+ * Loads an ASCII or binary (little or big endian) PLY mesh or point cloud
+ *
+ * `x`/`y`/`z`, `nx`/`ny`/`nz`, `red`/`green`/`blue` and `s`/`t` (or `u`/`v`)
+ * vertex properties fill the usual streams, every other scalar vertex
+ * property is kept as a named `VertexAttribute`. Faces of any arity are
+ * triangulated and smoothed as a single group, since PLY files are usually
+ * scanned surfaces without hard edges. A file without faces or edges is
+ * treated as a point cloud
+ */
+pub fn load_ply(bytes: &[u8]) -> Result<ModelData, ObjError> {
+    load_ply_with_limits(bytes, &LoadLimits::default())
+}
+
+/// loads a PLY file, failing with `LimitExceeded` as soon as it goes over `limits`
+pub fn load_ply_with_limits(bytes: &[u8], limits: &LoadLimits) -> Result<ModelData, ObjError> {
+    limits.check_file_size(bytes.len() as u64)?;
+    let header = parse_header(bytes)?;
+    // declared counts are checked before anything is allocated for them
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => limits.check_vertices(element.count)?,
+            "face" => limits.check_faces(element.count)?,
+            _ => {}
+        }
+    }
+    let body = &bytes[header.body_offset..];
+    for element in &header.elements {
+        if element.properties.is_empty() && element.count > 0 {
+            return Err(element.bad(
+                0,
+                format!(
+                    "{} instances are declared without any properties",
+                    element.count
+                ),
+            ));
+        }
+        // the last ASCII value of the file needs no separator after it
+        let needed = element
+            .count
+            .saturating_mul(element.min_encoded_size(header.encoding));
+        if needed > body.len() + 1 {
+            return Err(ObjError::Truncated {
+                expected: header.body_offset.saturating_add(needed),
+                actual: bytes.len(),
+            });
+        }
+    }
+    let mut ascii_values;
+    let mut binary_values;
+    let values: &mut dyn Values = match header.encoding {
+        Encoding::Ascii => {
+            let text = std::str::from_utf8(body).map_err(|e| ObjError::InvalidUtf8 {
+                line: header.body_line
+                    + body[..e.valid_up_to()]
+                        .iter()
+                        .filter(|&&b| b == b'\n')
+                        .count(),
+                column: 1,
+                token: String::from_utf8_lossy(&body[e.valid_up_to()..])
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            })?;
+            ascii_values = AsciiValues {
+                lines: text.lines().enumerate(),
+                first_line: header.body_line,
+                tokens: LineTokens::new(b"", header.body_line),
+                limits: *limits,
+            };
+            &mut ascii_values
+        }
+        Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => {
+            binary_values = BinaryValues {
+                bytes: body,
+                big_endian: header.encoding == Encoding::BinaryBigEndian,
+            };
+            &mut binary_values
+        }
+    };
+
+    let mut model = ModelData::default();
+    // vertex properties in header order, then sorted into streams below
+    let mut vertex_columns = Vec::<Column>::new();
+    let mut polygons = Vec::<(usize, Vec<u32>)>::new();
+    let mut edges = Vec::<(usize, [u32; 2])>::new();
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => {
+                vertex_columns = element
+                    .properties
+                    .iter()
+                    .filter_map(|property| match property.kind {
+                        PropertyKind::Scalar(scalar_type) => Some((
+                            property.name.clone(),
+                            scalar_type,
+                            Vec::with_capacity(element.count.min(body.len())),
+                        )),
+                        PropertyKind::List { .. } => None,
+                    })
+                    .collect();
+                let column_of: Vec<Option<usize>> = {
+                    let mut scalar = 0;
+                    element
+                        .properties
+                        .iter()
+                        .map(|property| match property.kind {
+                            PropertyKind::Scalar(_) => {
+                                scalar += 1;
+                                Some(scalar - 1)
+                            }
+                            PropertyKind::List { .. } => None,
+                        })
+                        .collect()
+                };
+                read_element(values, element, limits, |_, property, values| {
+                    if let Some(column) = column_of[property] {
+                        vertex_columns[column].2.push(values[0] as f32);
+                    }
+                    Ok(())
+                })?;
+            }
+            "face" => {
+                let indices_property = element.properties.iter().position(|property| {
+                    matches!(property.kind, PropertyKind::List { .. })
+                        && (property.name == "vertex_indices" || property.name == "vertex_index")
+                });
+                read_element(values, element, limits, |index, property, values| {
+                    if Some(property) == indices_property {
+                        let polygon = values
+                            .iter()
+                            .map(|&value| vertex_index(element, index, value))
+                            .collect::<Result<_, _>>()?;
+                        polygons.push((index, polygon));
+                    }
+                    Ok(())
+                })?;
+            }
+            "edge" => {
+                let ends: Vec<Option<usize>> = element
+                    .properties
+                    .iter()
+                    .map(|property| match (property.name.as_str(), property.kind) {
+                        ("vertex1", PropertyKind::Scalar(_)) => Some(0),
+                        ("vertex2", PropertyKind::Scalar(_)) => Some(1),
+                        _ => None,
+                    })
+                    .collect();
+                let mut edge = [None; 2];
+                read_element(values, element, limits, |index, property, values| {
+                    if let Some(end) = ends[property] {
+                        edge[end] = Some(vertex_index(element, index, values[0])?);
+                    }
+                    if property + 1 == ends.len() {
+                        if let [Some(start), Some(end)] = std::mem::take(&mut edge) {
+                            edges.push((index, [start, end]));
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+            // anything else still has to be read past to reach the next element
+            _ => read_element(values, element, limits, |_, _, _| Ok(()))?,
+        }
+    }
+
+    let vertex_count = header
+        .elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+    let [x, y, z] = match take_columns(&mut vertex_columns, ["x", "y", "z"]) {
+        Some(positions) => positions,
+        None if vertex_count == 0 => [(); 3].map(|_| (ScalarType::F32, Vec::new())),
+        None => {
+            return Err(ObjError::BadElement {
+                element: String::from("vertex"),
+                index: 0,
+                message: String::from("vertices need `x`, `y` and `z` properties"),
+            })
+        }
+    };
+    model.vertices = interleave(vertex_count, &[x, y, z]);
+    model.normals = take_columns(&mut vertex_columns, ["nx", "ny", "nz"])
+        .map(|normals| interleave(vertex_count, &normals));
+    model.colors = take_columns(&mut vertex_columns, ["red", "green", "blue"])
+        .or_else(|| {
+            take_columns(
+                &mut vertex_columns,
+                ["diffuse_red", "diffuse_green", "diffuse_blue"],
+            )
+        })
+        .map(|mut colors| {
+            for (scalar_type, values) in &mut colors {
+                let scale = scalar_type.color_scale();
+                values.iter_mut().for_each(|value| *value *= scale);
+            }
+            interleave(vertex_count, &colors)
+        });
+    model.texcoords = [["s", "t"], ["u", "v"], ["texture_u", "texture_v"]]
+        .iter()
+        .find_map(|&names| take_columns(&mut vertex_columns, names))
+        .map(|texcoords| interleave(vertex_count, &texcoords));
+    model.attributes = vertex_columns
+        .into_iter()
+        .map(|(name, _, values)| VertexAttribute { name, values })
+        .collect();
+
+    let positions: Vec<[f32; 3]> = model
+        .vertices
+        .chunks_exact(3)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let face_element = header.elements.iter().find(|e| e.name == "face");
+    for (index, polygon) in polygons {
+        if let Some(&out_of_range) = polygon.iter().find(|&&i| i as usize >= vertex_count) {
+            let face_element = face_element.expect("polygons come from a face element");
+            return Err(face_element.bad(
+                index,
+                format!(
+                    "vertex index {} is out of range, only {} vertices",
+                    out_of_range, vertex_count
+                ),
+            ));
+        }
+        let triangles = match polygon.len() {
+            3 => Some(vec![[polygon[0], polygon[1], polygon[2]]]),
+            n if n > 3 => triangulate(&positions, &polygon),
+            _ => None,
+        };
+        match triangles {
+            Some(triangles) => model.indices.extend(triangles.iter().flatten()),
+            // faces are numbered from 1 like OBJ lines
+            None => model.untriangulated_faces.push(index + 1),
+        }
+    }
+    let edge_element = header.elements.iter().find(|e| e.name == "edge");
+    for (index, edge) in edges {
+        if let Some(&out_of_range) = edge.iter().find(|&&i| i as usize >= vertex_count) {
+            let edge_element = edge_element.expect("edges come from an edge element");
+            return Err(edge_element.bad(
+                index,
+                format!(
+                    "vertex index {} is out of range, only {} vertices",
+                    out_of_range, vertex_count
+                ),
+            ));
+        }
+        model.line_indices.extend_from_slice(&edge);
+    }
+    if model.indices.is_empty() && model.line_indices.is_empty() {
+        model.point_indices = (0..vertex_count as u32).collect();
+    }
+
+    let triangle_count = model.indices.len() / 3;
+    model.smoothing_groups = vec![1; triangle_count];
+    SubmeshBuilder::new(0).close(&mut model.submeshes, triangle_count);
+    generate_normals(&mut model, NormalWeighting::Angle);
+    Ok(model)
+}
@@ -0,0 +1,152 @@
+use ahash::AHashMap;
+
+use super::ObjError;
+
+/**
+ * Supplies the files a model refers to (MTL libraries, textures) by the
+ * relative path written in the model
+ *
+ * Uploads arrive as a loose set of files rather than a directory, so the
+ * loader never touches a filesystem directly and instead asks a resolver
+ */
+pub trait FileResolver {
+    /// returns `Ok(None)` if the file simply isn't available
+    fn resolve(&self, path: &str) -> Result<Option<Vec<u8>>, ObjError>;
+
+    fn contains(&self, path: &str) -> Result<bool, ObjError> {
+        Ok(self.resolve(path)?.is_some())
+    }
+}
+
+impl<T: FileResolver + ?Sized> FileResolver for &T {
+    fn resolve(&self, path: &str) -> Result<Option<Vec<u8>>, ObjError> {
+        (**self).resolve(path)
+    }
+
+    fn contains(&self, path: &str) -> Result<bool, ObjError> {
+        (**self).contains(path)
+    }
+}
+
+/// a resolver for single-file uploads: nothing else is available
+pub struct NoFiles;
+
+impl FileResolver for NoFiles {
+    fn resolve(&self, path: &str) -> Result<Option<Vec<u8>>, ObjError> {
+        normalize_path(path)?;
+        Ok(None)
+    }
+}
+
+/// files held in memory, e.g. from a multi-file upload or a test fixture
+#[derive(Default)]
+pub struct MemoryResolver {
+    files: AHashMap<String, Vec<u8>>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, contents: Vec<u8>) -> Result<(), ObjError> {
+        self.files.insert(normalize_path(path)?, contents);
+        Ok(())
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+}
+
+impl FileResolver for MemoryResolver {
+    fn resolve(&self, path: &str) -> Result<Option<Vec<u8>>, ObjError> {
+        Ok(self.files.get(&normalize_path(path)?).cloned())
+    }
+
+    fn contains(&self, path: &str) -> Result<bool, ObjError> {
+        Ok(self.files.contains_key(&normalize_path(path)?))
+    }
+}
+
+/**
+ * Resolves paths relative to the directory of `base` through another resolver
+ *
+ * Used when the model sits in a subdirectory of an archive, so its
+ * `mtllib model.mtl` means the `model.mtl` next to it
+ */
+pub struct RelativeResolver<R: FileResolver> {
+    base: String,
+    inner: R,
+}
+
+impl<R: FileResolver> RelativeResolver<R> {
+    /// `base` is the (normalized) path of the file the references come from
+    pub fn new(base: &str, inner: R) -> Self {
+        Self {
+            base: base.to_string(),
+            inner,
+        }
+    }
+
+    /// `../` may leave the model's directory, but not the root of `inner`
+    fn full_path(&self, path: &str) -> Result<String, ObjError> {
+        let unified = path.trim().replace('\\', "/");
+        if unified.starts_with('/') {
+            return Err(ObjError::UnsafePath {
+                path: path.to_string(),
+            });
+        }
+        normalize_path(&join_relative(&self.base, &unified))
+    }
+}
+
+impl<R: FileResolver> FileResolver for RelativeResolver<R> {
+    fn resolve(&self, path: &str) -> Result<Option<Vec<u8>>, ObjError> {
+        self.inner.resolve(&self.full_path(path)?)
+    }
+
+    fn contains(&self, path: &str) -> Result<bool, ObjError> {
+        self.inner.contains(&self.full_path(path)?)
+    }
+}
+
+/**
+ * Normalizes a relative path to `/` separated components without `.` or `..`
+ *
+ * Absolute paths and paths that climb above the upload root (`../../x`) are
+ * rejected, since they can only be attempts to read something we never gave
+ * the loader
+ */
+pub fn normalize_path(path: &str) -> Result<String, ObjError> {
+    let unsafe_path = || ObjError::UnsafePath {
+        path: path.to_string(),
+    };
+    let unified = path.trim().replace('\\', "/");
+    if unified.starts_with('/') || unified.contains(':') {
+        return Err(unsafe_path());
+    }
+
+    let mut components = Vec::<&str>::new();
+    for component in unified.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop().ok_or_else(unsafe_path)?;
+            }
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(unsafe_path());
+    }
+    Ok(components.join("/"))
+}
+
+/// resolves `path` relative to the directory containing `base`
+pub fn join_relative(base: &str, path: &str) -> String {
+    match base.rfind('/') {
+        Some(i) => format!("{}/{}", &base[..i], path),
+        None => path.to_string(),
+    }
+}
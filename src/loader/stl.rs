@@ -0,0 +1,268 @@
+use std::convert::TryInto;
+
+use ahash::AHashMap;
+
+use super::{
+    tokens::LineTokens, triangulate::triangulate_corners, LoadLimits, ModelData, ObjError,
+    SubmeshBuilder,
+};
+use crate::geometry::{generate_normals, NormalWeighting};
+
+/// 80 byte header followed by the little-endian u32 triangle count
+const BINARY_HEADER_BYTES: usize = 84;
+/// normal, three corners and a u16 attribute byte count
+const BINARY_TRIANGLE_BYTES: usize = 50;
+
+/**
+ * Whether `bytes` hold a binary rather than an ASCII STL file
+ *
+ * Binary files are allowed to start with `solid` too, so a file whose length
+ * matches its triangle count is binary regardless of how its header reads
+ */
+pub fn is_binary_stl(bytes: &[u8]) -> bool {
+    if let Some(expected) = binary_len(bytes) {
+        if expected == bytes.len() {
+            return true;
+        }
+    }
+    !is_ascii_stl(bytes) && bytes.len() >= BINARY_HEADER_BYTES
+}
+
+/// whether `bytes` start like an ASCII STL file (`solid` and a name)
+pub(super) fn is_ascii_stl(bytes: &[u8]) -> bool {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    let text = &bytes[start.unwrap_or(bytes.len())..];
+    text.starts_with(b"solid") && text.get(5).is_none_or(u8::is_ascii_whitespace)
+}
+
+/// length a binary STL file should have according to its triangle count
+pub(super) fn binary_len(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(80..BINARY_HEADER_BYTES)?;
+    let count = u32::from_le_bytes(count.try_into().ok()?) as usize;
+    count
+        .checked_mul(BINARY_TRIANGLE_BYTES)?
+        .checked_add(BINARY_HEADER_BYTES)
+}
+
+/**
+ * Loads an ASCII or binary STL file
+ *
+ * STL stores every facet with its own three corners, so corners that share
+ * both a position and a facet normal are welded into a single vertex. Facet
+ * normals are kept as the vertex normals, and facets whose normal is zero
+ * get one generated from their winding
+ */
+pub fn load_stl(bytes: &[u8]) -> Result<ModelData, ObjError> {
+    load_stl_with_limits(bytes, &LoadLimits::default())
+}
+
+/// loads an STL file, failing with `LimitExceeded` as soon as it goes over `limits`
+pub fn load_stl_with_limits(bytes: &[u8], limits: &LoadLimits) -> Result<ModelData, ObjError> {
+    limits.check_file_size(bytes.len() as u64)?;
+    let mut builder = StlBuilder {
+        limits: *limits,
+        ..StlBuilder::default()
+    };
+    if is_binary_stl(bytes) {
+        builder.read_binary(bytes)?;
+    } else {
+        builder.read_ascii(bytes)?;
+    }
+    Ok(builder.finish())
+}
+
+#[derive(Default)]
+struct StlBuilder {
+    model: ModelData,
+    /// output vertex for each (position, normal) pair, keyed by their bits
+    welded: AHashMap<[u32; 6], u32>,
+    submesh_builder: Option<SubmeshBuilder>,
+    limits: LoadLimits,
+    facets: usize,
+    /// scratch space for the triangles of the current facet
+    triangles: Vec<[u32; 3]>,
+}
+
+impl StlBuilder {
+    fn read_binary(&mut self, bytes: &[u8]) -> Result<(), ObjError> {
+        let expected = binary_len(bytes).ok_or(ObjError::Truncated {
+            expected: BINARY_HEADER_BYTES,
+            actual: bytes.len(),
+        })?;
+        if bytes.len() < expected {
+            return Err(ObjError::Truncated {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let triangle_count = (expected - BINARY_HEADER_BYTES) / BINARY_TRIANGLE_BYTES;
+        self.limits.check_faces(triangle_count)?;
+
+        self.start_submesh("default");
+        let read_vec3 = |bytes: &[u8]| -> [f32; 3] {
+            let mut vec3 = [0.0; 3];
+            for (i, component) in vec3.iter_mut().enumerate() {
+                let le_bytes = bytes[i * 4..i * 4 + 4].try_into().expect("4 byte slice");
+                *component = f32::from_le_bytes(le_bytes);
+            }
+            vec3
+        };
+        let triangles = bytes[BINARY_HEADER_BYTES..expected].chunks_exact(BINARY_TRIANGLE_BYTES);
+        for (index, triangle) in triangles.enumerate() {
+            let normal = read_vec3(&triangle[0..12]);
+            let corners = [
+                read_vec3(&triangle[12..24]),
+                read_vec3(&triangle[24..36]),
+                read_vec3(&triangle[36..48]),
+            ];
+            // facets are numbered from 1 like the lines of ASCII files
+            self.add_facet(normal, &corners, index + 1)?;
+        }
+        Ok(())
+    }
+
+    fn read_ascii(&mut self, bytes: &[u8]) -> Result<(), ObjError> {
+        let text = std::str::from_utf8(bytes).map_err(|e| {
+            let valid = &bytes[..e.valid_up_to()];
+            let line_start = valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            let line_end = bytes[line_start..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(bytes.len(), |i| line_start + i);
+            ObjError::InvalidUtf8 {
+                line: valid.iter().filter(|&&b| b == b'\n').count() + 1,
+                column: e.valid_up_to() - line_start + 1,
+                token: String::from_utf8_lossy(&bytes[line_start..line_end])
+                    .trim()
+                    .to_string(),
+            }
+        })?;
+
+        let mut normal = [0.0; 3];
+        let mut corners = Vec::<[f32; 3]>::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            self.limits.check_line_length(line.len())?;
+            let mut tokens = LineTokens::new(line.as_bytes(), line_number);
+            let (column, keyword) = match tokens.next_str()? {
+                Some(next) => next,
+                None => continue,
+            };
+            match keyword {
+                "solid" => {
+                    let name = tokens.rest()?;
+                    self.start_submesh(if name.is_empty() { "default" } else { name });
+                }
+                "facet" => {
+                    tokens.expect("normal")?;
+                    normal = [
+                        tokens.next_f32(keyword)?,
+                        tokens.next_f32(keyword)?,
+                        tokens.next_f32(keyword)?,
+                    ];
+                    corners.clear();
+                }
+                "outer" => tokens.expect("loop")?,
+                "vertex" => {
+                    self.limits.check_polygon_arity(corners.len() + 1)?;
+                    corners.push([
+                        tokens.next_f32(keyword)?,
+                        tokens.next_f32(keyword)?,
+                        tokens.next_f32(keyword)?,
+                    ]);
+                }
+                "endfacet" => {
+                    self.add_facet(normal, &corners, line_number)?;
+                    corners.clear();
+                }
+                "endloop" | "endsolid" => {}
+                _ => {
+                    return Err(ObjError::UnexpectedToken {
+                        line: line_number,
+                        column,
+                        token: keyword.to_string(),
+                        expected: "facet",
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn start_submesh(&mut self, name: &str) {
+        let triangle_count = self.model.indices.len() / 3;
+        if let Some(mut previous) = self.submesh_builder.take() {
+            previous.close(&mut self.model.submeshes, triangle_count);
+        }
+        let mut submesh_builder = SubmeshBuilder::new(triangle_count);
+        submesh_builder.name = name.to_string();
+        self.submesh_builder = Some(submesh_builder);
+    }
+
+    /// adds a facet's triangles, `line_number` is reported if it can't be triangulated
+    fn add_facet(
+        &mut self,
+        normal: [f32; 3],
+        corners: &[[f32; 3]],
+        line_number: usize,
+    ) -> Result<(), ObjError> {
+        self.facets += 1;
+        self.limits.check_faces(self.facets)?;
+        if self.submesh_builder.is_none() {
+            self.start_submesh("default");
+        }
+        // a zero normal is left for `generate_normals` to fill in
+        let normal = if normal.iter().all(|n| n.is_finite()) {
+            normal
+        } else {
+            [0.0; 3]
+        };
+
+        let mut triangles = std::mem::take(&mut self.triangles);
+        triangles.clear();
+        if !triangulate_corners(corners, &mut triangles) {
+            self.model.untriangulated_faces.push(line_number);
+        }
+        for triangle in &triangles {
+            for &corner in triangle {
+                let vertex = self.weld(corners[corner as usize], normal)?;
+                self.model.indices.push(vertex);
+            }
+        }
+        self.triangles = triangles;
+        Ok(())
+    }
+
+    fn weld(&mut self, position: [f32; 3], normal: [f32; 3]) -> Result<u32, ObjError> {
+        // adding 0.0 turns -0.0 into 0.0 so both weld together
+        let bits = |v: f32| (v + 0.0).to_bits();
+        let key = [
+            bits(position[0]),
+            bits(position[1]),
+            bits(position[2]),
+            bits(normal[0]),
+            bits(normal[1]),
+            bits(normal[2]),
+        ];
+        if let Some(&vertex) = self.welded.get(&key) {
+            return Ok(vertex);
+        }
+        let vertex = self.model.vertices.len() / 3;
+        self.limits.check_vertices(vertex + 1)?;
+        self.model.vertices.extend_from_slice(&position);
+        self.model
+            .normals
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(&normal);
+        self.welded.insert(key, vertex as u32);
+        Ok(vertex as u32)
+    }
+
+    fn finish(mut self) -> ModelData {
+        if let Some(mut submesh_builder) = self.submesh_builder.take() {
+            submesh_builder.close(&mut self.model.submeshes, self.model.indices.len() / 3);
+        }
+        generate_normals(&mut self.model, NormalWeighting::Angle);
+        self.model
+    }
+}
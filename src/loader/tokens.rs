@@ -0,0 +1,156 @@
+use super::ObjError;
+
+/**
+ * Splits a single line of a text model format into whitespace separated
+ * tokens, keeping track of where each token starts so parse errors can point
+ * at it
+ *
+ * Works on the raw bytes of the line: numbers are parsed straight from them,
+ * and only tokens that are used as text (names, paths) are checked to be
+ * valid UTF-8
+ */
+pub(super) struct LineTokens<'a> {
+    line: &'a [u8],
+    pub(super) line_number: usize,
+    offset: usize,
+}
+
+impl<'a> LineTokens<'a> {
+    pub(super) fn new(line: &'a [u8], line_number: usize) -> Self {
+        Self {
+            line,
+            line_number,
+            offset: 0,
+        }
+    }
+
+    /// returns the next token and its 1-based column
+    pub(super) fn next_token(&mut self) -> Option<(usize, &'a [u8])> {
+        let start = self.offset
+            + self.line[self.offset..]
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+        let token_len = self.line[start..]
+            .iter()
+            .take_while(|b| !b.is_ascii_whitespace())
+            .count();
+        self.offset = start + token_len;
+        if token_len == 0 {
+            return None;
+        }
+        Some((start + 1, &self.line[start..self.offset]))
+    }
+
+    /// the next token as text, failing if it isn't valid UTF-8
+    pub(super) fn next_str(&mut self) -> Result<Option<(usize, &'a str)>, ObjError> {
+        match self.next_token() {
+            Some((column, token)) => Ok(Some((column, self.text(column, token)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// everything after the current token, e.g. a name containing spaces
+    pub(super) fn rest(&self) -> Result<&'a str, ObjError> {
+        let start = self.offset
+            + self.line[self.offset..]
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+        self.text(start + 1, self.line[start..].trim_ascii_end())
+    }
+
+    /// checks that `token`, found at `column`, is valid UTF-8
+    pub(super) fn text(&self, column: usize, token: &'a [u8]) -> Result<&'a str, ObjError> {
+        std::str::from_utf8(token).map_err(|e| ObjError::InvalidUtf8 {
+            line: self.line_number,
+            column: column + e.valid_up_to(),
+            token: String::from_utf8_lossy(token).into_owned(),
+        })
+    }
+
+    pub(super) fn missing(&self, keyword: &str) -> ObjError {
+        ObjError::MissingCoordinate {
+            line: self.line_number,
+            column: self.line.trim_ascii_end().len() + 1,
+            token: keyword.to_string(),
+        }
+    }
+
+    /// the error for a token that isn't the number it should be
+    pub(super) fn bad_float(&self, column: usize, token: &[u8]) -> ObjError {
+        match std::str::from_utf8(token) {
+            Ok(token) => ObjError::BadFloat {
+                line: self.line_number,
+                column,
+                token: token.to_string(),
+            },
+            Err(e) => ObjError::InvalidUtf8 {
+                line: self.line_number,
+                column: column + e.valid_up_to(),
+                token: String::from_utf8_lossy(token).into_owned(),
+            },
+        }
+    }
+
+    pub(super) fn next_optional_f32(&mut self) -> Result<Option<f32>, ObjError> {
+        match self.next_token() {
+            Some((column, token)) => parse_f32(token)
+                .map(Some)
+                .ok_or_else(|| self.bad_float(column, token)),
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn next_f32(&mut self, keyword: &str) -> Result<f32, ObjError> {
+        let (column, token) = self.next_token().ok_or_else(|| self.missing(keyword))?;
+        parse_f32(token).ok_or_else(|| self.bad_float(column, token))
+    }
+
+    /// consumes the next token, which has to be `keyword`
+    pub(super) fn expect(&mut self, keyword: &'static str) -> Result<(), ObjError> {
+        match self.next_token() {
+            Some((_, token)) if token == keyword.as_bytes() => Ok(()),
+            Some((column, token)) => Err(ObjError::UnexpectedToken {
+                line: self.line_number,
+                column,
+                token: String::from_utf8_lossy(token).into_owned(),
+                expected: keyword,
+            }),
+            None => Err(ObjError::UnexpectedToken {
+                line: self.line_number,
+                column: self.line.trim_ascii_end().len() + 1,
+                token: String::new(),
+                expected: keyword,
+            }),
+        }
+    }
+}
+
+pub(super) fn parse_f32(token: &[u8]) -> Option<f32> {
+    fast_float2::parse(token).ok()
+}
+
+pub(super) fn parse_f64(token: &[u8]) -> Option<f64> {
+    fast_float2::parse(token).ok()
+}
+
+/// a decimal integer with an optional sign, `None` if it doesn't fit an i64
+pub(super) fn parse_i64(token: &[u8]) -> Option<i64> {
+    let (negative, digits) = match token.split_first()? {
+        (b'-', digits) => (true, digits),
+        (b'+', digits) => (false, digits),
+        _ => (false, token),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value: i64 = 0;
+    for &digit in digits {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((digit - b'0') as i64)?;
+    }
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,184 @@
+use glam::{Vec2, Vec3};
+
+/**
+ * Splits a polygon (a list of position indices) into triangles
+ *
+ * Convex polygons are fanned out from their first vertex. Concave polygons
+ * are projected onto their best-fit plane and ear-clipped. Winding order is
+ * preserved in both cases
+ *
+ * Returns `None` if the polygon is degenerate (zero area) or self-intersecting
+ * in a way that leaves no ear to clip
+ */
+pub fn triangulate(positions: &[[f32; 3]], polygon: &[u32]) -> Option<Vec<[u32; 3]>> {
+    if polygon.len() < 3 {
+        return None;
+    }
+    let points: Option<Vec<Vec3>> = polygon
+        .iter()
+        .map(|&i| positions.get(i as usize).map(|p| Vec3::from(*p)))
+        .collect();
+    let mut triangles = Vec::with_capacity(polygon.len() - 2);
+    match points {
+        Some(points) => {
+            if !triangulate_points(&points, &mut triangles) {
+                return None;
+            }
+        }
+        // without positions there is nothing to test convexity against
+        None => fan(polygon.len(), &mut triangles),
+    }
+    for triangle in &mut triangles {
+        *triangle = triangle.map(|corner| polygon[corner as usize]);
+    }
+    Some(triangles)
+}
+
+/// polygons with up to this many corners are triangulated without allocating
+const STACK_CORNERS: usize = 8;
+
+/**
+ * Like `triangulate`, for a polygon given by its corner positions
+ *
+ * Appends triangles of indices into `corners` to `triangles` and returns
+ * whether the polygon could be triangulated. Triangles and small convex
+ * polygons don't allocate, which is what keeps the OBJ face loop free of
+ * per-line allocations
+ */
+pub(super) fn triangulate_corners(corners: &[[f32; 3]], triangles: &mut Vec<[u32; 3]>) -> bool {
+    if corners.len() <= STACK_CORNERS {
+        let mut points = [Vec3::ZERO; STACK_CORNERS];
+        for (point, corner) in points.iter_mut().zip(corners) {
+            *point = Vec3::from(*corner);
+        }
+        triangulate_points(&points[..corners.len()], triangles)
+    } else {
+        let points: Vec<Vec3> = corners.iter().copied().map(Vec3::from).collect();
+        triangulate_points(&points, triangles)
+    }
+}
+
+/// appends triangles of indices into `points`, returns false if there are none
+fn triangulate_points(points: &[Vec3], triangles: &mut Vec<[u32; 3]>) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+    let normal = newell_normal(points);
+    // twice the area, compared with the polygon's own size so that small
+    // faces of models in large units aren't mistaken for slivers
+    let longest_edge_squared = (0..points.len())
+        .map(|i| points[i].distance_squared(points[(i + 1) % points.len()]))
+        .fold(0.0, f32::max);
+    if normal.length() <= longest_edge_squared * f32::EPSILON {
+        return false;
+    }
+    if points.len() == 3 {
+        triangles.push([0, 1, 2]);
+        return true;
+    }
+    let normal = normal.normalize();
+
+    let mut stack_projected = [Vec2::ZERO; STACK_CORNERS];
+    let mut heap_projected;
+    let projected = if points.len() <= STACK_CORNERS {
+        &mut stack_projected[..points.len()]
+    } else {
+        heap_projected = vec![Vec2::ZERO; points.len()];
+        &mut heap_projected[..]
+    };
+    project_onto_plane(points, normal, projected);
+
+    if is_convex(projected) {
+        fan(points.len(), triangles);
+        true
+    } else {
+        ear_clip(projected, triangles)
+    }
+}
+
+fn fan(corner_count: usize, triangles: &mut Vec<[u32; 3]>) {
+    triangles.extend((1..corner_count as u32 - 1).map(|i| [0, i, i + 1]));
+}
+
+/// area-weighted normal of a (possibly non-planar) polygon
+fn newell_normal(points: &[Vec3]) -> Vec3 {
+    // relative to the first corner, so polygons far from the origin don't
+    // lose their area to cancellation
+    let origin = points[0];
+    let mut normal = Vec3::ZERO;
+    for (i, current) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        normal += (*current - origin).cross(next - origin);
+    }
+    normal
+}
+
+/// projects points into a 2D basis in which the polygon winds counter-clockwise
+fn project_onto_plane(points: &[Vec3], normal: Vec3, projected: &mut [Vec2]) {
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    for (projected, p) in projected.iter_mut().zip(points) {
+        *projected = Vec2::new(p.dot(u), p.dot(v));
+    }
+}
+
+fn is_convex(points: &[Vec2]) -> bool {
+    let n = points.len();
+    (0..n).all(|i| {
+        let prev = points[(i + n - 1) % n];
+        let current = points[i];
+        let next = points[(i + 1) % n];
+        (current - prev).perp_dot(next - current) >= 0.0
+    })
+}
+
+/// appends the ears of `points` to `triangles`, leaving it untouched on failure
+fn ear_clip(points: &[Vec2], triangles: &mut Vec<[u32; 3]>) -> bool {
+    let mut remaining: Vec<u32> = (0..points.len() as u32).collect();
+    let first_triangle = triangles.len();
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let a = points[remaining[(i + n - 1) % n] as usize];
+            let b = points[remaining[i] as usize];
+            let c = points[remaining[(i + 1) % n] as usize];
+            if (b - a).perp_dot(c - b) <= 0.0 {
+                return false;
+            }
+            remaining
+                .iter()
+                .filter(|&&j| {
+                    j != remaining[(i + n - 1) % n]
+                        && j != remaining[i]
+                        && j != remaining[(i + 1) % n]
+                })
+                .all(|&j| !point_in_triangle(points[j as usize], a, b, c))
+        });
+        let ear = match ear {
+            Some(ear) => ear,
+            None => {
+                triangles.truncate(first_triangle);
+                return false;
+            }
+        };
+
+        triangles.push([
+            remaining[(ear + n - 1) % n],
+            remaining[ear],
+            remaining[(ear + 1) % n],
+        ]);
+        remaining.remove(ear);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    true
+}
+
+/// inclusive of edges so that ears touching another vertex are rejected
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let d1 = (b - a).perp_dot(p - a);
+    let d2 = (c - b).perp_dot(p - b);
+    let d3 = (a - c).perp_dot(p - c);
+    d1 >= 0.0 && d2 >= 0.0 && d3 >= 0.0
+}
@@ -0,0 +1,133 @@
+use super::{
+    load_archive, Archive, LoadLimits, MemoryResolver, ModelData, ModelFormat, ObjError, ObjParser,
+    Progress,
+};
+
+/// size of the slices an uploaded model is read and parsed in
+pub(super) const MODEL_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// how the uploaded model is being parsed, decided once its first slice arrives
+enum ModelReader {
+    /// nothing has arrived yet, holds the rest of the upload
+    Undetected(MemoryResolver),
+    /// OBJ is parsed slice by slice as it arrives
+    Streaming(Box<ObjParser<MemoryResolver>>),
+    /// formats that are parsed in one go, collected until the last slice
+    Buffered {
+        format: ModelFormat,
+        bytes: Vec<u8>,
+        files: MemoryResolver,
+    },
+    /// a zip or gzip upload, unpacked once it has been read completely
+    Archive {
+        archive: Archive,
+        bytes: Vec<u8>,
+        files: MemoryResolver,
+    },
+}
+
+/**
+ * Parses an uploaded model from the slices it is read in, with the rest of
+ * the upload (MTL libraries, textures) already in memory
+ *
+ * The format is taken from the magic bytes of the first slice, falling back
+ * to the file extension; OBJ slices are parsed as soon as they are pushed,
+ * other formats and archives once `finish` is called
+ *
+ * This is what the model worker runs, `parse_upload` runs it synchronously
+ */
+pub struct UploadParser {
+    name: String,
+    size: u64,
+    limits: LoadLimits,
+    reader: ModelReader,
+}
+
+impl UploadParser {
+    /// `name` and `size` are those of the whole model file
+    pub fn new(name: &str, size: u64, files: MemoryResolver) -> Self {
+        Self {
+            name: name.to_string(),
+            size,
+            limits: LoadLimits::default(),
+            reader: ModelReader::Undetected(files),
+        }
+    }
+
+    /// replaces the default `LoadLimits`
+    pub fn with_limits(mut self, limits: LoadLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// feeds the next slice of the model, returning the parser's progress if it streams
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Option<Progress>, ObjError> {
+        if let ModelReader::Undetected(files) = &mut self.reader {
+            let files = std::mem::take(files);
+            let bytes = Vec::with_capacity(self.size.min(self.limits.max_file_bytes) as usize);
+            self.reader = match Archive::detect(&self.name, chunk) {
+                Some(archive) => ModelReader::Archive {
+                    archive,
+                    bytes,
+                    files,
+                },
+                None => match ModelFormat::detect(&self.name, chunk, self.size) {
+                    ModelFormat::Obj => ModelReader::Streaming(Box::new(
+                        ObjParser::new(files)
+                            .with_limits(self.limits)
+                            .with_size_hint(self.size),
+                    )),
+                    format => ModelReader::Buffered {
+                        format,
+                        bytes,
+                        files,
+                    },
+                },
+            };
+        }
+        match &mut self.reader {
+            ModelReader::Undetected(_) => unreachable!("format was detected above"),
+            ModelReader::Streaming(parser) => parser.push(chunk).map(Some),
+            ModelReader::Buffered { bytes, .. } | ModelReader::Archive { bytes, .. } => {
+                self.limits
+                    .check_file_size((bytes.len() + chunk.len()) as u64)?;
+                bytes.extend_from_slice(chunk);
+                Ok(None)
+            }
+        }
+    }
+
+    /// parses whatever hasn't been yet and assembles the model
+    pub fn finish(self) -> Result<ModelData, ObjError> {
+        match self.reader {
+            ModelReader::Undetected(files) => ObjParser::new(files).finish(),
+            ModelReader::Streaming(parser) => parser.finish(),
+            ModelReader::Buffered {
+                format,
+                bytes,
+                files,
+            } => format.load(&bytes, &files, &self.limits),
+            ModelReader::Archive {
+                archive,
+                bytes,
+                files,
+            } => load_archive(archive, &self.name, &bytes, &files, &self.limits),
+        }
+    }
+}
+
+/**
+ * Parses a whole uploaded model on the calling thread, in the same slices
+ * and with the same format detection as the model worker
+ */
+pub fn parse_upload(
+    name: &str,
+    bytes: &[u8],
+    files: MemoryResolver,
+) -> Result<ModelData, ObjError> {
+    let mut parser = UploadParser::new(name, bytes.len() as u64, files);
+    for chunk in bytes.chunks(MODEL_CHUNK_BYTES) {
+        parser.push(chunk)?;
+    }
+    parser.finish()
+}
@@ -0,0 +1,10 @@
+// Entry point of the model worker, see `src/loader/worker.rs`
+//
+// The page passes the URL of the generated bindings in the `bindings` query
+// parameter, wherever the bundle put them. Initializing them runs the
+// module's start function, which notices it is in a worker and waits for an
+// upload instead of setting up the page
+const bindings = new URL(import.meta.url).searchParams.get("bindings");
+const { default: init } = await import(bindings);
+
+await init();
@@ -0,0 +1,762 @@
+use std::{cell::RefCell, io};
+
+use js_sys::{
+    Array, ArrayBuffer, Float32Array, Float64Array, Object, Reflect, Uint32Array, Uint8Array,
+};
+use wasm_bindgen::{
+    prelude::{wasm_bindgen, Closure},
+    JsCast, JsValue,
+};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    Blob, Cache, DedicatedWorkerGlobalScope, File, FileReaderSync, MessageEvent, Response, Worker,
+    WorkerOptions, WorkerType,
+};
+
+use super::{
+    decode_lods, encode_lods, upload::MODEL_CHUNK_BYTES, CacheKeyBuilder, EmbeddedFile, Material,
+    MemoryResolver, ModelData, ObjError, PbrMaterial, Progress, Submesh, UploadParser,
+    VertexAttribute,
+};
+use crate::{
+    geometry::{generate_lods, generate_tangents, optimize_mesh, LodLevel, LodOptions},
+    log,
+};
+
+/*
+ * Messages between the page and the model worker are plain objects with a
+ * `kind`:
+ *
+ * page to worker
+ *   `upload`: `{ model: File, files: File[] }`, the model and the files it
+ *   may reference
+ *
+ * worker to page
+ *   `ready`: the worker has started and can take the upload
+ *   `progress`: `{ bytes, total, vertices?, faces? }` after each slice of the
+ *   model, with counts for formats that are parsed as they stream in
+ *   `error`: `{ message }`, why the upload couldn't be loaded
+ *   `model`: `{ levels, cached }`, the levels of detail of the model, the
+ *   full model first, each a `ModelData` with its `error` (only the full
+ *   model has materials and embedded files), every vertex and index stream
+ *   in an `ArrayBuffer` that is transferred rather than copied;
+ *   `cached` is whether they came from the model cache
+ */
+
+/// name of the Cache Storage cache that parsed models are kept in
+const MODEL_CACHE_NAME: &str = "models";
+
+#[wasm_bindgen]
+extern "C" {
+    // `import.meta` is read in the generated bindings, so this is their URL
+    #[wasm_bindgen(thread_local_v2, js_namespace = ["import", "meta"], js_name = url)]
+    static BINDINGS_URL: String;
+}
+
+thread_local! {
+    // the worker parsing the current upload, terminated when a new one starts
+    static CURRENT_WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
+}
+
+/// whether this instance of the module is running inside the model worker
+pub fn is_model_worker() -> bool {
+    js_sys::global()
+        .dyn_into::<DedicatedWorkerGlobalScope>()
+        .is_ok()
+}
+
+/**
+ * Starts a worker that reads and parses `model` together with `files`,
+ * logging its progress and handing the model's levels of detail to `on_model`
+ *
+ * Any worker still busy with an earlier upload is terminated
+ */
+pub(super) fn spawn_model_worker(
+    model: File,
+    files: Vec<File>,
+    on_model: impl Fn(Vec<LodLevel>) + 'static,
+) -> Result<(), JsValue> {
+    let options = WorkerOptions::new();
+    options.set_type(WorkerType::Module);
+    // worker.js imports the bindings from wherever they ended up
+    let script = format!(
+        "{}?bindings={}",
+        wasm_bindgen::link_to!(module = "/src/loader/worker.js"),
+        String::from(js_sys::encode_uri_component(
+            &BINDINGS_URL.with(String::clone)
+        ))
+    );
+    let worker = Worker::new_with_options(&script, &options)?;
+
+    let upload = message("upload");
+    set(&upload, "model", &model);
+    set(&upload, "files", &files.iter().collect::<Array>());
+
+    let message_worker = worker.clone();
+    let onmessage_cb = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let data = event.data();
+        let kind = get(&data, "kind").ok().and_then(|kind| kind.as_string());
+        match kind.as_deref() {
+            Some("ready") => {
+                if let Err(e) = message_worker.post_message(&upload) {
+                    log!("Failed to send the upload to the model worker: {:?}", e);
+                }
+            }
+            Some("progress") => {
+                let number = |key| get(&data, key).ok().and_then(|value| value.as_f64());
+                match (number("vertices"), number("faces")) {
+                    (Some(vertices), Some(faces)) => log!(
+                        "Parsed {} of {} bytes: {} vertices, {} faces",
+                        number("bytes").unwrap_or_default(),
+                        number("total").unwrap_or_default(),
+                        vertices,
+                        faces
+                    ),
+                    _ => log!(
+                        "Read {} of {} bytes",
+                        number("bytes").unwrap_or_default(),
+                        number("total").unwrap_or_default()
+                    ),
+                }
+            }
+            Some("error") => {
+                message_worker.terminate();
+                let message = get(&data, "message")
+                    .ok()
+                    .and_then(|message| message.as_string())
+                    .unwrap_or_default();
+                log!("Failed to parse model: {}", message);
+            }
+            Some("model") => {
+                message_worker.terminate();
+                if get(&data, "cached").is_ok_and(|cached| cached.is_truthy()) {
+                    log!("Loaded model from cache");
+                }
+                match lods_from_message(&data) {
+                    Ok(levels) => on_model(levels),
+                    Err(e) => log!("Model worker sent a malformed model: {:?}", e),
+                }
+            }
+            _ => log!("Unexpected message from the model worker: {:?}", data),
+        }
+    }) as Box<dyn Fn(MessageEvent)>);
+    let onerror_cb = Closure::wrap(Box::new(move |event: JsValue| {
+        log!("Model worker failed: {:?}", event);
+    }) as Box<dyn Fn(JsValue)>);
+
+    worker.set_onmessage(Some(onmessage_cb.as_ref().unchecked_ref()));
+    worker.set_onerror(Some(onerror_cb.as_ref().unchecked_ref()));
+    onmessage_cb.forget();
+    onerror_cb.forget();
+
+    CURRENT_WORKER.with(|current| {
+        if let Some(previous) = current.borrow_mut().replace(worker) {
+            previous.terminate();
+        }
+    });
+    Ok(())
+}
+
+/**
+ * Runs inside the worker: waits for an upload, loads its model from the cache
+ * or parses it slice by slice and prepares it for drawing, and posts the
+ * finished levels of detail back to the page
+ */
+pub fn run_model_worker() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let reply_scope = scope.clone();
+    let onmessage_cb = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let scope = reply_scope.clone();
+        spawn_local(async move {
+            let posted = match load_upload_message(&event.data(), &scope).await {
+                Ok((levels, cached)) => {
+                    let (message, transfer) = lods_to_message(levels);
+                    set(&message, "cached", &cached.into());
+                    scope.post_message_with_transfer(&message, &transfer)
+                }
+                Err(e) => {
+                    let message = message("error");
+                    set(&message, "message", &e.to_string().into());
+                    scope.post_message(&message)
+                }
+            };
+            if let Err(e) = posted {
+                log!("Model worker failed to reply: {:?}", e);
+            }
+        });
+    }) as Box<dyn Fn(MessageEvent)>);
+    scope.set_onmessage(Some(onmessage_cb.as_ref().unchecked_ref()));
+    onmessage_cb.forget();
+
+    if let Err(e) = scope.post_message(&message("ready")) {
+        log!("Model worker failed to start: {:?}", e);
+    }
+}
+
+/**
+ * Reads the files of an `upload` message and loads its model as a chain of
+ * levels of detail, returning whether they came from the model cache
+ *
+ * The other files are read into memory for the model to refer to, while the
+ * model itself is only ever read a slice at a time: once to compute the cache
+ * key and, if it isn't cached, once more to parse it. Models that had to be
+ * parsed get tangents, are simplified into levels of detail, each reordered
+ * for the GPU's vertex cache, and added to the cache keyed by the contents
+ * of the whole upload. The cache is only an optimization: when it can't be
+ * used the model is simply parsed again
+ */
+async fn load_upload_message(
+    data: &JsValue,
+    scope: &DedicatedWorkerGlobalScope,
+) -> Result<(Vec<LodLevel>, bool), ObjError> {
+    let model: File = get(data, "model")
+        .and_then(|model| model.dyn_into())
+        .map_err(read_error)?;
+    let others: Array = get(data, "files")
+        .and_then(|files| files.dyn_into())
+        .map_err(read_error)?;
+    let reader = FileReaderSync::new().map_err(read_error)?;
+    let read = |blob: &Blob| -> Result<Vec<u8>, ObjError> {
+        let contents = reader.read_as_array_buffer(blob).map_err(read_error)?;
+        Ok(Uint8Array::new(&contents).to_vec())
+    };
+    let size = model.size() as usize;
+    let slices = || (0..size).step_by(MODEL_CHUNK_BYTES);
+    let read_slice = |start: usize| -> Result<Vec<u8>, ObjError> {
+        let end = (start + MODEL_CHUNK_BYTES).min(size);
+        let slice = model
+            .slice_with_f64_and_f64(start as f64, end as f64)
+            .map_err(read_error)?;
+        read(&slice)
+    };
+
+    let mut uploaded = Vec::with_capacity(others.length() as usize);
+    for file in others.iter() {
+        let file: File = file.dyn_into().map_err(read_error)?;
+        uploaded.push((file.name(), read(&file)?));
+    }
+    let name = model.name();
+
+    // the same key as `cache_key`: the model, then the other files in name order
+    let mut key = CacheKeyBuilder::new();
+    key.start_model(&name, size as u64);
+    for start in slices() {
+        key.update(&read_slice(start)?);
+    }
+    let mut in_name_order: Vec<&(String, Vec<u8>)> = uploaded.iter().collect();
+    in_name_order.sort_unstable();
+    for (file_name, contents) in in_name_order {
+        key.start_file(file_name, contents.len() as u64);
+        key.update(contents);
+    }
+    let key = key.finish();
+
+    match cached_model(scope, key).await {
+        Ok(Some(cached)) => match decode_lods(&cached) {
+            Ok(levels) => return Ok((levels, true)),
+            Err(e) => log!("Ignoring cached model: {}", e),
+        },
+        Ok(None) => {}
+        Err(e) => log!("Model cache is unavailable: {:?}", e),
+    }
+
+    let mut files = MemoryResolver::new();
+    for (name, contents) in uploaded {
+        if let Err(e) = files.insert(&name, contents) {
+            log!("Ignoring uploaded file: {}", e);
+        }
+    }
+    let mut parser = UploadParser::new(&name, size as u64, files);
+    for start in slices() {
+        let slice = read_slice(start)?;
+        let progress = parser.push(&slice)?;
+        let parsed = start + slice.len();
+        if parsed < size {
+            post_progress(scope, parsed as f64, size as f64, progress);
+        }
+    }
+    let mut model = parser.finish()?;
+
+    // normal maps need tangents, and the simplifier carries them to every level
+    if model.texcoords.is_some() {
+        generate_tangents(&mut model);
+    }
+    let mut levels = generate_lods(model, &LodOptions::default());
+    // file order is rarely kind to the GPU's vertex cache
+    for (index, level) in levels.iter_mut().enumerate() {
+        let report = optimize_mesh(&mut level.model);
+        log!(
+            "level {}: {} triangles, ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}",
+            index,
+            level.model.indices.len() / 3,
+            report.before.acmr,
+            report.after.acmr,
+            report.before.atvr,
+            report.after.atvr
+        );
+    }
+    if let Err(e) = store_model(scope, key, encode_lods(&levels, key)).await {
+        log!("Failed to cache the model: {:?}", e);
+    }
+    Ok((levels, false))
+}
+
+/// the entry of the model cache that `key` is stored under
+fn cache_url(key: u64) -> String {
+    format!("model-cache/{:016x}", key)
+}
+
+async fn open_model_cache(scope: &DedicatedWorkerGlobalScope) -> Result<Cache, JsValue> {
+    let caches = scope.caches()?;
+    JsFuture::from(caches.open(MODEL_CACHE_NAME))
+        .await?
+        .dyn_into()
+}
+
+/// the cached model for an upload with `key`, if there is one
+async fn cached_model(
+    scope: &DedicatedWorkerGlobalScope,
+    key: u64,
+) -> Result<Option<Vec<u8>>, JsValue> {
+    let cache = open_model_cache(scope).await?;
+    let response = JsFuture::from(cache.match_with_str(&cache_url(key))).await?;
+    if response.is_undefined() {
+        return Ok(None);
+    }
+    let response: Response = response.dyn_into()?;
+    let contents = JsFuture::from(response.array_buffer()?).await?;
+    Ok(Some(Uint8Array::new(&contents).to_vec()))
+}
+
+async fn store_model(
+    scope: &DedicatedWorkerGlobalScope,
+    key: u64,
+    mut encoded: Vec<u8>,
+) -> Result<(), JsValue> {
+    let cache = open_model_cache(scope).await?;
+    let response = Response::new_with_opt_u8_array(Some(&mut encoded))?;
+    JsFuture::from(cache.put_with_str(&cache_url(key), &response)).await?;
+    Ok(())
+}
+
+fn post_progress(
+    scope: &DedicatedWorkerGlobalScope,
+    bytes: f64,
+    total: f64,
+    progress: Option<Progress>,
+) {
+    let message = message("progress");
+    set(&message, "bytes", &bytes.into());
+    set(&message, "total", &total.into());
+    if let Some(progress) = progress {
+        set(&message, "vertices", &(progress.vertices as f64).into());
+        set(&message, "faces", &(progress.faces as f64).into());
+    }
+    // progress is only informative, a lost message doesn't matter
+    let _ = scope.post_message(&message);
+}
+
+/// a failure of the browser APIs the worker reads the upload with
+fn read_error(e: JsValue) -> ObjError {
+    ObjError::Io(io::Error::other(format!("{:?}", e)))
+}
+
+fn message(kind: &str) -> Object {
+    let message = Object::new();
+    set(&message, "kind", &kind.into());
+    message
+}
+
+fn set(object: &Object, key: &str, value: &JsValue) {
+    Reflect::set(object, &key.into(), value).expect("properties can be set on plain objects");
+}
+
+fn get(object: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    Reflect::get(object, &key.into())
+}
+
+fn optional_string(value: &Option<String>) -> JsValue {
+    value.as_deref().map_or(JsValue::UNDEFINED, JsValue::from)
+}
+
+fn get_string(object: &JsValue, key: &str) -> Result<String, JsValue> {
+    get(object, key)?
+        .as_string()
+        .ok_or_else(|| JsValue::from(format!("`{}` is not a string", key)))
+}
+
+fn get_optional_string(object: &JsValue, key: &str) -> Result<Option<String>, JsValue> {
+    Ok(get(object, key)?.as_string())
+}
+
+fn get_f32(object: &JsValue, key: &str) -> Result<f32, JsValue> {
+    get(object, key)?
+        .as_f64()
+        .map(|value| value as f32)
+        .ok_or_else(|| JsValue::from(format!("`{}` is not a number", key)))
+}
+
+fn get_usize(object: &JsValue, key: &str) -> Result<usize, JsValue> {
+    get(object, key)?
+        .as_f64()
+        .map(|value| value as usize)
+        .ok_or_else(|| JsValue::from(format!("`{}` is not a number", key)))
+}
+
+fn get_array(object: &JsValue, key: &str) -> Result<Array, JsValue> {
+    get(object, key)?.dyn_into()
+}
+
+/// copies `values` into a new `ArrayBuffer` outside of wasm memory, which can be transferred
+fn f32_buffer(values: &[f32], transfer: &Array) -> JsValue {
+    let array = Float32Array::new_with_length(values.len() as u32);
+    array.copy_from(values);
+    let buffer = array.buffer();
+    transfer.push(&buffer);
+    buffer.into()
+}
+
+fn u32_buffer(values: &[u32], transfer: &Array) -> JsValue {
+    let array = Uint32Array::new_with_length(values.len() as u32);
+    array.copy_from(values);
+    let buffer = array.buffer();
+    transfer.push(&buffer);
+    buffer.into()
+}
+
+fn u8_buffer(values: &[u8], transfer: &Array) -> JsValue {
+    let array = Uint8Array::new_with_length(values.len() as u32);
+    array.copy_from(values);
+    let buffer = array.buffer();
+    transfer.push(&buffer);
+    buffer.into()
+}
+
+fn get_f32s(object: &JsValue, key: &str) -> Result<Vec<f32>, JsValue> {
+    let buffer: ArrayBuffer = get(object, key)?.dyn_into()?;
+    Ok(Float32Array::new(&buffer).to_vec())
+}
+
+fn get_optional_f32s(object: &JsValue, key: &str) -> Result<Option<Vec<f32>>, JsValue> {
+    match get(object, key)?.dyn_into::<ArrayBuffer>() {
+        Ok(buffer) => Ok(Some(Float32Array::new(&buffer).to_vec())),
+        Err(_) => Ok(None),
+    }
+}
+
+fn get_u32s(object: &JsValue, key: &str) -> Result<Vec<u32>, JsValue> {
+    let buffer: ArrayBuffer = get(object, key)?.dyn_into()?;
+    Ok(Uint32Array::new(&buffer).to_vec())
+}
+
+fn get_u8s(object: &JsValue, key: &str) -> Result<Vec<u8>, JsValue> {
+    let buffer: ArrayBuffer = get(object, key)?.dyn_into()?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+/**
+ * The `model` message for `levels` and the buffers to transfer with it
+ *
+ * Every stream is copied once, out of the worker's wasm memory into a buffer
+ * of its own, and then moved to the page without another copy
+ */
+fn lods_to_message(levels: Vec<LodLevel>) -> (Object, Array) {
+    let transfer = Array::new();
+    let message = message("model");
+    let levels: Array = levels
+        .into_iter()
+        .map(|level| {
+            let object = model_to_js(&level.model, &transfer);
+            set(&object, "error", &level.error.into());
+            JsValue::from(object)
+        })
+        .collect();
+    set(&message, "levels", &levels);
+    (message, transfer)
+}
+
+/// the levels of detail of a `model` message, copied into this thread's wasm memory
+fn lods_from_message(message: &JsValue) -> Result<Vec<LodLevel>, JsValue> {
+    get_array(message, "levels")?
+        .iter()
+        .map(|level| {
+            Ok(LodLevel {
+                model: model_from_js(&level)?,
+                error: get_f32(&level, "error")?,
+            })
+        })
+        .collect()
+}
+
+/// `model` as a plain object, its streams added to `transfer`
+fn model_to_js(model: &ModelData, transfer: &Array) -> Object {
+    let object = Object::new();
+    let optional = |values: &Option<Vec<f32>>| {
+        values
+            .as_deref()
+            .map_or(JsValue::UNDEFINED, |values| f32_buffer(values, transfer))
+    };
+    set(&object, "vertices", &f32_buffer(&model.vertices, transfer));
+    set(&object, "texcoords", &optional(&model.texcoords));
+    set(&object, "normals", &optional(&model.normals));
+    set(&object, "tangents", &optional(&model.tangents));
+    set(&object, "colors", &optional(&model.colors));
+    set(&object, "indices", &u32_buffer(&model.indices, transfer));
+    set(
+        &object,
+        "smoothingGroups",
+        &u32_buffer(&model.smoothing_groups, transfer),
+    );
+    set(
+        &object,
+        "lineIndices",
+        &u32_buffer(&model.line_indices, transfer),
+    );
+    set(
+        &object,
+        "pointIndices",
+        &u32_buffer(&model.point_indices, transfer),
+    );
+
+    let attributes: Array = model
+        .attributes
+        .iter()
+        .map(|attribute| {
+            let object = Object::new();
+            set(&object, "name", &attribute.name.as_str().into());
+            set(&object, "values", &f32_buffer(&attribute.values, transfer));
+            JsValue::from(object)
+        })
+        .collect();
+    set(&object, "attributes", &attributes);
+
+    let embedded_files: Array = model
+        .embedded_files
+        .iter()
+        .map(|file| {
+            let object = Object::new();
+            set(&object, "path", &file.path.as_str().into());
+            set(&object, "mimeType", &optional_string(&file.mime_type));
+            set(&object, "contents", &u8_buffer(&file.contents, transfer));
+            JsValue::from(object)
+        })
+        .collect();
+    set(&object, "embeddedFiles", &embedded_files);
+
+    let submeshes: Array = model
+        .submeshes
+        .iter()
+        .map(|submesh| {
+            let object = Object::new();
+            set(&object, "name", &submesh.name.as_str().into());
+            set(&object, "material", &optional_string(&submesh.material));
+            set(&object, "start", &(submesh.index_range.start as f64).into());
+            set(&object, "end", &(submesh.index_range.end as f64).into());
+            set(&object, "visible", &submesh.visible.into());
+            JsValue::from(object)
+        })
+        .collect();
+    set(&object, "submeshes", &submeshes);
+
+    let materials: Array = model.materials.iter().map(material_to_js).collect();
+    set(&object, "materials", &materials);
+    let missing_files: Array = model
+        .missing_files
+        .iter()
+        .map(|path| JsValue::from(path.as_str()))
+        .collect();
+    set(&object, "missingFiles", &missing_files);
+    let untriangulated_faces: Vec<f64> = model
+        .untriangulated_faces
+        .iter()
+        .map(|&line| line as f64)
+        .collect();
+    set(
+        &object,
+        "untriangulatedFaces",
+        &Float64Array::from(&untriangulated_faces[..]),
+    );
+    object
+}
+
+/// the `ModelData` of an object made by `model_to_js`
+fn model_from_js(object: &JsValue) -> Result<ModelData, JsValue> {
+    let attributes = get_array(object, "attributes")?
+        .iter()
+        .map(|attribute| {
+            Ok(VertexAttribute {
+                name: get_string(&attribute, "name")?,
+                values: get_f32s(&attribute, "values")?,
+            })
+        })
+        .collect::<Result<_, JsValue>>()?;
+    let embedded_files = get_array(object, "embeddedFiles")?
+        .iter()
+        .map(|file| {
+            Ok(EmbeddedFile {
+                path: get_string(&file, "path")?,
+                mime_type: get_optional_string(&file, "mimeType")?,
+                contents: get_u8s(&file, "contents")?,
+            })
+        })
+        .collect::<Result<_, JsValue>>()?;
+    let submeshes = get_array(object, "submeshes")?
+        .iter()
+        .map(|submesh| {
+            Ok(Submesh {
+                name: get_string(&submesh, "name")?,
+                material: get_optional_string(&submesh, "material")?,
+                index_range: get_usize(&submesh, "start")?..get_usize(&submesh, "end")?,
+                visible: get(&submesh, "visible")?.as_bool().unwrap_or(true),
+            })
+        })
+        .collect::<Result<_, JsValue>>()?;
+    let materials = get_array(object, "materials")?
+        .iter()
+        .map(|material| material_from_js(&material))
+        .collect::<Result<_, JsValue>>()?;
+    let missing_files = get_array(object, "missingFiles")?
+        .iter()
+        .filter_map(|path| path.as_string())
+        .collect();
+    let untriangulated_faces: Float64Array = get(object, "untriangulatedFaces")?.dyn_into()?;
+
+    Ok(ModelData {
+        vertices: get_f32s(object, "vertices")?,
+        texcoords: get_optional_f32s(object, "texcoords")?,
+        normals: get_optional_f32s(object, "normals")?,
+        tangents: get_optional_f32s(object, "tangents")?,
+        colors: get_optional_f32s(object, "colors")?,
+        attributes,
+        indices: get_u32s(object, "indices")?,
+        smoothing_groups: get_u32s(object, "smoothingGroups")?,
+        line_indices: get_u32s(object, "lineIndices")?,
+        point_indices: get_u32s(object, "pointIndices")?,
+        submeshes,
+        materials,
+        embedded_files,
+        missing_files,
+        untriangulated_faces: untriangulated_faces
+            .to_vec()
+            .into_iter()
+            .map(|line| line as usize)
+            .collect(),
+    })
+}
+
+fn f32s_to_js(values: &[f32]) -> JsValue {
+    Float32Array::from(values).into()
+}
+
+fn f32s_from_js<const N: usize>(object: &JsValue, key: &str) -> Result<[f32; N], JsValue> {
+    let array: Float32Array = get(object, key)?.dyn_into()?;
+    let mut values = [0.0; N];
+    if array.length() as usize != N {
+        return Err(JsValue::from(format!("`{}` should have {} values", key, N)));
+    }
+    array.copy_to(&mut values);
+    Ok(values)
+}
+
+fn material_to_js(material: &Material) -> JsValue {
+    let object = Object::new();
+    set(&object, "name", &material.name.as_str().into());
+    set(&object, "ambient", &f32s_to_js(&material.ambient));
+    set(&object, "diffuse", &f32s_to_js(&material.diffuse));
+    set(&object, "specular", &f32s_to_js(&material.specular));
+    set(&object, "shininess", &material.shininess.into());
+    set(&object, "dissolve", &material.dissolve.into());
+    set(&object, "opticalDensity", &material.optical_density.into());
+    set(
+        &object,
+        "illuminationModel",
+        &material
+            .illumination_model
+            .map_or(JsValue::UNDEFINED, JsValue::from),
+    );
+    let textures = [
+        ("ambientTexture", &material.ambient_texture),
+        ("diffuseTexture", &material.diffuse_texture),
+        ("specularTexture", &material.specular_texture),
+        ("normalTexture", &material.normal_texture),
+        ("shininessTexture", &material.shininess_texture),
+        ("dissolveTexture", &material.dissolve_texture),
+    ];
+    for (key, texture) in textures {
+        set(&object, key, &optional_string(texture));
+    }
+    let unknown_params = Object::new();
+    for (key, value) in &material.unknown_params {
+        set(&unknown_params, key, &value.as_str().into());
+    }
+    set(&object, "unknownParams", &unknown_params);
+
+    if let Some(pbr) = &material.pbr {
+        let pbr_object = Object::new();
+        set(&pbr_object, "baseColor", &f32s_to_js(&pbr.base_color));
+        set(&pbr_object, "metallic", &pbr.metallic.into());
+        set(&pbr_object, "roughness", &pbr.roughness.into());
+        set(&pbr_object, "emissive", &f32s_to_js(&pbr.emissive));
+        let textures = [
+            ("metallicRoughnessTexture", &pbr.metallic_roughness_texture),
+            ("occlusionTexture", &pbr.occlusion_texture),
+            ("emissiveTexture", &pbr.emissive_texture),
+        ];
+        for (key, texture) in textures {
+            set(&pbr_object, key, &optional_string(texture));
+        }
+        set(&pbr_object, "doubleSided", &pbr.double_sided.into());
+        set(&object, "pbr", &pbr_object);
+    }
+    object.into()
+}
+
+fn material_from_js(object: &JsValue) -> Result<Material, JsValue> {
+    let unknown_params = get(object, "unknownParams")?;
+    let unknown_params = Object::entries(unknown_params.unchecked_ref())
+        .iter()
+        .map(|entry| {
+            let entry: Array = entry.unchecked_into();
+            (
+                entry.get(0).as_string().unwrap_or_default(),
+                entry.get(1).as_string().unwrap_or_default(),
+            )
+        })
+        .collect();
+    let pbr = get(object, "pbr")?;
+    let pbr = if pbr.is_object() {
+        Some(PbrMaterial {
+            base_color: f32s_from_js(&pbr, "baseColor")?,
+            metallic: get_f32(&pbr, "metallic")?,
+            roughness: get_f32(&pbr, "roughness")?,
+            emissive: f32s_from_js(&pbr, "emissive")?,
+            metallic_roughness_texture: get_optional_string(&pbr, "metallicRoughnessTexture")?,
+            occlusion_texture: get_optional_string(&pbr, "occlusionTexture")?,
+            emissive_texture: get_optional_string(&pbr, "emissiveTexture")?,
+            double_sided: get(&pbr, "doubleSided")?.is_truthy(),
+        })
+    } else {
+        None
+    };
+
+    Ok(Material {
+        name: get_string(object, "name")?,
+        ambient: f32s_from_js(object, "ambient")?,
+        diffuse: f32s_from_js(object, "diffuse")?,
+        specular: f32s_from_js(object, "specular")?,
+        shininess: get_f32(object, "shininess")?,
+        dissolve: get_f32(object, "dissolve")?,
+        optical_density: get_f32(object, "opticalDensity")?,
+        illumination_model: get(object, "illuminationModel")?
+            .as_f64()
+            .map(|model| model as u8),
+        ambient_texture: get_optional_string(object, "ambientTexture")?,
+        diffuse_texture: get_optional_string(object, "diffuseTexture")?,
+        specular_texture: get_optional_string(object, "specularTexture")?,
+        normal_texture: get_optional_string(object, "normalTexture")?,
+        shininess_texture: get_optional_string(object, "shininessTexture")?,
+        dissolve_texture: get_optional_string(object, "dissolveTexture")?,
+        unknown_params,
+        pbr,
+    })
+}
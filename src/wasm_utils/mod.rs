@@ -1,4 +1,5 @@
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
+// Native builds (e.g. `cargo test`) have no console to log to, so they fall back to stdout.
 
 use wasm_bindgen::prelude::wasm_bindgen;
 
@@ -9,9 +10,12 @@ extern "C" {
 
 #[macro_export]
 macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into())
-    }
+    ( $( $t:tt )* ) => {{
+        #[cfg(target_arch = "wasm32")]
+        web_sys::console::log_1(&format!( $( $t )* ).into());
+        #[cfg(not(target_arch = "wasm32"))]
+        println!( $( $t )* );
+    }}
 }
 
 // pub fn set_panic_hook() {
@@ -2,19 +2,62 @@ use std::f32::consts::PI;
 
 use glam::{Mat4, Vec3};
 use wasm_bindgen::{JsCast, JsValue};
-use web_sys::{HtmlCanvasElement, WebGl2RenderingContext, WebGlProgram, WebGlShader};
-
-use crate::{loader::ModelData, log, CAMERA_TARGET};
+use web_sys::{
+    HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader,
+    WebGlVertexArrayObject,
+};
+
+use crate::{
+    camera::{Camera, FIELD_OF_VIEW_DEGREES},
+    geometry::{select_lod, LodLevel},
+    loader::ModelData,
+};
+
+const SURFACE_COLOR: [f32; 4] = [1.0, 0.7, 0.0, 1.0];
+const LINE_AND_POINT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/// how far, in pixels, a coarser level of detail may stray from the full model
+const MAX_SCREEN_ERROR_PIXELS: f32 = 1.0;
+
+/// the GPU copy of one level of detail, uploaded once when the model is set
+struct LodBuffers {
+    /// attribute bindings and index buffer, all a draw has to bind
+    vertex_array: WebGlVertexArrayObject,
+    /// vertex streams and the index buffer, deleted with the model
+    buffers: Vec<WebGlBuffer>,
+    /// where the line and then the point indices follow the triangles'
+    line_start: usize,
+    point_start: usize,
+}
 
 pub struct WebGLState {
     context: WebGl2RenderingContext,
     program: WebGlProgram,
-    model_data: Option<ModelData>,
+    /// levels of detail of the current model, the full model first
+    lods: Vec<LodLevel>,
+    /// GPU buffers of each of `lods`
+    lod_buffers: Vec<LodBuffers>,
 }
 
 impl WebGLState {
-    pub fn set_model_data(&mut self, model_data: Option<ModelData>) {
-        self.model_data = model_data;
+    /**
+     * Shows the levels of detail `lods`, prepared by the model worker,
+     * uploading each of them to the GPU and freeing the previous model's
+     * buffers
+     */
+    pub fn set_model_data(&mut self, lods: Vec<LodLevel>) -> Result<(), String> {
+        for old in self.lod_buffers.drain(..) {
+            self.context.delete_vertex_array(Some(&old.vertex_array));
+            for buffer in &old.buffers {
+                self.context.delete_buffer(Some(buffer));
+            }
+        }
+        self.lods.clear();
+        for lod in &lods {
+            let buffers = self.upload_model(&lod.model)?;
+            self.lod_buffers.push(buffers);
+        }
+        self.lods = lods;
+        Ok(())
     }
 
     pub fn new(canvas: &HtmlCanvasElement) -> Result<WebGLState, JsValue> {
@@ -28,13 +71,22 @@ impl WebGLState {
             WebGl2RenderingContext::VERTEX_SHADER,
             r##"
             attribute vec3 a_position;
+            attribute vec3 a_normal;
+            attribute vec3 a_color;
             
             uniform mat4 u_projection;
             uniform mat4 u_view;
             uniform mat4 u_world;
+            uniform float u_point_size;
+
+            varying vec3 v_normal;
+            varying vec3 v_color;
                  
             void main() {
               gl_Position = u_projection * u_view * u_world * vec4(a_position, 1.0);
+              gl_PointSize = u_point_size;
+              v_normal = (u_view * u_world * vec4(a_normal, 0.0)).xyz;
+              v_color = a_color;
             }
             "##,
         )?;
@@ -44,8 +96,19 @@ impl WebGLState {
             WebGl2RenderingContext::FRAGMENT_SHADER,
             r##"precision mediump float;
 
+            uniform vec4 u_color;
+            uniform float u_shaded;
+            uniform float u_use_vertex_color;
+
+            varying vec3 v_normal;
+            varying vec3 v_color;
+
             void main() {
-                gl_FragColor = vec4(1.0,0.7,0.0,1.0);
+                // headlight: the camera looks down +z in view space
+                float diffuse = max(dot(normalize(v_normal), vec3(0.0, 0.0, -1.0)), 0.0);
+                float light = mix(1.0, 0.25 + 0.75 * diffuse, u_shaded);
+                vec3 base = mix(u_color.rgb, v_color, u_use_vertex_color);
+                gl_FragColor = vec4(base * light, u_color.a);
             }
             "##,
         )?;
@@ -55,7 +118,8 @@ impl WebGLState {
         Ok(WebGLState {
             context,
             program,
-            model_data: None,
+            lods: Vec::new(),
+            lod_buffers: Vec::new(),
         })
     }
 
@@ -65,48 +129,65 @@ impl WebGLState {
         canvas_height: u32,
         x_rot: f32,
         y_rot: f32,
-        z_near: f32,
-        z_far: f32,
-        camera_offset: f32,
+        camera: &Camera,
     ) {
-        match &self.model_data {
+        let level = select_lod(
+            &self.lods,
+            camera.pixels_per_unit(canvas_height),
+            MAX_SCREEN_ERROR_PIXELS,
+        );
+        match self
+            .lods
+            .get(level)
+            .map(|lod| &lod.model)
+            .zip(self.lod_buffers.get(level))
+        {
             None => {
-                log!("no vertices available to buffer");
-                panic!();
+                // nothing uploaded yet
+                self.context.clear_color(0.2, 0.2, 0.2, 1.0);
+                self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
             }
-            Some(model_data) => {
+            Some((model_data, buffers)) => {
                 self.context
                     .viewport(0, 0, canvas_width as i32, canvas_height as i32);
                 self.context.enable(WebGl2RenderingContext::DEPTH_TEST);
                 self.context.enable(WebGl2RenderingContext::CULL_FACE);
                 self.context.cull_face(WebGl2RenderingContext::BACK);
+                // OBJ faces wind counter-clockwise, which the left-handed
+                // view/projection matrices mirror to clockwise on screen
+                self.context.front_face(WebGl2RenderingContext::CW);
                 self.context.use_program(Some(&self.program));
 
-                let world_matrix = Mat4::IDENTITY;
-                let field_of_view_radians = 60.0 * PI / 180.0;
+                // the model turns about the point the camera looks at
+                let world_matrix = Mat4::from_translation(camera.target);
+                let field_of_view_radians = FIELD_OF_VIEW_DEGREES * PI / 180.0;
                 let aspect: f32 = canvas_width as f32 / canvas_height as f32;
-                let projection_matrix =
-                    Mat4::perspective_lh(field_of_view_radians, aspect, z_near, z_far);
-                let up: Vec3 = Vec3::from([0.0, 1.0, 0.0]);
-                let view_matrix = Mat4::look_at_lh(
-                    Vec3::from([0.0, camera_offset, camera_offset]),
-                    CAMERA_TARGET,
-                    up,
+                let projection_matrix = Mat4::perspective_lh(
+                    field_of_view_radians,
+                    aspect,
+                    camera.z_near,
+                    camera.z_far,
                 );
+                let up: Vec3 = Vec3::from([0.0, 1.0, 0.0]);
+                let view_matrix = Mat4::look_at_lh(camera.eye(), camera.target, up);
 
                 // TODO: rotate world space
-                let x_rotation_matrix = Mat4::from_rotation_x(-1.0 * y_rot * PI / 180.0);
+                let x_rotation_matrix = Mat4::from_rotation_x(-y_rot * PI / 180.0);
                 let y_rotation_matrix = Mat4::from_rotation_y(0.0);
                 let z_rotation_matrix = Mat4::from_rotation_z(x_rot * PI / 180.0);
 
                 let rotated_world_matrix = world_matrix
                     .mul_mat4(&x_rotation_matrix)
                     .mul_mat4(&y_rotation_matrix)
-                    .mul_mat4(&z_rotation_matrix);
+                    .mul_mat4(&z_rotation_matrix)
+                    .mul_mat4(&Mat4::from_translation(-camera.target));
 
                 // clear the scene
-                let _ = self.context.clear_color(0.2, 0.2, 0.2, 1.0);
-                self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+                self.context.clear_color(0.2, 0.2, 0.2, 1.0);
+                self.context.clear(
+                    WebGl2RenderingContext::COLOR_BUFFER_BIT
+                        | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
+                );
 
                 // get shader uniform locations
                 let u_view = self.context.get_uniform_location(&self.program, "u_view");
@@ -114,6 +195,14 @@ impl WebGLState {
                 let u_projection = self
                     .context
                     .get_uniform_location(&self.program, "u_projection");
+                let u_color = self.context.get_uniform_location(&self.program, "u_color");
+                let u_point_size = self
+                    .context
+                    .get_uniform_location(&self.program, "u_point_size");
+                let u_shaded = self.context.get_uniform_location(&self.program, "u_shaded");
+                let u_use_vertex_color = self
+                    .context
+                    .get_uniform_location(&self.program, "u_use_vertex_color");
 
                 // set shader uniforms
                 self.context.uniform_matrix4fv_with_f32_array(
@@ -133,72 +222,150 @@ impl WebGLState {
                     &projection_matrix.to_cols_array(),
                 );
 
-                let mut vertex_count = 0;
-                // load vertex position and index data into a buffer for each model rendered
-                let vert_position_count = self.load_buffer_from_array(
-                    "a_position",
-                    model_data.vertices.clone(),
-                    WebGl2RenderingContext::FLOAT,
-                );
-                let index_count = self.load_index_buffer_from_array(model_data.indices.clone());
-                vertex_count += vert_position_count;
-
-                self.context.draw_elements_with_i32(
-                    WebGl2RenderingContext::POINTS,
-                    index_count,
-                    WebGl2RenderingContext::UNSIGNED_INT,
-                    0,
-                );
+                // the buffers were uploaded by `set_model_data`, only bind them
+                self.context.bind_vertex_array(Some(&buffers.vertex_array));
+
+                // draw each visible submesh from its own slice of the index buffer
+                self.context
+                    .uniform4fv_with_f32_array(u_color.as_ref(), &SURFACE_COLOR);
+                self.context.uniform1f(u_point_size.as_ref(), 1.0);
+                self.context.uniform1f(u_shaded.as_ref(), 1.0);
+                // vertex colors replace the surface color as the base color
+                let use_vertex_color = if model_data.colors.is_some() {
+                    1.0
+                } else {
+                    0.0
+                };
+                self.context
+                    .uniform1f(u_use_vertex_color.as_ref(), use_vertex_color);
+                for submesh in model_data.submeshes.iter().filter(|s| s.visible) {
+                    self.context.draw_elements_with_i32(
+                        WebGl2RenderingContext::TRIANGLES,
+                        submesh.index_range.len() as i32,
+                        WebGl2RenderingContext::UNSIGNED_INT,
+                        (submesh.index_range.start * std::mem::size_of::<u32>()) as i32,
+                    );
+                }
+
+                // polylines and point markers go on top of the surfaces
+                self.context
+                    .uniform4fv_with_f32_array(u_color.as_ref(), &LINE_AND_POINT_COLOR);
+                self.context.uniform1f(u_shaded.as_ref(), 0.0);
+                self.context.uniform1f(u_use_vertex_color.as_ref(), 0.0);
+                if !model_data.line_indices.is_empty() {
+                    self.context.draw_elements_with_i32(
+                        WebGl2RenderingContext::LINES,
+                        model_data.line_indices.len() as i32,
+                        WebGl2RenderingContext::UNSIGNED_INT,
+                        (buffers.line_start * std::mem::size_of::<u32>()) as i32,
+                    );
+                }
+                if !model_data.point_indices.is_empty() {
+                    self.context.uniform1f(u_point_size.as_ref(), 4.0);
+                    self.context.draw_elements_with_i32(
+                        WebGl2RenderingContext::POINTS,
+                        model_data.point_indices.len() as i32,
+                        WebGl2RenderingContext::UNSIGNED_INT,
+                        (buffers.point_start * std::mem::size_of::<u32>()) as i32,
+                    );
+                }
+                self.context.bind_vertex_array(None);
+            }
+        }
+    }
+
+    /**
+     * Uploads the vertex streams of `model_data` and a single index buffer
+     * holding its triangles, then its lines and then its points, recording
+     * the attribute bindings in a vertex array
+     */
+    fn upload_model(&self, model_data: &ModelData) -> Result<LodBuffers, String> {
+        let vertex_array = self
+            .context
+            .create_vertex_array()
+            .ok_or_else(|| String::from("Failed to create vertex array"))?;
+        self.context.bind_vertex_array(Some(&vertex_array));
+
+        // attributes without a stream stay disabled and read as zero
+        let streams = [
+            ("a_position", Some(&model_data.vertices)),
+            ("a_normal", model_data.normals.as_ref()),
+            ("a_color", model_data.colors.as_ref()),
+        ];
+        let mut buffers = Vec::new();
+        for (location, values) in streams {
+            if let Some(values) = values {
+                buffers.push(self.load_vertex_buffer(location, values)?);
             }
         }
+
+        let line_start = model_data.indices.len();
+        let point_start = line_start + model_data.line_indices.len();
+        let mut indices = Vec::with_capacity(point_start + model_data.point_indices.len());
+        indices.extend_from_slice(&model_data.indices);
+        indices.extend_from_slice(&model_data.line_indices);
+        indices.extend_from_slice(&model_data.point_indices);
+        buffers.push(self.load_index_buffer(&indices)?);
+
+        self.context.bind_vertex_array(None);
+        Ok(LodBuffers {
+            vertex_array,
+            buffers,
+            line_start,
+            point_start,
+        })
     }
 
-    pub fn load_buffer_from_array(&self, location: &str, array: Vec<f32>, data_type: u32) -> i32 {
-        let position_attribute_location = self.context.get_attrib_location(&self.program, location);
+    /// uploads three floats per vertex and binds them to the attribute `location`
+    fn load_vertex_buffer(&self, location: &str, values: &[f32]) -> Result<WebGlBuffer, String> {
+        let attribute_location = self.context.get_attrib_location(&self.program, location);
 
         let buffer = self
             .context
             .create_buffer()
-            .ok_or("Failed to create buffer")
-            .unwrap();
+            .ok_or_else(|| String::from("Failed to create buffer"))?;
         self.context
             .bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
         unsafe {
-            let positions_array_buf_view = js_sys::Float32Array::view(&array);
+            // the view is only used until the data has been copied to the GPU
+            let array_buf_view = js_sys::Float32Array::view(values);
 
             self.context.buffer_data_with_array_buffer_view(
                 WebGl2RenderingContext::ARRAY_BUFFER,
-                &positions_array_buf_view,
+                &array_buf_view,
                 WebGl2RenderingContext::STATIC_DRAW,
             );
         }
 
-        self.context.vertex_attrib_pointer_with_i32(
-            position_attribute_location as u32,
-            3,
-            data_type,
-            false,
-            0,
-            0,
-        );
-        self.context
-            .enable_vertex_attrib_array(position_attribute_location as u32);
+        // an attribute the shader compiled out has nothing to bind
+        if attribute_location >= 0 {
+            self.context.vertex_attrib_pointer_with_i32(
+                attribute_location as u32,
+                3,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                0,
+                0,
+            );
+            self.context
+                .enable_vertex_attrib_array(attribute_location as u32);
+        }
 
-        (array.len() / 3) as i32
+        Ok(buffer)
     }
 
-    pub fn load_index_buffer_from_array(&self, array: Vec<u32>) -> i32 {
+    /// uploads `indices` as the element buffer of the bound vertex array
+    fn load_index_buffer(&self, indices: &[u32]) -> Result<WebGlBuffer, String> {
         let buffer = self
             .context
             .create_buffer()
-            .ok_or("Failed to create buffer")
-            .unwrap();
+            .ok_or_else(|| String::from("Failed to create buffer"))?;
 
         self.context
             .bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&buffer));
 
         unsafe {
-            let index_array_buf_view = js_sys::Uint32Array::view(&array);
+            let index_array_buf_view = js_sys::Uint32Array::view(indices);
 
             self.context.buffer_data_with_array_buffer_view(
                 WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
@@ -207,7 +374,7 @@ impl WebGLState {
             );
         }
 
-        array.len() as i32
+        Ok(buffer)
     }
 }
 
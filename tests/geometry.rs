@@ -0,0 +1,769 @@
+//! Native test suite for the geometry processing steps.
+
+use std::io::{BufReader, Cursor};
+
+use glam::Vec3;
+use wasm_conways::{
+    camera::{Camera, FIELD_OF_VIEW_DEGREES},
+    geometry::{
+        analyze_vertex_cache, generate_lods, generate_normals, generate_tangents, model_bounds,
+        optimize_mesh, optimize_overdraw, optimize_vertex_cache, select_lod, simplify,
+        submesh_bounds, weld_vertices, BoundingSphere, LodOptions, NormalWeighting,
+        SimplifyOptions, VertexCacheStats, WeldOptions, WeldReport, OVERDRAW_THRESHOLD,
+        VERTEX_CACHE_SIZE,
+    },
+    loader::{
+        decode_model, encode_model, load_model, EmbeddedFile, MemoryResolver, ModelData,
+        UploadParser,
+    },
+};
+
+fn load_str(source: &str) -> ModelData {
+    load_model(&mut BufReader::new(Cursor::new(source))).unwrap()
+}
+
+fn normal(model: &ModelData, vertex: u32) -> [f32; 3] {
+    let v = vertex as usize;
+    let normals = model.normals.as_ref().unwrap();
+    [normals[v * 3], normals[v * 3 + 1], normals[v * 3 + 2]]
+}
+
+fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+}
+
+// two triangles meeting at a right angle along the edge from vertex 1 to vertex 2
+const HINGE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n";
+
+#[test]
+fn keeps_hard_edges_with_smoothing_off() {
+    let model = load_str(&format!("{}s off\nf 1 2 3\nf 1 4 2\n", HINGE));
+    // the two shared corners are split so each face keeps its own normal
+    assert_eq!(model.vertex_count(), 6);
+    for &vertex in &model.indices[..3] {
+        assert_close(normal(&model, vertex), [0.0, 0.0, 1.0]);
+    }
+    for &vertex in &model.indices[3..] {
+        assert_close(normal(&model, vertex), [0.0, 1.0, 0.0]);
+    }
+}
+
+#[test]
+fn smooths_across_faces_in_the_same_group() {
+    let model = load_str(&format!("{}s 1\nf 1 2 3\nf 1 4 2\n", HINGE));
+    assert_eq!(model.vertex_count(), 4);
+    let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+    assert_close(normal(&model, 1), [0.0, diagonal, diagonal]);
+    assert_close(normal(&model, 2), [0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn splits_vertices_between_smoothing_groups() {
+    let model = load_str(&format!("{}s 1\nf 1 2 3\ns 2\nf 1 4 2\n", HINGE));
+    assert_eq!(model.vertex_count(), 6);
+}
+
+#[test]
+fn keeps_normals_from_the_file() {
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 1 0 0\nf 1//1 2//1 3//1\n");
+    assert_close(normal(&model, 0), [1.0, 0.0, 0.0]);
+}
+
+#[test]
+fn generates_unit_normals_for_the_cornell_box() {
+    let model = load_str(include_str!("../cornell_box.obj"));
+    let normals = model.normals.as_ref().unwrap();
+    for &vertex in &model.indices {
+        let [x, y, z] = normal(&model, vertex);
+        assert!(((x * x + y * y + z * z) - 1.0).abs() < 1e-4);
+    }
+    assert_eq!(normals.len(), model.vertices.len());
+}
+
+fn positions(model: &ModelData) -> Vec<Vec3> {
+    model
+        .vertices
+        .chunks_exact(3)
+        .map(Vec3::from_slice)
+        .collect()
+}
+
+#[test]
+fn bounds_the_unit_cube() {
+    let model = load_str(include_str!("../cube.obj"));
+    let bounds = model_bounds(&model).unwrap();
+    assert_eq!(bounds.aabb.min, Vec3::ZERO);
+    assert_eq!(bounds.aabb.max, Vec3::ONE);
+    assert_eq!(bounds.aabb.center(), Vec3::splat(0.5));
+    // the smallest sphere has the cube's diagonal as its diameter
+    assert!(bounds.sphere.center.distance(Vec3::splat(0.5)) < 1e-5);
+    assert!((bounds.sphere.radius - 3f32.sqrt() * 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn bounding_sphere_contains_every_vertex() {
+    for source in [
+        include_str!("../cornell_box.obj"),
+        include_str!("../dodecahedron.obj"),
+        include_str!("../lamp.obj"),
+    ] {
+        let model = load_str(source);
+        let bounds = model_bounds(&model).unwrap();
+        let aabb_radius = bounds.aabb.size().length() * 0.5;
+        assert!(bounds.sphere.radius <= aabb_radius * 1.0001);
+        for point in positions(&model) {
+            assert!(point.cmpge(bounds.aabb.min).all() && point.cmple(bounds.aabb.max).all());
+            let distance = point.distance(bounds.sphere.center);
+            assert!(distance <= bounds.sphere.radius * 1.0001);
+        }
+    }
+}
+
+#[test]
+fn bounds_each_submesh_by_its_own_triangles() {
+    let model = load_str(include_str!("../cornell_box.obj"));
+    let light = model.submeshes.iter().find(|s| s.name == "light").unwrap();
+    let bounds = submesh_bounds(&model, light).unwrap();
+    assert_eq!(bounds.aabb.min, Vec3::new(213.0, 548.0, 227.0));
+    assert_eq!(bounds.aabb.max, Vec3::new(343.0, 548.0, 332.0));
+}
+
+#[test]
+fn has_no_bounds_without_vertices() {
+    let model = load_str("# nothing here\n");
+    assert_eq!(model_bounds(&model), None);
+}
+
+#[test]
+fn frames_the_bounding_sphere() {
+    let sphere = BoundingSphere {
+        center: Vec3::new(-30.0, 100.0, 30.0),
+        radius: 150.0,
+    };
+    for aspect in [0.5, 1.0, 4.0 / 3.0] {
+        let camera = Camera::framing(&sphere, aspect);
+        assert_eq!(camera.target, sphere.center);
+        // the sphere fits inside the narrower of the two fields of view
+        let half_vertical = FIELD_OF_VIEW_DEGREES.to_radians() * 0.5;
+        let half_horizontal = (half_vertical.tan() * aspect).atan();
+        let half_angle = half_vertical.min(half_horizontal);
+        assert!(sphere.radius / camera.distance <= half_angle.sin());
+        assert!(camera.eye().distance(sphere.center) - camera.distance < 1e-3);
+        // and between the clipping planes
+        assert!(camera.z_near > 0.0 && camera.z_near < camera.distance - sphere.radius);
+        assert!(camera.z_far > camera.distance + sphere.radius);
+    }
+}
+
+#[test]
+fn frames_uploaded_models() {
+    // the way the worker hands models to the page: streamed in slices, and
+    // on later uploads decoded from the cache
+    let bytes = include_bytes!("../lamp.obj");
+    let mut parser = UploadParser::new("lamp.obj", bytes.len() as u64, MemoryResolver::new());
+    for slice in bytes.chunks(4096) {
+        parser.push(slice).unwrap();
+    }
+    let model = parser.finish().unwrap();
+    let cached = decode_model(&encode_model(&model, 0)).unwrap();
+
+    let sphere = model_bounds(&model).unwrap().sphere;
+    for model in [&model, &cached] {
+        let camera = Camera::framing_model(model, 4.0 / 3.0).unwrap();
+        assert_eq!(camera, Camera::framing(&sphere, 4.0 / 3.0));
+        assert!(camera.z_near < camera.distance - sphere.radius);
+        assert!(camera.z_far > camera.distance + sphere.radius);
+    }
+    assert_eq!(Camera::framing_model(&ModelData::default(), 1.0), None);
+}
+
+#[test]
+fn zooming_keeps_the_clipping_planes_around_the_model() {
+    let sphere = BoundingSphere {
+        center: Vec3::ZERO,
+        radius: 1.0,
+    };
+    let mut camera = Camera::framing(&sphere, 1.0);
+    let framed = camera;
+    camera.zoom(-500.0);
+    assert!(camera.distance < framed.distance);
+    // closer up the model covers more of the screen
+    assert!(camera.pixels_per_unit(600) > framed.pixels_per_unit(600));
+    assert!(camera.z_far > camera.distance + sphere.radius);
+    // inside the model the near plane stays in front of the camera
+    for _ in 0..20 {
+        camera.zoom(-500.0);
+    }
+    assert!(camera.distance > 0.0 && camera.z_near > 0.0);
+    for _ in 0..100 {
+        camera.zoom(1000.0);
+    }
+    assert!(camera.distance <= sphere.radius * 100.0);
+    assert!(camera.z_far > camera.distance + sphere.radius);
+}
+
+fn tangent(model: &ModelData, vertex: u32) -> [f32; 4] {
+    let v = vertex as usize;
+    let tangents = model.tangents.as_ref().unwrap();
+    [
+        tangents[v * 4],
+        tangents[v * 4 + 1],
+        tangents[v * 4 + 2],
+        tangents[v * 4 + 3],
+    ]
+}
+
+/// the triangles of a model as the reference MikkTSpace implementation sees them
+struct ReferenceMikkTSpace<'a> {
+    model: &'a ModelData,
+    /// what the reference produced for each corner, in index order
+    tangents: Vec<[f32; 4]>,
+}
+
+impl bevy_mikktspace::Geometry for ReferenceMikkTSpace<'_> {
+    fn num_faces(&self) -> usize {
+        self.model.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let v = self.model.indices[face * 3 + vert] as usize;
+        [0, 1, 2].map(|i| self.model.vertices[v * 3 + i])
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        normal(self.model, self.model.indices[face * 3 + vert])
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let v = self.model.indices[face * 3 + vert] as usize;
+        let texcoords = self.model.texcoords.as_ref().unwrap();
+        [texcoords[v * 2], texcoords[v * 2 + 1]]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+#[test]
+fn generates_tangents_for_the_textured_cube() {
+    let mut model = load_str(include_str!("../cube_tex.obj"));
+    // the file's normals all point up, start from the faces' own normals instead
+    model.normals = None;
+    generate_normals(&mut model, NormalWeighting::Angle);
+
+    // what the reference implementation (bevy_mikktspace is a port of
+    // Mikkelsen's C code) gives every triangle corner of the same input
+    let mut reference = ReferenceMikkTSpace {
+        model: &model,
+        tangents: vec![[0.0; 4]; model.indices.len()],
+    };
+    assert!(bevy_mikktspace::generate_tangents(&mut reference));
+    let expected = reference.tangents;
+
+    let vertex_count = model.vertex_count();
+    generate_tangents(&mut model);
+    // every UV island winds the same way, so no vertex needs splitting
+    assert_eq!(model.vertex_count(), vertex_count);
+    for (corner, expected) in expected.iter().enumerate() {
+        let actual = tangent(&model, model.indices[corner]);
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (a - e).abs() < 1e-5,
+                "corner {}: {:?} != {:?}",
+                corner,
+                actual,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn keeps_tangents_in_the_plane_of_the_file_normals() {
+    let mut model = load_str(include_str!("../cube_tex.obj"));
+    generate_tangents(&mut model);
+    for vertex in 0..model.vertex_count() as u32 {
+        let [x, y, z, w] = tangent(&model, vertex);
+        let t = Vec3::new(x, y, z);
+        assert!((t.length() - 1.0).abs() < 1e-5);
+        assert!(t.dot(Vec3::from(normal(&model, vertex))).abs() < 1e-5);
+        assert!(w == 1.0 || w == -1.0);
+    }
+}
+
+// two quads side by side, the right one with its UVs mirrored about the shared edge
+const MIRRORED: &str = "v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nv 1 1 0\nv 2 1 0\n\
+    vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\nvn 0 0 1\n\
+    f 1/1/1 2/2/1 5/4/1 4/3/1\nf 2/2/1 3/1/1 6/3/1 5/4/1\n";
+
+#[test]
+fn splits_vertices_where_mirrored_uvs_meet() {
+    let mut model = load_str(MIRRORED);
+    assert_eq!(model.vertex_count(), 6);
+    generate_tangents(&mut model);
+    // the two vertices on the mirror line get a copy for each side
+    assert_eq!(model.vertex_count(), 8);
+    for &vertex in &model.indices[..6] {
+        assert_eq!(tangent(&model, vertex), [1.0, 0.0, 0.0, 1.0]);
+    }
+    for &vertex in &model.indices[6..] {
+        assert_eq!(tangent(&model, vertex), [-1.0, 0.0, 0.0, -1.0]);
+    }
+}
+
+#[test]
+fn averages_tangents_at_shared_vertices() {
+    // a smooth strip bent along its middle vertices, which both quads share
+    let mut model = load_str(
+        "v 0 0 0\nv 1 0 0\nv 2 0 1\nv 0 1 0\nv 1 1 0\nv 2 1 1\n\
+         vt 0 0\nvt 1 0\nvt 2 0\nvt 0 1\nvt 1 1\nvt 2 1\n\
+         s 1\nf 1/1 2/2 5/5 4/4\nf 2/2 3/3 6/6 5/5\n",
+    );
+    generate_tangents(&mut model);
+    let middle = tangent(&model, model.indices[1]);
+    let bend = Vec3::new(1.0, 0.0, 0.5).normalize();
+    assert!(Vec3::new(middle[0], middle[1], middle[2]).dot(bend) > 0.9);
+    let end = tangent(&model, model.indices[0]);
+    assert_close([end[0], end[1], end[2]], [1.0, 0.0, 0.0]);
+}
+
+#[test]
+fn has_no_tangents_without_texcoords() {
+    let mut model = load_str(&format!("{}f 1 2 3\nf 1 4 2\n", HINGE));
+    generate_tangents(&mut model);
+    assert_eq!(model.tangents, None);
+}
+
+#[test]
+fn welds_the_cube_only_across_normal_seams_when_asked() {
+    let mut model = load_str(include_str!("../cube.obj"));
+    let vertex_count = model.vertex_count();
+    // every corner is split three ways by the face normals
+    assert_eq!(vertex_count, 24);
+    let report = weld_vertices(&mut model, &WeldOptions::default());
+    assert_eq!(report, WeldReport::default());
+    assert_eq!(model.vertex_count(), vertex_count);
+
+    let options = WeldOptions {
+        respect_normal_seams: false,
+        ..WeldOptions::default()
+    };
+    let report = weld_vertices(&mut model, &options);
+    assert_eq!(report.merged_vertices, 16);
+    assert_eq!(report.removed_triangles, 0);
+    assert_eq!(model.vertex_count(), 8);
+    assert_eq!(model.normals.as_ref().unwrap().len(), 8 * 3);
+    assert_eq!(model.indices.len(), 36);
+    assert!(model.indices.iter().all(|&index| index < 8));
+}
+
+// two triangles of a quad whose shared edge was exported twice, slightly apart
+const CRACKED: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1.000001 0 0\nv 1 1 0\nv 0 1.000001 0\n\
+    vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\nvn 0 0 1\n";
+
+#[test]
+fn closes_cracks_within_epsilon() {
+    let mut model = load_str(&format!(
+        "{}f 1/1/1 2/2/1 3/3/1\nf 4/2/1 5/4/1 6/3/1\n",
+        CRACKED
+    ));
+    assert_eq!(model.vertex_count(), 6);
+    let report = weld_vertices(&mut model, &WeldOptions::default());
+    assert_eq!(report.merged_vertices, 2);
+    assert_eq!(model.vertex_count(), 4);
+    assert_eq!(model.indices, [0, 1, 2, 1, 3, 2]);
+
+    // nothing is close enough for an exact weld
+    let mut model = load_str(&format!(
+        "{}f 1/1/1 2/2/1 3/3/1\nf 4/2/1 5/4/1 6/3/1\n",
+        CRACKED
+    ));
+    let exact = WeldOptions {
+        epsilon: 0.0,
+        ..WeldOptions::default()
+    };
+    assert_eq!(weld_vertices(&mut model, &exact).merged_vertices, 0);
+
+    // but -0.0 and 0.0 are the same position
+    let mut model = load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nv -0 0 0\nf 1 2 3\nf 4 2 3\n");
+    assert_eq!(model.vertex_count(), 4);
+    assert_eq!(weld_vertices(&mut model, &exact).merged_vertices, 1);
+}
+
+#[test]
+fn keeps_uv_seams_apart_unless_told_otherwise() {
+    // the second triangle maps the shared edge to different texture coordinates
+    let source = format!("{}f 1/1/1 2/2/1 3/3/1\nf 4/4/1 5/4/1 6/4/1\n", CRACKED);
+    let mut model = load_str(&source);
+    assert_eq!(
+        weld_vertices(&mut model, &WeldOptions::default()).merged_vertices,
+        0
+    );
+
+    let mut model = load_str(&source);
+    let options = WeldOptions {
+        respect_uv_seams: false,
+        ..WeldOptions::default()
+    };
+    assert_eq!(weld_vertices(&mut model, &options).merged_vertices, 2);
+    // the first vertex of each pair keeps its attributes
+    assert_eq!(&model.texcoords.as_ref().unwrap()[2..4], &[1.0, 0.0]);
+}
+
+#[test]
+fn removes_triangles_collapsed_by_welding() {
+    let mut model = load_str(
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0.001 0 0\n\
+         o first\nf 1 2 3\no sliver\nf 1 4 3\no last\nf 2 3 4\n",
+    );
+    let options = WeldOptions {
+        epsilon: 0.01,
+        respect_normal_seams: false,
+        ..WeldOptions::default()
+    };
+    let report = weld_vertices(&mut model, &options);
+    assert_eq!(report.merged_vertices, 1);
+    assert_eq!(report.removed_triangles, 1);
+    assert_eq!(model.indices, [0, 1, 2, 1, 2, 0]);
+    assert_eq!(model.smoothing_groups.len(), 2);
+    let ranges: Vec<_> = model
+        .submeshes
+        .iter()
+        .map(|submesh| (submesh.name.as_str(), submesh.index_range.clone()))
+        .collect();
+    assert_eq!(ranges, [("first", 0..3), ("sliver", 3..3), ("last", 3..6)]);
+}
+
+fn triangle_area(model: &ModelData, triangle: &[u32]) -> f32 {
+    let p = |v: u32| Vec3::from_slice(&model.vertices[v as usize * 3..][..3]);
+    (p(triangle[1]) - p(triangle[0]))
+        .cross(p(triangle[2]) - p(triangle[0]))
+        .length()
+        * 0.5
+}
+
+/**
+ * A flat 10 by 10 grid of quads; with `seam` the right half has UVs of its
+ * own, so the vertices down the middle come in pairs
+ */
+fn grid(seam: bool) -> String {
+    let mut source = String::new();
+    for y in 0..=10 {
+        for x in 0..=10 {
+            source.push_str(&format!(
+                "v {} {} 0\nvt {} {}\n",
+                x,
+                y,
+                x as f32 / 10.0,
+                y as f32 / 10.0
+            ));
+        }
+    }
+    // a second column of UVs for the middle, shifted over
+    for y in 0..=10 {
+        source.push_str(&format!("vt 0.6 {}\n", y as f32 / 10.0));
+    }
+    for y in 0..10 {
+        for x in 0..10 {
+            let corner = |dx: usize, dy: usize| {
+                let v = (y + dy) * 11 + x + dx + 1;
+                let vt = if seam && x + dx == 5 && x >= 5 {
+                    121 + y + dy + 1
+                } else {
+                    v
+                };
+                format!("{}/{}", v, vt)
+            };
+            source.push_str(&format!(
+                "f {} {} {} {}\n",
+                corner(0, 0),
+                corner(1, 0),
+                corner(1, 1),
+                corner(0, 1)
+            ));
+        }
+    }
+    source
+}
+
+#[test]
+fn simplifies_the_helmet_within_the_target() {
+    let mut model = load_str(include_str!("../model.obj"));
+    let original = model_bounds(&model).unwrap();
+    let target = model.indices.len() / 3 / 2;
+    let report = simplify(
+        &mut model,
+        &SimplifyOptions {
+            target_triangles: target,
+            max_error: f32::INFINITY,
+        },
+    );
+    assert!(report.triangles <= target);
+    assert_eq!(report.triangles, model.indices.len() / 3);
+    // half the triangles go for well under a percent of the size
+    assert!(report.error < original.sphere.radius * 0.01, "{:?}", report);
+    // the vertices nothing uses any more are gone, the others keep every stream
+    assert!(model
+        .indices
+        .iter()
+        .all(|&v| (v as usize) < model.vertex_count()));
+    assert_eq!(
+        model.texcoords.as_ref().unwrap().len(),
+        model.vertex_count() * 2
+    );
+    assert_eq!(
+        model.normals.as_ref().unwrap().len(),
+        model.vertex_count() * 3
+    );
+    let simplified = model_bounds(&model).unwrap();
+    assert!(simplified.aabb.min.distance(original.aabb.min) < original.sphere.radius * 0.01);
+    assert!(simplified.aabb.max.distance(original.aabb.max) < original.sphere.radius * 0.01);
+    assert_eq!(model.submeshes[0].index_range, 0..model.indices.len());
+}
+
+#[test]
+fn stops_at_the_error_limit() {
+    let mut model = load_str(include_str!("../model.obj"));
+    let triangles = model.indices.len() / 3;
+    let report = simplify(
+        &mut model,
+        &SimplifyOptions {
+            target_triangles: 0,
+            max_error: 1e-3,
+        },
+    );
+    assert!(report.error <= 1e-3);
+    assert!(report.triangles < triangles);
+    assert!(report.triangles > 0);
+}
+
+#[test]
+fn keeps_open_boundaries_in_place() {
+    let mut model = load_str(&grid(false));
+    let report = simplify(
+        &mut model,
+        &SimplifyOptions {
+            target_triangles: 0,
+            max_error: 1e-4,
+        },
+    );
+    // the flat inside and the straight sides fold away, the four corners stay
+    assert!(report.triangles <= 4, "{:?}", report);
+    let bounds = model_bounds(&model).unwrap();
+    assert_eq!(bounds.aabb.min, Vec3::ZERO);
+    assert_eq!(bounds.aabb.max, Vec3::new(10.0, 10.0, 0.0));
+    let area: f32 = model
+        .indices
+        .chunks_exact(3)
+        .map(|t| triangle_area(&model, t))
+        .sum();
+    assert!((area - 100.0).abs() < 1e-3);
+}
+
+#[test]
+fn keeps_uv_seams_closed() {
+    let mut model = load_str(&grid(true));
+    let report = simplify(
+        &mut model,
+        &SimplifyOptions {
+            target_triangles: 0,
+            max_error: 1e-4,
+        },
+    );
+    // each half folds down to its corners and the ends of the seam
+    assert!(report.triangles <= 8, "{:?}", report);
+    // no triangle reaches across the seam, and none is missing along it
+    let texcoords = model.texcoords.as_ref().unwrap();
+    for triangle in model.indices.chunks_exact(3) {
+        let u = |v: u32| texcoords[v as usize * 2];
+        let left = triangle.iter().all(|&v| u(v) <= 0.5);
+        let right = triangle.iter().all(|&v| u(v) >= 0.5);
+        assert!(left || right, "{:?}", triangle);
+    }
+    let area: f32 = model
+        .indices
+        .chunks_exact(3)
+        .map(|t| triangle_area(&model, t))
+        .sum();
+    assert!((area - 100.0).abs() < 1e-3);
+    let seam_vertices = model
+        .vertices
+        .chunks_exact(3)
+        .filter(|p| p[0] == 5.0)
+        .count();
+    assert!(seam_vertices >= 4);
+}
+
+#[test]
+fn builds_a_chain_of_levels_and_picks_one_by_screen_size() {
+    let model = load_str(include_str!("../model.obj"));
+    let triangles = model.indices.len() / 3;
+    let levels = generate_lods(model, &LodOptions::default());
+    assert!(levels.len() >= 2);
+    assert_eq!(levels[0].model.indices.len() / 3, triangles);
+    assert_eq!(levels[0].error, 0.0);
+    for pair in levels.windows(2) {
+        assert!(pair[1].model.indices.len() < pair[0].model.indices.len());
+        assert!(pair[1].error >= pair[0].error);
+    }
+
+    // close up every level is too coarse, far away the coarsest is fine
+    assert_eq!(select_lod(&levels, 1e6, 1.0), 0);
+    assert_eq!(select_lod(&levels, 1e-3, 1.0), levels.len() - 1);
+    let between = 1.0 / levels[1].error;
+    assert_eq!(select_lod(&levels, between, 1.0), 1);
+}
+
+#[test]
+fn keeps_file_metadata_on_the_full_level_only() {
+    let mut model = load_str(include_str!("../model.obj"));
+    model.embedded_files.push(EmbeddedFile {
+        path: String::from("texture.png"),
+        mime_type: Some(String::from("image/png")),
+        contents: vec![0; 1024],
+    });
+    model.untriangulated_faces.push(3);
+    let levels = generate_lods(model.clone(), &LodOptions::default());
+    assert!(levels.len() >= 2);
+    assert_eq!(levels[0].model, model);
+    for level in &levels[1..] {
+        assert!(level.model.embedded_files.is_empty());
+        assert!(level.model.materials.is_empty());
+        assert!(level.model.missing_files.is_empty());
+        assert!(level.model.untriangulated_faces.is_empty());
+        // submeshes are geometry, each level has its own ranges
+        assert_eq!(level.model.submeshes.len(), model.submeshes.len());
+    }
+}
+
+#[test]
+fn has_a_single_level_for_small_models() {
+    let levels = generate_lods(
+        load_str(include_str!("../cube.obj")),
+        &LodOptions::default(),
+    );
+    assert_eq!(levels.len(), 1);
+}
+
+/// every triangle as the attributes of its corners, starting from the smallest corner
+fn triangle_set(model: &ModelData, range: std::ops::Range<usize>) -> Vec<Vec<u32>> {
+    let vertex = |v: u32| {
+        let v = v as usize;
+        let mut bits: Vec<u32> = model.vertices[v * 3..v * 3 + 3]
+            .iter()
+            .map(|value| value.to_bits())
+            .collect();
+        for (stream, components) in [(&model.texcoords, 2), (&model.normals, 3)] {
+            if let Some(values) = stream {
+                bits.extend(
+                    values[v * components..(v + 1) * components]
+                        .iter()
+                        .map(|value| value.to_bits()),
+                );
+            }
+        }
+        bits
+    };
+    let mut triangles: Vec<Vec<u32>> = model.indices[range]
+        .chunks_exact(3)
+        .map(|triangle| {
+            let corners: Vec<Vec<u32>> = triangle.iter().map(|&v| vertex(v)).collect();
+            let first = (0..3).min_by_key(|&i| corners[i].clone()).unwrap();
+            (0..3)
+                .flat_map(|i| corners[(first + i) % 3].clone())
+                .collect()
+        })
+        .collect();
+    triangles.sort();
+    triangles
+}
+
+#[test]
+fn measures_the_vertex_cache() {
+    assert_eq!(
+        analyze_vertex_cache(&[0, 1, 2], VERTEX_CACHE_SIZE),
+        VertexCacheStats {
+            acmr: 3.0,
+            atvr: 1.0
+        }
+    );
+    // the second triangle only brings one new vertex
+    let strip = analyze_vertex_cache(&[0, 1, 2, 2, 1, 3], VERTEX_CACHE_SIZE);
+    assert_eq!(strip.acmr, 2.0);
+    // with room for a single vertex, nearly everything misses
+    let tiny = analyze_vertex_cache(&[0, 1, 2, 2, 1, 3], 1);
+    assert_eq!(tiny.acmr, 2.5);
+    assert_eq!(tiny.atvr, 1.25);
+}
+
+#[test]
+fn optimizes_the_bundled_models_for_the_vertex_cache() {
+    for source in [
+        include_str!("../model.obj"),
+        include_str!("../lamp.obj"),
+        include_str!("../cornell_box.obj"),
+        include_str!("../dodecahedron.obj"),
+        include_str!("../cube_tex.obj"),
+    ] {
+        let mut model = load_str(source);
+        let original = model.clone();
+        let report = optimize_mesh(&mut model);
+        assert!(report.after.acmr <= report.before.acmr, "{:?}", report);
+        assert!(report.after.atvr <= report.before.atvr, "{:?}", report);
+        assert!(report.after.atvr >= 1.0);
+
+        // each submesh draws the same triangles, only in another order
+        assert_eq!(model.submeshes, original.submeshes);
+        for submesh in &model.submeshes {
+            assert_eq!(
+                triangle_set(&model, submesh.index_range.clone()),
+                triangle_set(&original, submesh.index_range.clone())
+            );
+        }
+        // and vertices are numbered in the order they are first drawn
+        let mut next = 0;
+        for &index in &model.indices {
+            assert!(index <= next);
+            next = next.max(index + 1);
+        }
+    }
+}
+
+#[test]
+fn reorders_shuffled_triangles_close_to_the_best_order() {
+    let mut model = load_str(include_str!("../model.obj"));
+    // draw the triangles in a scattered order, as some exporters write them
+    let triangle_count = model.indices.len() / 3;
+    let scattered: Vec<u32> = (0..triangle_count)
+        .flat_map(|t| {
+            let t = t * 7919 % triangle_count;
+            model.indices[t * 3..t * 3 + 3].to_vec()
+        })
+        .collect();
+    model.indices = scattered;
+    model.smoothing_groups.clear();
+
+    let report = optimize_mesh(&mut model);
+    assert!(report.before.acmr > 2.5, "{:?}", report);
+    assert!(report.after.acmr < 1.1, "{:?}", report);
+    assert!(report.after.atvr < 1.2, "{:?}", report);
+}
+
+#[test]
+fn keeps_the_overdraw_order_within_the_threshold() {
+    let mut model = load_str(include_str!("../model.obj"));
+    optimize_vertex_cache(&mut model);
+    let cache_order = analyze_vertex_cache(&model.indices, VERTEX_CACHE_SIZE);
+    let triangles = triangle_set(&model, 0..model.indices.len());
+    optimize_overdraw(&mut model, OVERDRAW_THRESHOLD);
+    let overdraw_order = analyze_vertex_cache(&model.indices, VERTEX_CACHE_SIZE);
+    assert!(overdraw_order.acmr <= cache_order.acmr * OVERDRAW_THRESHOLD);
+    assert_eq!(triangle_set(&model, 0..model.indices.len()), triangles);
+}
@@ -0,0 +1,1614 @@
+//! Native test suite for the model loaders.
+
+use std::io::{BufReader, Cursor, Write};
+
+use base64::Engine;
+use flate2::{write::GzEncoder, Compression};
+
+use wasm_conways::geometry::{generate_lods, generate_tangents, optimize_mesh, LodOptions};
+use wasm_conways::loader::{
+    cache_key, decode_lods, decode_model, encode_lods, encode_model, find_primary_model, gunzip,
+    image_dimensions, is_binary_stl, load_archive, load_gltf, load_gltf_with_limits, load_model,
+    load_model_with_limits, load_model_with_resolver, load_ply, load_ply_with_limits, load_stl,
+    load_stl_with_limits, normalize_path, parse_upload, triangulate, unzip, Archive,
+    CacheKeyBuilder, Limit, LoadLimits, MemoryResolver, ModelData, ModelFormat, NoFiles, ObjError,
+    ObjParser, Progress, Tessellation, UploadParser, VertexAttribute,
+};
+
+fn load_str(source: &str) -> Result<ModelData, ObjError> {
+    load_model(&mut BufReader::new(Cursor::new(source)))
+}
+
+#[test]
+fn loads_bundled_cube() {
+    let model = load_str(include_str!("../cube.obj")).unwrap();
+    assert!(!model.vertices.is_empty());
+    assert_eq!(model.indices.len() % 3, 0);
+}
+
+#[test]
+fn reports_bad_float_with_location() {
+    let err = load_str("v 0 0 0\nv 1.0 abc 2.0\n").unwrap_err();
+    match err {
+        ObjError::BadFloat {
+            line,
+            column,
+            token,
+        } => {
+            assert_eq!((line, column), (2, 7));
+            assert_eq!(token, "abc");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn reports_missing_coordinate() {
+    let err = load_str("v 1.0 2.0\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::MissingCoordinate {
+            line: 1,
+            column: 10,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn reports_bad_index() {
+    let err = load_str("v 0 0 0\nf 1 x/1 1\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::BadIndex {
+            line: 2,
+            column: 5,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn reports_invalid_utf8() {
+    let bytes = b"v 0 0 0\nv 1 \xff 0\n";
+    let err = load_model(&mut BufReader::new(&bytes[..])).unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::InvalidUtf8 {
+            line: 2,
+            column: 5,
+            ..
+        }
+    ));
+    assert!(err.to_string().starts_with("line 2, column 5"));
+}
+
+#[test]
+fn triangulates_every_polygon_in_lamp() {
+    let source = include_str!("../lamp.obj");
+    let expected_triangles: usize = source
+        .lines()
+        .filter(|l| l.starts_with("f "))
+        .map(|l| l.split_whitespace().count() - 1 - 2)
+        .sum();
+    let model = load_str(source).unwrap();
+    assert!(model.untriangulated_faces.is_empty());
+    assert_eq!(model.indices.len(), expected_triangles * 3);
+}
+
+#[test]
+fn ear_clips_concave_polygons() {
+    // an L shape whose fan from vertex 0 would spill outside the outline
+    let positions = [
+        [0.0, 0.0, 0.0],
+        [2.0, 0.0, 0.0],
+        [2.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 2.0, 0.0],
+        [0.0, 2.0, 0.0],
+    ];
+    let polygon = [3, 4, 5, 0, 1, 2];
+    let triangles = triangulate(&positions, &polygon).unwrap();
+    assert_eq!(triangles.len(), 4);
+    let area: f32 = triangles
+        .iter()
+        .map(|[a, b, c]| {
+            let (a, b, c) = (
+                positions[*a as usize],
+                positions[*b as usize],
+                positions[*c as usize],
+            );
+            ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+        })
+        .sum();
+    assert_eq!(area, 3.0);
+}
+
+#[test]
+fn reports_degenerate_polygons() {
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 2 0 0\nv 3 0 0\nf 1 2 3 4\n").unwrap();
+    assert_eq!(model.untriangulated_faces, vec![5]);
+}
+
+#[test]
+fn reports_degenerate_triangles() {
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nf 1 2 3\nf 1 2 4\n").unwrap();
+    assert_eq!(model.untriangulated_faces, vec![5]);
+    assert_eq!(model.indices, [0, 1, 3]);
+}
+
+#[test]
+fn keeps_small_faces() {
+    // a 0.2 mm triangle in a model measured in metres
+    let model = load_str("v 0 0 0\nv 0.0002 0 0\nv 0 0.0002 0\nf 1 2 3\n").unwrap();
+    assert!(model.untriangulated_faces.is_empty());
+    assert_eq!(model.indices.len(), 3);
+    // and a small quad far from the origin
+    let model = load_str(
+        "v 1000 1000 0\nv 1000.001 1000 0\nv 1000.001 1000.001 0\nv 1000 1000.001 0\nf 1 2 3 4\n",
+    )
+    .unwrap();
+    assert!(model.untriangulated_faces.is_empty());
+    assert_eq!(model.indices.len(), 6);
+}
+
+#[test]
+fn resolves_relative_indices() {
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf -4 -3 -2 -1\n").unwrap();
+    assert_eq!(model.indices, [0, 1, 2, 0, 2, 3]);
+}
+
+#[test]
+fn loads_cornell_box_with_relative_indices() {
+    let model = load_str(include_str!("../cornell_box.obj")).unwrap();
+    assert!(model.untriangulated_faces.is_empty());
+    assert!(model.indices.len() > 3);
+}
+
+#[test]
+fn rejects_relative_indices_before_the_first_vertex() {
+    let err = load_str("v 0 0 0\nvt 0 0\nf 1/-1 1/-2 1/1\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::IndexOutOfRange {
+            line: 3,
+            column: 8,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn deduplicates_unique_vertex_triples() {
+    let model = load_str(include_str!("../cube_tex.obj")).unwrap();
+    // 20 distinct v/vt/vn references
+    assert_eq!(model.vertex_count(), 20);
+    assert_eq!(model.texcoords.as_ref().unwrap().len(), 20 * 2);
+    assert_eq!(model.normals.as_ref().unwrap().len(), 20 * 3);
+    assert!(model.indices.iter().all(|&i| (i as usize) < 20));
+}
+
+#[test]
+fn interleaves_attribute_streams() {
+    let model = load_str("v 1 2 3\nvt 0.5 0.25\nvn 0 0 1\nf 1/1/1 1/1/1 1/1/1\n").unwrap();
+    assert_eq!(model.stride(), 8);
+    assert_eq!(
+        model.interleaved(),
+        &[1.0, 2.0, 3.0, 0.5, 0.25, 0.0, 0.0, 1.0]
+    );
+}
+
+#[test]
+fn omits_streams_that_are_never_referenced() {
+    let model = load_str(include_str!("../cube.obj")).unwrap();
+    assert!(model.texcoords.is_none());
+    assert!(model.normals.is_some());
+}
+
+#[test]
+fn splits_cornell_box_objects_into_submeshes() {
+    let model = load_str(include_str!("../cornell_box.obj")).unwrap();
+    let names: Vec<&str> = model.submeshes.iter().map(|s| s.name.as_str()).collect();
+    // front_wall has its only face commented out, so it has nothing to draw
+    assert_eq!(
+        names,
+        [
+            "floor",
+            "light",
+            "ceiling",
+            "back_wall",
+            "green_wall",
+            "red_wall",
+            "short_block",
+            "tall_block"
+        ]
+    );
+    // submeshes tile the whole index buffer
+    assert_eq!(model.submeshes[0].index_range.start, 0);
+    for pair in model.submeshes.windows(2) {
+        assert_eq!(pair[0].index_range.end, pair[1].index_range.start);
+    }
+    assert_eq!(
+        model.submeshes.last().unwrap().index_range.end,
+        model.indices.len()
+    );
+}
+
+#[test]
+fn records_groups_and_materials() {
+    let model = load_str(include_str!("../lamp.obj")).unwrap();
+    let parts: Vec<(&str, Option<&str>)> = model
+        .submeshes
+        .iter()
+        .map(|s| (s.name.as_str(), s.material.as_deref()))
+        .collect();
+    assert!(parts.contains(&("post", Some("bronze"))));
+    assert!(model.submeshes.iter().all(|s| !s.index_range.is_empty()));
+}
+
+const TEXTURED_TRIANGLE: &str = "mtllib model.mtl
+usemtl Material_MR
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+";
+
+#[test]
+fn attaches_materials_from_resolved_libraries() {
+    let mut files = MemoryResolver::new();
+    files
+        .insert("model.mtl", include_bytes!("../model.mtl").to_vec())
+        .unwrap();
+    files
+        .insert("Binary_0.jpeg", include_bytes!("../Binary_0.jpeg").to_vec())
+        .unwrap();
+    let model =
+        load_model_with_resolver(&mut BufReader::new(Cursor::new(TEXTURED_TRIANGLE)), &files)
+            .unwrap();
+
+    let material = model.material_for(&model.submeshes[0]).unwrap();
+    assert_eq!(material.name, "Material_MR");
+    assert_eq!(material.diffuse, [1.0, 1.0, 1.0]);
+    assert_eq!(material.diffuse_texture.as_deref(), Some("Binary_0.jpeg"));
+    assert!(model.missing_files.is_empty());
+}
+
+#[test]
+fn reports_missing_libraries_and_textures() {
+    let model = load_str(TEXTURED_TRIANGLE).unwrap();
+    assert_eq!(model.missing_files, ["model.mtl"]);
+
+    let mut files = MemoryResolver::new();
+    files
+        .insert(
+            "assets/lib.mtl",
+            b"newmtl red\nKd 1 0 0\nmap_Kd ../textures/red.png\n".to_vec(),
+        )
+        .unwrap();
+    let model = load_model_with_resolver(
+        &mut BufReader::new(Cursor::new("mtllib ./assets/lib.mtl\n")),
+        &files,
+    )
+    .unwrap();
+    assert_eq!(model.materials[0].name, "red");
+    assert_eq!(model.missing_files, ["textures/red.png"]);
+}
+
+#[test]
+fn rejects_path_traversal() {
+    let err = load_str("mtllib ../../etc/passwd\n").unwrap_err();
+    assert!(matches!(err, ObjError::UnsafePath { .. }));
+    assert!(normalize_path("/etc/passwd").is_err());
+    assert!(normalize_path("C:\\models\\a.mtl").is_err());
+    assert_eq!(normalize_path("a/./b/../c.mtl").unwrap(), "a/c.mtl");
+}
+
+#[test]
+fn rejects_indices_past_the_end_of_the_vertex_list() {
+    let err = load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::IndexOutOfRange {
+            line: 4,
+            column: 7,
+            element: "positions",
+            count: 3,
+            ..
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "line 4, column 7: `4` is out of range, only 3 positions declared so far"
+    );
+
+    let err = load_str("v 0 0 0\nvn 0 0 1\nf 1//1 1//2 1//1\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::IndexOutOfRange {
+            element: "normals",
+            ..
+        }
+    ));
+    assert!(load_str("v 0 0 0\nf 0 1 1\n").is_err());
+}
+
+#[test]
+fn produces_zero_based_indices_without_padding() {
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    assert_eq!(
+        model.vertices,
+        [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+    );
+    assert_eq!(model.indices, [0, 1, 2]);
+}
+
+#[test]
+fn streaming_parser_matches_whole_file_parse() {
+    let source = include_str!("../cube_tex.obj");
+    let whole = load_str(source).unwrap();
+
+    // chunk sizes that split lines and tokens at every offset
+    for chunk_size in [1, 7, 64] {
+        let mut parser = ObjParser::new(NoFiles);
+        for chunk in source.as_bytes().chunks(chunk_size) {
+            parser.push(chunk).unwrap();
+        }
+        let streamed = parser.finish().unwrap();
+        assert_eq!(streamed.vertices, whole.vertices);
+        assert_eq!(streamed.texcoords, whole.texcoords);
+        assert_eq!(streamed.indices, whole.indices);
+    }
+}
+
+#[test]
+fn reports_progress() {
+    let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nv 0 0";
+    let mut parser = ObjParser::new(NoFiles);
+    let progress = parser.push(source.as_bytes()).unwrap();
+    assert_eq!(
+        progress,
+        Progress {
+            bytes: source.len() as u64,
+            lines: 4,
+            vertices: 3,
+            faces: 1,
+        }
+    );
+    // the unterminated last line is only parsed once the input is finished
+    assert!(matches!(
+        parser.finish(),
+        Err(ObjError::MissingCoordinate { line: 5, .. })
+    ));
+}
+
+#[test]
+fn stops_when_cancelled() {
+    let mut parser = ObjParser::new(NoFiles);
+    parser.push(b"v 0 0 0\n").unwrap();
+    parser.cancel_token().cancel();
+    assert!(matches!(
+        parser.push(b"v 1 0 0\n"),
+        Err(ObjError::Cancelled)
+    ));
+    assert!(matches!(parser.finish(), Err(ObjError::Cancelled)));
+}
+
+#[test]
+fn keeps_polylines_and_points_as_separate_primitives() {
+    let model =
+        load_str("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nl 1/1 2 3 4\np 1 -1\nf 1 2 3\n")
+            .unwrap();
+    // `1/1` is a different vertex from the bare `1` used by the face
+    assert_eq!(model.line_indices, [0, 1, 1, 2, 2, 3]);
+    assert_eq!(model.point_indices, [4, 3]);
+    assert_eq!(model.indices, [4, 1, 2]);
+    assert_eq!(model.vertex_count(), 5);
+}
+
+#[test]
+fn rejects_lines_with_a_single_vertex() {
+    let err = load_str("v 0 0 0\nl 1\n").unwrap_err();
+    assert!(matches!(err, ObjError::MissingCoordinate { line: 2, .. }));
+}
+
+#[test]
+fn reads_vertex_colors() {
+    let model = load_str("v 0 0 0 1 0 0\nv 1 0 0 0 0.5 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    // vertices without a color are white
+    assert_eq!(
+        model.colors.unwrap(),
+        [1.0, 0.0, 0.0, 0.0, 0.5, 0.0, 1.0, 1.0, 1.0]
+    );
+    // a single component above 1 makes the whole file 0-255
+    let model = load_str("v 0 0 0 1 0 0\nv 1 0 0 0 255 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    assert_eq!(
+        model.colors.unwrap(),
+        [1.0 / 255.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0]
+    );
+    assert!(load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n")
+        .unwrap()
+        .colors
+        .is_none());
+}
+
+#[test]
+fn rejects_partial_vertex_colors() {
+    let err = load_str("v 0 0 0 1 0\n").unwrap_err();
+    assert!(matches!(err, ObjError::MissingCoordinate { line: 1, .. }));
+    let err = load_str("v 0 0 0\nv 0 0 0 1 0 0 0.5\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::UnexpectedToken {
+            line: 2,
+            column: 15,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn ignores_w_components_and_trailing_comments() {
+    let model =
+        load_str("v 0 0 0 1 # origin\nv 1 0 0 0.5\nv 0 1 0\nf 1 2 3 # the only face\n").unwrap();
+    assert_eq!(
+        model.vertices,
+        [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+    );
+    assert!(model.colors.is_none());
+}
+
+#[test]
+fn joins_continuation_lines() {
+    let err = load_str("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 \\\n  3 4\nv 0 0\n").unwrap_err();
+    // errors after a continuation still report the physical line they're on
+    assert!(matches!(err, ObjError::MissingCoordinate { line: 7, .. }));
+
+    let model = load_str("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 \\\r\n  3 4\n").unwrap();
+    assert_eq!(model.indices.len(), 6);
+}
+
+#[test]
+fn accepts_any_line_ending() {
+    let expected = load_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    for source in &[
+        "v 0 0 0\r\nv 1 0 0\r\nv 0 1 0\r\nf 1 2 3\r\n",
+        "v 0 0 0\rv 1 0 0\rv 0 1 0\rf 1 2 3\r",
+    ] {
+        let model = load_str(source).unwrap();
+        assert_eq!(model.vertices, expected.vertices);
+        assert_eq!(model.indices, expected.indices);
+    }
+
+    // a `\r\n` split between chunks is still a single line break
+    let mut parser = ObjParser::new(NoFiles);
+    parser.push(b"v 0 0 0\r").unwrap();
+    let progress = parser.push(b"\nv 1 0 0\r\n").unwrap();
+    assert_eq!(progress.lines, 2);
+}
+
+const ASCII_STL: &str = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+
+/// a binary STL with one facet per (normal, corners) entry
+fn binary_stl(header: &[u8], facets: &[([f32; 3], [[f32; 3]; 3])]) -> Vec<u8> {
+    let mut bytes = header.to_vec();
+    bytes.resize(80, 0);
+    bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+    for (normal, corners) in facets {
+        for value in normal.iter().chain(corners.iter().flatten()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0]);
+    }
+    bytes
+}
+
+#[test]
+fn welds_ascii_stl_facets() {
+    let model = load_stl(ASCII_STL.as_bytes()).unwrap();
+    assert_eq!(model.vertex_count(), 4);
+    assert_eq!(model.indices, [0, 1, 2, 0, 2, 3]);
+    assert_eq!(model.normals.unwrap()[..3], [0.0, 0.0, 1.0]);
+    assert_eq!(model.submeshes[0].name, "square");
+}
+
+#[test]
+fn keeps_facet_normals_of_binary_stl() {
+    let corners = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+    // a header starting with `solid` must not be mistaken for ASCII
+    let bytes = binary_stl(
+        b"solid but binary",
+        &[([0.0, 0.0, 1.0], corners), ([0.0, 0.0, -1.0], corners)],
+    );
+    assert!(is_binary_stl(&bytes));
+    let model = load_stl(&bytes).unwrap();
+    // same positions with different facet normals stay separate vertices
+    assert_eq!(model.vertex_count(), 6);
+    let normals = model.normals.unwrap();
+    assert_eq!(normals[..3], [0.0, 0.0, 1.0]);
+    assert_eq!(normals[9..12], [0.0, 0.0, -1.0]);
+}
+
+#[test]
+fn generates_missing_stl_normals() {
+    let bytes = binary_stl(
+        b"",
+        &[(
+            [0.0; 3],
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        )],
+    );
+    let model = load_stl(&bytes).unwrap();
+    assert_eq!(model.normals.unwrap(), [0.0, 0.0, 1.0].repeat(3));
+}
+
+#[test]
+fn reports_degenerate_binary_stl_facets() {
+    let good = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+    let flat = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]];
+    let bytes = binary_stl(b"", &[([0.0; 3], good), ([0.0; 3], flat)]);
+    let model = load_stl(&bytes).unwrap();
+    assert_eq!(model.untriangulated_faces, vec![2]);
+}
+
+#[test]
+fn reports_truncated_binary_stl() {
+    let mut bytes = binary_stl(b"", &[([0.0; 3], [[0.0; 3]; 3])]);
+    bytes.truncate(100);
+    assert!(matches!(
+        load_stl(&bytes),
+        Err(ObjError::Truncated {
+            expected: 134,
+            actual: 100
+        })
+    ));
+}
+
+#[test]
+fn reports_unexpected_stl_keywords() {
+    let err = load_stl(b"solid x\n  facet normal 0 0 1\n    outer lop\n").unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::UnexpectedToken {
+            line: 3,
+            column: 11,
+            expected: "loop",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn detects_model_formats() {
+    let binary = binary_stl(b"", &[([0.0; 3], [[0.0; 3]; 3])]);
+    let size = binary.len() as u64;
+    assert_eq!(
+        ModelFormat::detect("model.obj", &binary, size),
+        ModelFormat::Stl
+    );
+    assert_eq!(
+        ModelFormat::detect("part", ASCII_STL.as_bytes(), ASCII_STL.len() as u64),
+        ModelFormat::Stl
+    );
+    assert_eq!(ModelFormat::detect("part.STL", b"", 0), ModelFormat::Stl);
+    assert_eq!(
+        ModelFormat::detect("cube", b"v 0 0 0\n", 8),
+        ModelFormat::Obj
+    );
+}
+
+const ASCII_PLY: &str = "ply
+format ascii 1.0
+comment a quad and a triangle sharing an edge
+element vertex 5
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float confidence
+element face 2
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0 0.5
+1 0 0 0 255 0 1
+1 1 0 0 0 255 1
+0 1 0 255 255 255 0.25
+2 0 0 0 0 0 0
+4 0 1 2 3
+3 1 4 2
+";
+
+/// the vertices and faces of `ASCII_PLY` as a binary body
+fn binary_ply(big_endian: bool) -> Vec<u8> {
+    let format = if big_endian {
+        "binary_big_endian"
+    } else {
+        "binary_little_endian"
+    };
+    let mut bytes = ASCII_PLY
+        .replace("format ascii", &format!("format {}", format))
+        .split("end_header\n")
+        .next()
+        .unwrap()
+        .to_string()
+        .into_bytes();
+    bytes.extend_from_slice(b"end_header\n");
+    let f32_bytes = |v: f32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let i32_bytes = |v: i32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let vertices = [
+        ([0.0, 0.0, 0.0], [255, 0, 0], 0.5),
+        ([1.0, 0.0, 0.0], [0, 255, 0], 1.0),
+        ([1.0, 1.0, 0.0], [0, 0, 255], 1.0),
+        ([0.0, 1.0, 0.0], [255, 255, 255], 0.25),
+        ([2.0, 0.0, 0.0], [0, 0, 0], 0.0),
+    ];
+    for (position, color, confidence) in &vertices {
+        for &v in position {
+            bytes.extend_from_slice(&f32_bytes(v));
+        }
+        bytes.extend_from_slice(color);
+        bytes.extend_from_slice(&f32_bytes(*confidence));
+    }
+    for face in &[&[0, 1, 2, 3][..], &[1, 4, 2]] {
+        bytes.push(face.len() as u8);
+        for &i in face.iter() {
+            bytes.extend_from_slice(&i32_bytes(i));
+        }
+    }
+    bytes
+}
+
+#[test]
+fn loads_ply_in_every_encoding() {
+    let ascii = load_ply(ASCII_PLY.as_bytes()).unwrap();
+    for big_endian in [false, true].iter() {
+        let binary = load_ply(&binary_ply(*big_endian)).unwrap();
+        assert_eq!(binary.vertices, ascii.vertices);
+        assert_eq!(binary.indices, ascii.indices);
+        assert_eq!(binary.colors, ascii.colors);
+        assert_eq!(binary.attributes, ascii.attributes);
+    }
+
+    // the quad is split into two triangles
+    assert_eq!(ascii.indices.len(), 9);
+    let colors = ascii.colors.unwrap();
+    assert_eq!(colors[..6], [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    assert_eq!(ascii.attributes.len(), 1);
+    assert_eq!(ascii.attributes[0].name, "confidence");
+    assert_eq!(ascii.attributes[0].values, [0.5, 1.0, 1.0, 0.25, 0.0]);
+    assert!(ascii.normals.is_some());
+}
+
+#[test]
+fn loads_ply_point_clouds() {
+    let model = load_ply(
+        b"ply\nformat ascii 1.0\nelement vertex 2\nproperty double x\nproperty double y\n\
+          property double z\nproperty float nx\nproperty float ny\nproperty float nz\n\
+          end_header\n0 0 0 0 0 1\n1 2 3 0 1 0\n",
+    )
+    .unwrap();
+    assert_eq!(model.point_indices, [0, 1]);
+    assert!(model.indices.is_empty());
+    assert_eq!(model.normals.unwrap(), [0.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+}
+
+#[test]
+fn reports_bad_ply_files() {
+    let out_of_range = ASCII_PLY.replace("3 1 4 2", "3 1 5 2");
+    assert!(matches!(
+        load_ply(out_of_range.as_bytes()),
+        Err(ObjError::BadElement { index: 1, .. })
+    ));
+
+    let truncated = binary_ply(false);
+    assert!(matches!(
+        load_ply(&truncated[..truncated.len() - 2]),
+        Err(ObjError::BadElement { index: 1, .. })
+    ));
+
+    // elements other than vertices and faces can't claim more than the file holds either
+    let junk = b"ply\nformat ascii 1.0\nelement junk 18446744073709551615\nend_header\n";
+    assert!(matches!(
+        load_ply(junk),
+        Err(ObjError::BadElement { element, .. }) if element == "junk"
+    ));
+    let junk = ASCII_PLY.replace(
+        "end_header",
+        "element junk 1000000000000\nproperty uchar flag\nend_header",
+    );
+    assert!(matches!(
+        load_ply(junk.as_bytes()),
+        Err(ObjError::Truncated { .. })
+    ));
+
+    let bad_type = ASCII_PLY.replace("property float confidence", "property real confidence");
+    assert!(matches!(
+        load_ply(bad_type.as_bytes()),
+        Err(ObjError::UnexpectedToken {
+            line: 11,
+            column: 10,
+            ..
+        })
+    ));
+
+    assert_eq!(
+        ModelFormat::detect("scan.bin", ASCII_PLY.as_bytes(), 0),
+        ModelFormat::Ply
+    );
+}
+
+/// one triangle, its u16 indices and a tiny "image", as laid out in `gltf_json`
+fn gltf_buffer() -> Vec<u8> {
+    let mut buffer = Vec::new();
+    for v in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+        buffer.extend_from_slice(&v.to_le_bytes());
+    }
+    for i in &[0u16, 1, 2, 0] {
+        buffer.extend_from_slice(&i.to_le_bytes());
+    }
+    buffer.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+    buffer
+}
+
+/// a scene with the triangle under a translated and mirrored node
+fn gltf_json(buffer_uri: Option<&str>) -> String {
+    let uri = buffer_uri.map_or(String::new(), |uri| format!(r#","uri":"{}""#, uri));
+    format!(
+        r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [{{"children": [1], "translation": [10, 0, 0]}},
+            {{"name": "tri", "mesh": 0, "scale": [-1, 1, 1]}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+  "materials": [{{"name": "paint",
+                  "pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 0.5],
+                                            "baseColorTexture": {{"index": 0}},
+                                            "metallicFactor": 0.25}}}}],
+  "textures": [{{"source": 0}}],
+  "images": [{{"bufferView": 2, "mimeType": "image/png"}}],
+  "buffers": [{{"byteLength": 52{}}}],
+  "bufferViews": [{{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                  {{"buffer": 0, "byteOffset": 36, "byteLength": 6}},
+                  {{"buffer": 0, "byteOffset": 44, "byteLength": 8}}],
+  "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}]
+}}"#,
+        uri
+    )
+}
+
+fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let mut bytes = b"glTF".to_vec();
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"JSON");
+    bytes.extend_from_slice(&json);
+    bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"BIN\0");
+    bytes.extend_from_slice(bin);
+    bytes
+}
+
+#[test]
+fn loads_glb_with_transforms_and_materials() {
+    let bytes = glb(&gltf_json(None), &gltf_buffer());
+    assert_eq!(
+        ModelFormat::detect("model.bin", &bytes, bytes.len() as u64),
+        ModelFormat::Gltf
+    );
+    let model = load_gltf(&bytes, &NoFiles).unwrap();
+
+    // scaled by -1 in x, then moved along x by the parent node
+    assert_eq!(
+        model.vertices,
+        [10.0, 0.0, 0.0, 9.0, 0.0, 0.0, 10.0, 1.0, 0.0]
+    );
+    // the mirroring scale flips the winding back to counter-clockwise
+    assert_eq!(model.indices, [0, 2, 1]);
+    assert_eq!(model.submeshes[0].name, "tri");
+    assert_eq!(model.submeshes[0].material.as_deref(), Some("paint"));
+
+    let material = model.material_for(&model.submeshes[0]).unwrap();
+    assert_eq!(material.diffuse, [1.0, 0.0, 0.0]);
+    assert_eq!(material.dissolve, 0.5);
+    assert_eq!(material.pbr.as_ref().unwrap().metallic, 0.25);
+    let texture = material.diffuse_texture.as_deref().unwrap();
+    let embedded = &model.embedded_files[0];
+    assert_eq!(embedded.path, texture);
+    assert_eq!(embedded.contents, b"\x89PNG\r\n\x1a\n");
+}
+
+#[test]
+fn loads_gltf_buffers_from_data_uris_and_the_resolver() {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(gltf_buffer());
+    let data_uri = format!("data:application/octet-stream;base64,{}", encoded);
+    let model = load_gltf(gltf_json(Some(&data_uri)).as_bytes(), &NoFiles).unwrap();
+    assert_eq!(model.vertex_count(), 3);
+
+    let external = gltf_json(Some("buffers/tri%20angle.bin"));
+    assert!(matches!(
+        load_gltf(external.as_bytes(), &NoFiles),
+        Err(ObjError::MissingFile { path }) if path == "buffers/tri angle.bin"
+    ));
+    let mut files = MemoryResolver::new();
+    files
+        .insert("buffers/tri angle.bin", gltf_buffer())
+        .unwrap();
+    assert_eq!(
+        load_gltf(external.as_bytes(), &files)
+            .unwrap()
+            .vertex_count(),
+        3
+    );
+
+    let escaping = gltf_json(Some("../secret.bin"));
+    assert!(matches!(
+        load_gltf(escaping.as_bytes(), &files),
+        Err(ObjError::UnsafePath { .. })
+    ));
+}
+
+#[test]
+fn drops_the_unpaired_index_of_gltf_lines() {
+    // the triangle's three indices drawn as LINES
+    let json = gltf_json(None).replace(r#""material": 0}"#, r#""material": 0, "mode": 1}"#);
+    let model = load_gltf(&glb(&json, &gltf_buffer()), &NoFiles).unwrap();
+    assert_eq!(model.line_indices, [0, 1]);
+    assert!(model.indices.is_empty());
+
+    // LINE_LOOP closes them instead
+    let json = gltf_json(None).replace(r#""material": 0}"#, r#""material": 0, "mode": 2}"#);
+    let model = load_gltf(&glb(&json, &gltf_buffer()), &NoFiles).unwrap();
+    assert_eq!(model.line_indices, [0, 1, 1, 2, 2, 0]);
+}
+
+#[test]
+fn rejects_gltf_attributes_shorter_than_the_positions() {
+    let json = gltf_json(None)
+        .replace(r#"{"POSITION": 0}"#, r#"{"POSITION": 0, "NORMAL": 2}"#)
+        .replace(
+            r#""type": "SCALAR"}"#,
+            r#""type": "SCALAR"},
+                {"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}"#,
+        );
+    let bytes = glb(&json, &gltf_buffer());
+    assert!(matches!(
+        load_gltf(&bytes, &NoFiles),
+        Err(ObjError::Gltf { message }) if message == "NORMAL accessor has 2 elements but there are 3 positions"
+    ));
+}
+
+fn zip_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        writer
+            .start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn gzip(contents: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(contents).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn loads_the_primary_model_of_a_zip_upload() {
+    let bytes = zip_archive(&[
+        ("__MACOSX/scene/._model.obj", b"junk"),
+        ("scene/extras/part.stl", ASCII_STL.as_bytes()),
+        ("scene/model.obj", TEXTURED_TRIANGLE.as_bytes()),
+        ("scene/model.mtl", include_bytes!("../model.mtl")),
+        ("scene/Binary_0.jpeg", include_bytes!("../Binary_0.jpeg")),
+    ]);
+    assert_eq!(Archive::detect("upload", &bytes), Some(Archive::Zip));
+
+    let files = unzip(&bytes, &LoadLimits::default()).unwrap();
+    assert_eq!(
+        find_primary_model(&files).as_deref(),
+        Some("scene/model.obj")
+    );
+
+    // references resolve next to the model, not at the root of the archive
+    let model = load_archive(
+        Archive::Zip,
+        "upload.zip",
+        &bytes,
+        &NoFiles,
+        &LoadLimits::default(),
+    )
+    .unwrap();
+    let material = model.material_for(&model.submeshes[0]).unwrap();
+    assert_eq!(material.diffuse_texture.as_deref(), Some("Binary_0.jpeg"));
+    assert!(model.missing_files.is_empty());
+
+    let without_model = zip_archive(&[("readme.txt", b"nothing to see")]);
+    assert!(matches!(
+        load_archive(
+            Archive::Zip,
+            "upload.zip",
+            &without_model,
+            &NoFiles,
+            &LoadLimits::default()
+        ),
+        Err(ObjError::Archive { .. })
+    ));
+}
+
+#[test]
+fn rejects_zip_entries_outside_the_archive() {
+    let bytes = zip_archive(&[("../model.obj", TEXTURED_TRIANGLE.as_bytes())]);
+    assert!(matches!(
+        unzip(&bytes, &LoadLimits::default()),
+        Err(ObjError::UnsafePath { .. })
+    ));
+}
+
+#[test]
+fn loads_gzip_compressed_models() {
+    let obj = gzip(include_bytes!("../cube.obj"));
+    let model = load_archive(
+        Archive::Gzip,
+        "cube.obj.gz",
+        &obj,
+        &NoFiles,
+        &LoadLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        model.vertices,
+        load_str(include_str!("../cube.obj")).unwrap().vertices
+    );
+
+    // without a model extension the format comes from the magic bytes
+    let stl = gzip(ASCII_STL.as_bytes());
+    assert_eq!(Archive::detect("part", &stl), Some(Archive::Gzip));
+    let model = load_archive(
+        Archive::Gzip,
+        "part.gz",
+        &stl,
+        &NoFiles,
+        &LoadLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(model.vertex_count(), 4);
+}
+
+fn load_limited(source: &str, limits: LoadLimits) -> Result<ModelData, ObjError> {
+    load_model_with_limits(&mut Cursor::new(source), &NoFiles, &limits)
+}
+
+fn exceeded(result: Result<ModelData, ObjError>) -> (Limit, u64, u64) {
+    match result {
+        Err(ObjError::LimitExceeded { limit, value, max }) => (limit, value, max),
+        other => panic!("expected a limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn enforces_obj_element_limits() {
+    let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 4 3\nf 1 2 3\n";
+    assert!(load_limited(source, LoadLimits::default()).is_ok());
+
+    let limits = LoadLimits {
+        max_vertices: 3,
+        ..LoadLimits::default()
+    };
+    assert_eq!(
+        exceeded(load_limited(source, limits)),
+        (Limit::Vertices, 4, 3)
+    );
+
+    let limits = LoadLimits {
+        max_polygon_arity: 3,
+        ..LoadLimits::default()
+    };
+    assert_eq!(
+        exceeded(load_limited(source, limits)),
+        (Limit::PolygonArity, 4, 3)
+    );
+
+    let limits = LoadLimits {
+        max_faces: 1,
+        ..LoadLimits::default()
+    };
+    assert_eq!(exceeded(load_limited(source, limits)), (Limit::Faces, 2, 1));
+}
+
+#[test]
+fn stops_streaming_at_size_limits() {
+    let limits = LoadLimits {
+        max_file_bytes: 64,
+        max_line_bytes: 16,
+        ..LoadLimits::default()
+    };
+
+    // an endless line is rejected before its end ever arrives
+    let mut parser = ObjParser::new(NoFiles).with_limits(limits);
+    parser.push(b"v 0 0 0\n# a comment ").unwrap();
+    assert!(matches!(
+        parser.push(b"goes on and on"),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::LineLength,
+            ..
+        })
+    ));
+
+    let mut parser = ObjParser::new(NoFiles).with_limits(limits);
+    for _ in 0..8 {
+        parser.push(b"v 0 0 0\n").unwrap();
+    }
+    assert!(matches!(
+        parser.push(b"v 0 0 0\n"),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::FileSize,
+            value: 72,
+            max: 64
+        })
+    ));
+}
+
+#[test]
+fn enforces_the_file_size_limit_on_material_libraries() {
+    let mut files = MemoryResolver::new();
+    files.insert("big.mtl", b"newmtl big\n".repeat(10)).unwrap();
+    let limits = LoadLimits {
+        max_file_bytes: 64,
+        ..LoadLimits::default()
+    };
+    let mut parser = ObjParser::new(files).with_limits(limits);
+    assert!(matches!(
+        parser.push(b"mtllib big.mtl\n"),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::FileSize,
+            value: 110,
+            max: 64
+        })
+    ));
+}
+
+#[test]
+fn checks_declared_counts_before_reading_them() {
+    let limits = LoadLimits {
+        max_faces: 1,
+        ..LoadLimits::default()
+    };
+    let corners = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+    let stl = binary_stl(b"", &[([0.0, 0.0, 1.0], corners); 2]);
+    assert_eq!(
+        exceeded(load_stl_with_limits(&stl, &limits)),
+        (Limit::Faces, 2, 1)
+    );
+
+    // a header claiming a billion vertices fails without allocating for them
+    let huge = ASCII_PLY.replace("element vertex 5", "element vertex 1000000000");
+    assert_eq!(
+        exceeded(load_ply(huge.as_bytes())),
+        (
+            Limit::Vertices,
+            1_000_000_000,
+            LoadLimits::default().max_vertices as u64
+        )
+    );
+
+    let limits = LoadLimits {
+        max_polygon_arity: 3,
+        ..LoadLimits::default()
+    };
+    assert_eq!(
+        exceeded(load_ply_with_limits(ASCII_PLY.as_bytes(), &limits)).0,
+        Limit::PolygonArity
+    );
+}
+
+#[test]
+fn stops_decompressing_at_the_file_size_limit() {
+    let limits = LoadLimits {
+        max_file_bytes: 4096,
+        ..LoadLimits::default()
+    };
+    let bomb = gzip(&vec![b' '; 1 << 20]);
+    assert!(bomb.len() < 4096);
+    assert!(matches!(
+        gunzip(&bomb, &limits),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::FileSize,
+            ..
+        })
+    ));
+
+    // every file fits on its own, but not all of them together
+    let half = vec![b' '; 3000];
+    let bytes = zip_archive(&[("a.obj", &half), ("b.mtl", &half)]);
+    assert!(matches!(
+        unzip(&bytes, &limits),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::FileSize,
+            ..
+        })
+    ));
+}
+
+fn png_header(width: u32, height: u32) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&width.to_be_bytes());
+    png.extend_from_slice(&height.to_be_bytes());
+    png
+}
+
+#[test]
+fn reads_texture_dimensions_from_headers() {
+    assert_eq!(image_dimensions(&png_header(640, 480)), Some((640, 480)));
+    assert_eq!(
+        image_dimensions(include_bytes!("../Binary_0.jpeg")),
+        Some((2048, 2048))
+    );
+    assert_eq!(image_dimensions(b"not an image"), None);
+}
+
+#[test]
+fn enforces_texture_dimensions() {
+    let limits = LoadLimits {
+        max_texture_dimension: 1024,
+        ..LoadLimits::default()
+    };
+    let mut files = MemoryResolver::new();
+    files
+        .insert("model.mtl", include_bytes!("../model.mtl").to_vec())
+        .unwrap();
+    files
+        .insert("Binary_0.jpeg", include_bytes!("../Binary_0.jpeg").to_vec())
+        .unwrap();
+    assert_eq!(
+        exceeded(load_model_with_limits(
+            &mut Cursor::new(TEXTURED_TRIANGLE),
+            &files,
+            &limits
+        )),
+        (Limit::TextureDimension, 2048, 1024)
+    );
+
+    // the image embedded in the GLB is a PNG of 4096 by 16
+    let mut bin = gltf_buffer();
+    bin.truncate(44);
+    bin.extend_from_slice(&png_header(4096, 16));
+    let json = gltf_json(None).replace(
+        r#""byteLength": 8}]"#,
+        &format!(r#""byteLength": {}}}]"#, bin.len() - 44),
+    );
+    let json = json.replace(
+        r#""byteLength": 52}"#,
+        &format!(r#""byteLength": {}}}"#, bin.len()),
+    );
+    let glb = glb(&json, &bin);
+    assert!(load_gltf(&glb, &NoFiles).is_ok());
+    assert_eq!(
+        exceeded(load_gltf_with_limits(&glb, &NoFiles, &limits)),
+        (Limit::TextureDimension, 4096, 1024)
+    );
+}
+
+#[test]
+fn loads_whole_buffers_like_streams() {
+    let bytes = include_bytes!("../lamp.obj");
+    let streamed = load_model(&mut BufReader::new(&bytes[..])).unwrap();
+    let whole = ModelFormat::Obj
+        .load(bytes, &NoFiles, &LoadLimits::default())
+        .unwrap();
+    assert_eq!(whole.vertices, streamed.vertices);
+    assert_eq!(whole.indices, streamed.indices);
+    assert_eq!(whole.normals, streamed.normals);
+    assert_eq!(whole.submeshes.len(), streamed.submeshes.len());
+}
+
+#[test]
+fn reads_text_statements_as_utf8_and_skips_the_rest() {
+    let model = load_model(&mut &b"# caf\xe9 latin-1 comment\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\xff 1 2\ng \xc3\xa9t\xc3\xa9\nf 1 2 3\n"[..]).unwrap();
+    assert_eq!(model.submeshes[0].name, "\u{e9}t\u{e9}");
+
+    let err = load_model(&mut &b"g caf\xe9\n"[..]).unwrap_err();
+    assert!(matches!(
+        err,
+        ObjError::InvalidUtf8 {
+            line: 1,
+            column: 6,
+            ..
+        }
+    ));
+}
+
+const BILINEAR_PATCH: &str = "\
+v 0 0 0
+v 2 0 0
+v 0 2 0
+v 2 2 0
+g patch
+cstype bspline
+deg 1 1
+surf 0 1 0 1 1 2 3 4
+parm u 0 0 1 1
+parm v 0 0 1 1
+end
+";
+
+#[test]
+fn tessellates_free_form_surfaces() {
+    let model = load_str(BILINEAR_PATCH).unwrap();
+    // 8 segments each way across the single span
+    assert_eq!(model.vertex_count(), 81);
+    assert_eq!(model.indices.len(), 128 * 3);
+    assert_eq!(model.smoothing_groups.len(), 128);
+    assert_eq!(model.submeshes.len(), 1);
+    assert_eq!(model.submeshes[0].name, "patch");
+    assert_eq!(model.submeshes[0].index_range, 0..128 * 3);
+
+    let vertices = &model.vertices;
+    assert!(vertices.chunks(3).all(|v| v[2] == 0.0));
+    assert_eq!(&vertices[3..6], &[0.25, 0.0, 0.0]);
+    assert_eq!(&vertices[80 * 3..], &[2.0, 2.0, 0.0]);
+    let normals = model.normals.as_ref().unwrap();
+    assert!(normals.chunks(3).all(|n| n == [0.0, 0.0, 1.0]));
+    let texcoords = model.texcoords.as_ref().unwrap();
+    assert_eq!(&texcoords[80 * 2..], &[1.0, 1.0]);
+}
+
+#[test]
+fn tessellates_free_form_curves() {
+    // a cubic Bezier without `parm`, its single segment spans 0 to 1
+    let model = load_str(
+        "v 0 0 0\nv 1 2 0\nv 3 2 0\nv 4 0 0\ncstype bezier\ndeg 3\ncurv 0 1 1 2 3 4\nend\n",
+    )
+    .unwrap();
+    assert_eq!(model.vertex_count(), 9);
+    assert!(model.indices.is_empty());
+    assert_eq!(model.line_indices.len(), 8 * 2);
+    assert_eq!(&model.line_indices[..4], &[0, 1, 1, 2]);
+    assert_eq!(&model.vertices[..3], &[0.0, 0.0, 0.0]);
+    // (p0 + 3 p1 + 3 p2 + p3) / 8 halfway along
+    assert_eq!(&model.vertices[4 * 3..5 * 3], &[2.0, 1.5, 0.0]);
+    assert_eq!(&model.vertices[8 * 3..], &[4.0, 0.0, 0.0]);
+
+    let quarter_circle = "\
+v 1 0 0
+v 1 1 0 0.70710678
+v 0 1 0
+cstype rat bezier
+deg 2
+curv 0 1 1 2 3
+parm u 0 1
+end
+";
+    let model = load_str(quarter_circle).unwrap();
+    assert_eq!(model.vertex_count(), 9);
+    for point in model.vertices.chunks(3) {
+        let radius = (point[0] * point[0] + point[1] * point[1]).sqrt();
+        assert!((radius - 1.0).abs() < 1e-5, "{:?} is off the circle", point);
+    }
+}
+
+#[test]
+fn tessellates_with_the_configured_density() {
+    // a clamped cubic B-spline with two knot spans
+    let source = "\
+v 0 0 0
+v 1 1 0
+v 2 0 0
+v 3 1 0
+v 4 0 0
+cstype bspline
+deg 3
+curv 0 2 1 2 3 4 5
+parm u 0 0 0 0 1 2 2 2 2
+end
+";
+    let mut parser = ObjParser::new(NoFiles).with_tessellation(Tessellation {
+        segments_per_span: 2,
+    });
+    parser.push(source.as_bytes()).unwrap();
+    let model = parser.finish().unwrap();
+    assert_eq!(model.vertex_count(), 5);
+    assert_eq!(&model.vertices[..3], &[0.0, 0.0, 0.0]);
+    assert_eq!(&model.vertices[12..], &[4.0, 0.0, 0.0]);
+
+    let mut parser = ObjParser::new(NoFiles).with_tessellation(Tessellation {
+        segments_per_span: 16,
+    });
+    parser.push(BILINEAR_PATCH.as_bytes()).unwrap();
+    assert_eq!(parser.finish().unwrap().vertex_count(), 17 * 17);
+}
+
+#[test]
+fn reports_invalid_free_form_elements() {
+    let mismatched = BILINEAR_PATCH.replace("surf 0 1 0 1 1 2 3 4", "surf 0 1 0 1 1 2 3");
+    assert!(matches!(
+        load_str(&mismatched).unwrap_err(),
+        ObjError::Freeform { line: 8, .. }
+    ));
+    let unterminated = BILINEAR_PATCH.replace("end\n", "");
+    assert!(matches!(
+        load_str(&unterminated).unwrap_err(),
+        ObjError::Freeform { line: 8, .. }
+    ));
+
+    // bases that can't be evaluated are skipped and reported
+    let cardinal = BILINEAR_PATCH.replace("cstype bspline", "cstype cardinal");
+    let model = load_str(&cardinal).unwrap();
+    assert!(model.indices.is_empty());
+    assert_eq!(model.untriangulated_faces, vec![8]);
+}
+
+#[test]
+fn parses_uploads_synchronously_in_slices() {
+    // over 1 MiB, so it is pushed through the parser in several slices
+    let bytes = include_bytes!("../model.obj");
+    let uploaded = parse_upload("model.obj", bytes, MemoryResolver::new()).unwrap();
+    let loaded = load_model(&mut BufReader::new(&bytes[..])).unwrap();
+    assert_eq!(uploaded.vertices, loaded.vertices);
+    assert_eq!(uploaded.indices, loaded.indices);
+    assert_eq!(uploaded.submeshes.len(), loaded.submeshes.len());
+
+    let cube = include_bytes!("../cube.obj");
+    let archive = zip_archive(&[("models/cube.obj", cube)]);
+    let unzipped = parse_upload("upload.zip", &archive, MemoryResolver::new()).unwrap();
+    assert_eq!(
+        unzipped.vertices,
+        load_str(include_str!("../cube.obj")).unwrap().vertices
+    );
+}
+
+#[test]
+fn reports_upload_progress_while_streaming() {
+    let bytes = include_bytes!("../lamp.obj");
+    let mut parser = UploadParser::new("lamp.obj", bytes.len() as u64, MemoryResolver::new());
+    let (first, second) = bytes.split_at(bytes.len() / 2);
+    let progress = parser.push(first).unwrap().unwrap();
+    assert_eq!(progress.bytes, first.len() as u64);
+    assert!(progress.vertices > 0);
+    let progress = parser.push(second).unwrap().unwrap();
+    assert_eq!(progress.bytes, bytes.len() as u64);
+    assert!(parser.finish().unwrap().vertex_count() > 0);
+
+    // other formats are only parsed once the whole file is there
+    let stl = binary_stl(
+        b"",
+        &[(
+            [0.0, 0.0, 1.0],
+            [[0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        )],
+    );
+    let mut parser = UploadParser::new("facet.stl", stl.len() as u64, MemoryResolver::new());
+    assert_eq!(parser.push(&stl).unwrap(), None);
+    assert_eq!(parser.finish().unwrap().indices.len(), 3);
+
+    let limits = LoadLimits {
+        max_file_bytes: 64,
+        ..LoadLimits::default()
+    };
+    let mut parser =
+        UploadParser::new("facet.stl", stl.len() as u64, MemoryResolver::new()).with_limits(limits);
+    assert!(matches!(
+        parser.push(&stl),
+        Err(ObjError::LimitExceeded {
+            limit: Limit::FileSize,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn round_trips_models_through_the_cache_format() {
+    let mut files = MemoryResolver::new();
+    files
+        .insert("model.mtl", include_bytes!("../model.mtl").to_vec())
+        .unwrap();
+    let mut with_materials =
+        load_model_with_resolver(&mut BufReader::new(Cursor::new(TEXTURED_TRIANGLE)), &files)
+            .unwrap();
+    generate_tangents(&mut with_materials);
+
+    let bytes = glb(&gltf_json(None), &gltf_buffer());
+    let mut gltf = load_gltf(&bytes, &NoFiles).unwrap();
+    gltf.attributes.push(VertexAttribute {
+        name: String::from("confidence"),
+        values: vec![0.5, 1.0, f32::NAN],
+    });
+    gltf.untriangulated_faces.push(7);
+
+    let models = [
+        load_str(include_str!("../lamp.obj")).unwrap(),
+        load_str(BILINEAR_PATCH).unwrap(),
+        with_materials,
+        gltf,
+    ];
+    for model in models {
+        let encoded = encode_model(&model, 42);
+        let decoded = decode_model(&encoded).unwrap();
+        // compared as debug output so the NaN attribute counts as equal
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", model));
+        // the same model always encodes to the same bytes
+        assert_eq!(encode_model(&decoded, 42), encoded);
+    }
+}
+
+#[test]
+fn round_trips_levels_of_detail_through_the_cache_format() {
+    let model = load_str(include_str!("../model.obj")).unwrap();
+    let mut levels = generate_lods(model, &LodOptions::default());
+    for level in &mut levels {
+        optimize_mesh(&mut level.model);
+    }
+    assert!(levels.len() > 1);
+
+    let encoded = encode_lods(&levels, 42);
+    let decoded = decode_lods(&encoded).unwrap();
+    assert_eq!(decoded.len(), levels.len());
+    for (decoded, level) in decoded.iter().zip(&levels) {
+        assert_eq!(decoded.model, level.model);
+        assert_eq!(decoded.error, level.error);
+    }
+
+    assert!(matches!(
+        decode_lods(&encoded[..encoded.len() - 1]),
+        Err(ObjError::Truncated { .. })
+    ));
+    let mut trailing = encoded;
+    trailing.push(0);
+    assert!(matches!(
+        decode_lods(&trailing),
+        Err(ObjError::Cache { .. })
+    ));
+    assert!(matches!(
+        decode_lods(&encode_lods(&[], 42)),
+        Err(ObjError::Cache { .. })
+    ));
+    // a single model isn't a chain
+    assert!(decode_lods(&encode_model(&levels[0].model, 42)).is_err());
+}
+
+#[test]
+fn rejects_corrupt_and_outdated_cached_models() {
+    let model = load_str(include_str!("../cube.obj")).unwrap();
+    let encoded = encode_model(&model, 0);
+
+    let mut corrupt = encoded.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        decode_model(&corrupt),
+        Err(ObjError::Cache { .. })
+    ));
+
+    let mut outdated = encoded.clone();
+    outdated[4..8].copy_from_slice(&0u32.to_le_bytes());
+    assert!(matches!(
+        decode_model(&outdated),
+        Err(ObjError::Cache { .. })
+    ));
+
+    assert!(matches!(
+        decode_model(&encoded[..encoded.len() - 4]),
+        Err(ObjError::Truncated { .. })
+    ));
+    assert!(matches!(
+        decode_model(b"v 0 0 0\n"),
+        Err(ObjError::Truncated { .. })
+    ));
+
+    // a well formed file whose streams don't fit together
+    let mut out_of_range = model.clone();
+    out_of_range.indices[4] = model.vertex_count() as u32;
+    let mut past_the_indices = model.clone();
+    past_the_indices.submeshes[0].index_range.end = model.indices.len() + 3;
+    let mut short_normals = model.clone();
+    short_normals.normals.as_mut().unwrap().pop();
+    let mut stray_point = model;
+    stray_point.point_indices.push(u32::MAX);
+    for broken in [out_of_range, past_the_indices, short_normals, stray_point] {
+        assert!(matches!(
+            decode_model(&encode_model(&broken, 0)),
+            Err(ObjError::Cache { .. })
+        ));
+    }
+
+    // stream table entries that pass the checksum but don't match their stream:
+    // the first entry is the positions and the second the normals
+    let resealed = |edit: &dyn Fn(&mut Vec<u8>)| {
+        let mut bytes = encoded.clone();
+        edit(&mut bytes);
+        let checksum = crc32fast::hash(&bytes[32..]);
+        bytes[24..28].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    };
+    let as_u32 = resealed(&|bytes| bytes[33] = 1);
+    let two_components = resealed(&|bytes| bytes[34..36].copy_from_slice(&2u16.to_le_bytes()));
+    let positions_twice = resealed(&|bytes| bytes[48] = 0);
+    assert_eq!(encoded[48], 2);
+    for broken in [as_u32, two_components, positions_twice] {
+        assert!(matches!(decode_model(&broken), Err(ObjError::Cache { .. })));
+    }
+}
+
+#[test]
+fn keys_caches_by_the_whole_upload() {
+    let obj: &[u8] = b"mtllib a.mtl\n";
+    let mtl: &[u8] = b"newmtl a\n";
+    let png: &[u8] = b"\x89PNG";
+    let key = cache_key(("model.obj", obj), [("a.mtl", mtl), ("a.png", png)]);
+    // the same upload in another order has the same key
+    assert_eq!(
+        key,
+        cache_key(("model.obj", obj), [("a.png", png), ("a.mtl", mtl)])
+    );
+    assert_ne!(key, cache_key(("model.obj", obj), []));
+    assert_ne!(
+        key,
+        cache_key(
+            ("model.obj", obj),
+            [("a.mtl", &b"newmtl b\n"[..]), ("a.png", png)]
+        )
+    );
+    // stable from one run (and platform) to the next
+    assert_eq!(cache_key(("model.obj", obj), []), 0x9562_e902_55d4_dbc1);
+
+    // another file named like the model doesn't stand in for it, and files
+    // sharing a name all count
+    let shadowed = cache_key(("model.obj", obj), [("model.obj", mtl)]);
+    assert_ne!(
+        shadowed,
+        cache_key(("model.obj", mtl), [("model.obj", obj)])
+    );
+    assert_ne!(shadowed, cache_key(("model.obj", obj), []));
+    assert_ne!(
+        cache_key(("model.obj", obj), [("a.mtl", mtl), ("a.mtl", png)]),
+        cache_key(("model.obj", obj), [("a.mtl", png)])
+    );
+
+    // files read a slice at a time get the same key
+    let mut builder = CacheKeyBuilder::new();
+    builder.start_model("model.obj", obj.len() as u64);
+    for slice in obj.chunks(5) {
+        builder.update(slice);
+    }
+    builder.start_file("a.mtl", mtl.len() as u64);
+    builder.update(mtl);
+    builder.start_file("a.png", png.len() as u64);
+    builder.update(png);
+    assert_eq!(builder.finish(), key);
+}